@@ -0,0 +1,57 @@
+//! Silero VAD over 60s of audio: one pass for the speech ratio and the
+//! segments, against one pass for each.
+//! Needs the downloaded model: cargo bench --bench vad
+
+use flemme_app_lib::audio::{Vad, VoiceActivityDetector};
+use flemme_app_lib::transcription::downloader::models_dir;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 5;
+
+fn main() {
+    let model_path = models_dir()
+        .expect("Failed to get the models directory")
+        .join("silero_vad.onnx");
+    if !model_path.exists() {
+        panic!("Silero VAD model not found at {:?}, download it from the app first", model_path);
+    }
+    let mut vad = VoiceActivityDetector::new_default(&model_path).expect("Failed to load the Silero VAD model");
+
+    // 60s of tone bursts over noise
+    let mut seed = 1u32;
+    let audio: Vec<f32> = (0..16000 * 60)
+        .map(|i| {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            let noise = (seed as f32 / u32::MAX as f32 * 2.0 - 1.0) * 0.01;
+            let voiced = (i / 16000) % 3 == 0;
+            let tone = if voiced { (i as f32 * 0.08).sin() * 0.3 } else { 0.0 };
+            noise + tone
+        })
+        .collect();
+    let chunk_size = vad.chunk_size();
+
+    let mut two_passes = Duration::ZERO;
+    let mut single_pass = Duration::ZERO;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let ratio = vad.speech_ratio(&audio, chunk_size);
+        let segments = vad.speech_segments(&audio, chunk_size);
+        two_passes += start.elapsed();
+
+        let start = Instant::now();
+        let probabilities = vad.probabilities(&audio, chunk_size);
+        let single_ratio = probabilities.speech_ratio(vad.threshold());
+        let single_segments = probabilities.segments(vad.settings());
+        single_pass += start.elapsed();
+
+        assert_eq!(single_ratio, ratio);
+        assert_eq!(single_segments, segments);
+    }
+
+    println!(
+        "[BENCH] VAD over 60s: two passes {:.0}ms, single pass {:.0}ms ({:.1}x)",
+        two_passes.as_secs_f64() * 1000.0 / RUNS as f64,
+        single_pass.as_secs_f64() * 1000.0 / RUNS as f64,
+        two_passes.as_secs_f64() / single_pass.as_secs_f64()
+    );
+}
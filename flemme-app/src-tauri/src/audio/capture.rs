@@ -0,0 +1,532 @@
+// CapturePipeline - moves audio from the cpal callback to a resampling worker thread
+use super::level::{AudioLevel, LevelListener, LevelMeter, LevelSummary};
+use super::resampler::StreamingResampler;
+use super::spool::{SpoolSettings, SpoolWriter};
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Seconds of device audio the ring buffer can hold before the worker must catch up
+const RING_BUFFER_SECONDS: usize = 2;
+
+/// How long the worker sleeps when the ring buffer is empty
+const WORKER_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Level blocks the ring buffer can hold (about 3s of 50ms blocks)
+const LEVEL_RING_CAPACITY: usize = 64;
+
+/// Minimum interval between two live level notifications
+const LEVEL_EVENT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Receives the 16kHz audio of the recording in progress as it is resampled
+pub type SampleListener = Arc<dyn Fn(&[f32]) + Send + Sync>;
+
+/// Optional behaviour of a capture pipeline
+#[derive(Default)]
+pub struct CaptureOptions {
+    /// Device frames kept between recordings and prepended to the next one
+    pub pre_roll_samples: usize,
+    pub level_listener: Option<LevelListener>,
+    pub sample_listener: Option<SampleListener>,
+    pub spool: Option<SpoolSettings>,
+}
+
+/// 16kHz audio of a finished recording
+#[derive(Debug)]
+pub enum CapturedAudio {
+    Memory(Vec<f32>),
+    /// Long recording left in its spool file, to be read in blocks
+    Spooled { path: PathBuf, samples: usize },
+}
+
+enum CaptureControl {
+    Begin { reply: Sender<()> },
+    End { reply: Sender<Result<(CapturedAudio, LevelSummary), String>> },
+    Cancel { reply: Sender<Result<(), String>> },
+}
+
+/// Producer side of the pipeline, moved into the audio callback
+pub struct CaptureInput {
+    samples: Producer<f32>,
+    levels: Producer<AudioLevel>,
+    meter: LevelMeter,
+}
+
+impl CaptureInput {
+    /// Queue one mono frame and meter its level. Frames are dropped if the
+    /// worker falls more than the ring size behind.
+    pub fn push(&mut self, sample: f32) {
+        let _ = self.samples.push(sample);
+        if let Some(level) = self.meter.push(sample) {
+            let _ = self.levels.push(level);
+        }
+    }
+}
+
+/// Running capture: the cpal callback pushes mono frames into a lock-free ring
+/// buffer, and a worker thread owning the resampler turns them into 16kHz audio
+/// while the user is still speaking.
+///
+/// Between recordings the worker keeps the last `pre_roll_samples` frames, which
+/// are prepended to the next recording so speech starting right at the hotkey
+/// press is not clipped (only useful when the stream stays open).
+///
+/// The callback also meters the input every 50ms; while recording, the worker
+/// forwards these levels to the listener (throttled) and sums them up.
+///
+/// The sample listener sees the recording while it grows (e.g. to detect the
+/// end of speech).
+///
+/// With spooling enabled, a recording reaching `SpoolSettings::after_samples`
+/// is moved to a WAV file and continued there, so long recordings don't grow
+/// in memory and survive a crash. The file is handed over when the recording ends.
+pub struct CapturePipeline {
+    control: Sender<CaptureControl>,
+    worker: JoinHandle<StreamingResampler>,
+}
+
+impl CapturePipeline {
+    /// Start the worker thread. Returns the pipeline handle and the producer side
+    /// of the ring buffers, which must be moved into the audio callback.
+    pub fn start(resampler: StreamingResampler, options: CaptureOptions) -> (Self, CaptureInput) {
+        let pre_roll_samples = options.pre_roll_samples;
+        let input_rate = resampler.input_rate();
+        let capacity = input_rate as usize * RING_BUFFER_SECONDS;
+        let (producer, consumer) = RingBuffer::<f32>::new(capacity);
+        let (level_producer, level_consumer) = RingBuffer::<AudioLevel>::new(LEVEL_RING_CAPACITY);
+        let (control, control_rx) = mpsc::channel();
+
+        let worker = std::thread::spawn(move || {
+            let mut worker = CaptureWorker {
+                resampler,
+                consumer,
+                pre_roll: VecDeque::with_capacity(pre_roll_samples),
+                pre_roll_samples,
+                recording: None,
+                levels: level_consumer,
+                level_listener: options.level_listener,
+                level_summary: LevelSummary::default(),
+                pending_level: None,
+                last_level_event: Instant::now(),
+                sample_listener: options.sample_listener,
+                spool: options.spool,
+                spool_writer: None,
+            };
+            worker.run(control_rx);
+            worker.resampler
+        });
+
+        let input = CaptureInput {
+            samples: producer,
+            levels: level_producer,
+            meter: LevelMeter::new(input_rate),
+        };
+        (Self { control, worker }, input)
+    }
+
+    /// Start accumulating a recording (prefixed with the pre-roll)
+    ///
+    /// Returns once the worker has switched to recording, so frames pushed
+    /// after this call are guaranteed to be part of the recording.
+    pub fn begin(&self) -> Result<(), String> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.control
+            .send(CaptureControl::Begin { reply: reply_tx })
+            .map_err(|_| "Capture worker stopped".to_string())?;
+        reply_rx
+            .recv()
+            .map_err(|_| "Capture worker stopped".to_string())
+    }
+
+    /// End the recording: resample every frame queued so far, flush the
+    /// resampler tail and return the 16kHz recording with its level summary
+    pub fn end(&self) -> Result<(CapturedAudio, LevelSummary), String> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.control
+            .send(CaptureControl::End { reply: reply_tx })
+            .map_err(|_| "Capture worker stopped".to_string())?;
+        reply_rx
+            .recv()
+            .map_err(|_| "Capture worker stopped".to_string())?
+    }
+
+    /// End the recording without keeping it: the sample listener still gets
+    /// every frame, but nothing is returned and the spool file is deleted
+    pub fn cancel(&self) -> Result<(), String> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.control
+            .send(CaptureControl::Cancel { reply: reply_tx })
+            .map_err(|_| "Capture worker stopped".to_string())?;
+        reply_rx
+            .recv()
+            .map_err(|_| "Capture worker stopped".to_string())?
+    }
+
+    /// Stop the worker and get the resampler back for the next stream
+    ///
+    /// The audio stream should be dropped before calling this.
+    pub fn shutdown(self) -> Result<StreamingResampler, String> {
+        drop(self.control);
+        self.worker
+            .join()
+            .map_err(|_| "Capture worker panicked".to_string())
+    }
+}
+
+struct CaptureWorker {
+    resampler: StreamingResampler,
+    consumer: Consumer<f32>,
+    pre_roll: VecDeque<f32>,
+    pre_roll_samples: usize,
+    // 16kHz output of the recording in progress
+    recording: Option<Vec<f32>>,
+    levels: Consumer<AudioLevel>,
+    level_listener: Option<LevelListener>,
+    level_summary: LevelSummary,
+    // Loudest level since the last notification
+    pending_level: Option<AudioLevel>,
+    last_level_event: Instant,
+    sample_listener: Option<SampleListener>,
+    spool: Option<SpoolSettings>,
+    // Spool file of the recording in progress, once it got long enough
+    spool_writer: Option<SpoolWriter>,
+}
+
+impl CaptureWorker {
+    fn run(&mut self, control: Receiver<CaptureControl>) {
+        loop {
+            match control.try_recv() {
+                Ok(command) => {
+                    // Drain first so the frames captured up to now end up on the
+                    // right side of the begin/end boundary
+                    self.drain();
+                    self.handle(command);
+                }
+                Err(TryRecvError::Empty) => {
+                    if !self.drain() {
+                        std::thread::sleep(WORKER_POLL_INTERVAL);
+                    }
+                }
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+    }
+
+    fn handle(&mut self, command: CaptureControl) {
+        match command {
+            CaptureControl::Begin { reply } => {
+                self.resampler.reset();
+                let mut output = Vec::new();
+
+                // Prepend the audio captured just before the hotkey press
+                if !self.pre_roll.is_empty() {
+                    let (first, second) = self.pre_roll.as_slices();
+                    let result = self
+                        .resampler
+                        .process(first, &mut output)
+                        .and_then(|_| self.resampler.process(second, &mut output));
+                    if let Err(e) = result {
+                        eprintln!("Failed to resample pre-roll: {}", e);
+                    }
+                    println!("Prepended {} ms of pre-roll", self.pre_roll.len() * 1000 / self.resampler.input_rate() as usize);
+                    self.pre_roll.clear();
+                }
+                if let Some(listener) = self.sample_listener.as_ref() {
+                    listener(&output);
+                }
+
+                if let Some(writer) = self.spool_writer.take() {
+                    writer.discard();
+                }
+                self.recording = Some(output);
+                self.level_summary = LevelSummary::default();
+                self.pending_level = None;
+                let _ = reply.send(());
+            }
+            CaptureControl::End { reply } => {
+                let result = match self.recording.take() {
+                    Some(mut output) => self.resampler.flush(&mut output).and_then(|_| {
+                        // Complete the spool file with the tail still in memory
+                        match self.spool_writer.take() {
+                            Some(mut writer) => writer
+                                .append(&output)
+                                .and_then(|_| writer.finish())
+                                .map(|(path, samples)| CapturedAudio::Spooled { path, samples }),
+                            None => Ok(CapturedAudio::Memory(output)),
+                        }
+                    }),
+                    None => Err("Not recording".to_string()),
+                }
+                .map(|output| (output, self.level_summary));
+                let _ = reply.send(result);
+            }
+            CaptureControl::Cancel { reply } => {
+                let result = match self.recording.take() {
+                    Some(_) => {
+                        let mut tail = Vec::new();
+                        let result = self.resampler.flush(&mut tail);
+                        if let Some(listener) = self.sample_listener.as_ref() {
+                            listener(&tail);
+                        }
+                        result
+                    }
+                    None => Err("Not recording".to_string()),
+                };
+                if let Some(writer) = self.spool_writer.take() {
+                    writer.discard();
+                }
+                let _ = reply.send(result);
+            }
+        }
+    }
+
+    /// Move every queued frame to the recording or the pre-roll.
+    /// Returns false if the ring buffer was empty.
+    fn drain(&mut self) -> bool {
+        self.drain_levels();
+
+        let available = self.consumer.slots();
+        if available == 0 {
+            return false;
+        }
+
+        let chunk = match self.consumer.read_chunk(available) {
+            Ok(chunk) => chunk,
+            Err(_) => return false,
+        };
+        let (first, second) = chunk.as_slices();
+
+        if let Some(output) = self.recording.as_mut() {
+            let start = output.len();
+            let result = self
+                .resampler
+                .process(first, output)
+                .and_then(|_| self.resampler.process(second, output));
+            if let Err(e) = result {
+                eprintln!("Capture worker: {}", e);
+            }
+            if let Some(listener) = self.sample_listener.as_ref() {
+                listener(&output[start..]);
+            }
+        } else if self.pre_roll_samples > 0 {
+            self.pre_roll.extend(first.iter().chain(second.iter()));
+            let excess = self.pre_roll.len().saturating_sub(self.pre_roll_samples);
+            self.pre_roll.drain(..excess);
+        }
+
+        chunk.commit_all();
+        self.spool_recording();
+        true
+    }
+
+    /// Move the recording to the spool file once it is long enough, then keep
+    /// appending to it. Falls back to memory if the file can't be written.
+    fn spool_recording(&mut self) {
+        let (Some(settings), Some(output)) = (self.spool.as_ref(), self.recording.as_mut()) else {
+            return;
+        };
+
+        if self.spool_writer.is_none() {
+            if output.len() < settings.after_samples {
+                return;
+            }
+            match SpoolWriter::create(&settings.dir) {
+                Ok(writer) => {
+                    println!("Spooling long recording to {:?}", writer.path());
+                    self.spool_writer = Some(writer);
+                }
+                Err(e) => {
+                    eprintln!("{}. Keeping the recording in memory.", e);
+                    self.spool = None;
+                    return;
+                }
+            }
+        }
+
+        let Some(writer) = self.spool_writer.as_mut() else {
+            return;
+        };
+        if let Err(e) = writer.append(output) {
+            eprintln!("{}. Keeping the recording in memory.", e);
+            if let Some(writer) = self.spool_writer.take() {
+                match writer.read_back() {
+                    Ok(mut spooled) => {
+                        spooled.extend_from_slice(output);
+                        *output = spooled;
+                    }
+                    Err(e) => eprintln!("Failed to read back the spooled recording: {}", e),
+                }
+            }
+            self.spool = None;
+            return;
+        }
+        output.clear();
+    }
+
+    /// Account for the metered levels and notify the listener, at most every
+    /// `LEVEL_EVENT_INTERVAL`. Levels are ignored between recordings.
+    fn drain_levels(&mut self) {
+        while let Ok(level) = self.levels.pop() {
+            if self.recording.is_some() {
+                self.level_summary.add(&level);
+                self.pending_level = Some(self.pending_level.map_or(level, |p| p.max(level)));
+            }
+        }
+
+        if let (Some(listener), Some(level)) = (self.level_listener.as_ref(), self.pending_level) {
+            if self.last_level_event.elapsed() >= LEVEL_EVENT_INTERVAL {
+                listener(level);
+                self.pending_level = None;
+                self.last_level_event = Instant::now();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_all(input: &mut CaptureInput, value: f32, count: usize) {
+        for _ in 0..count {
+            input.push(value);
+        }
+    }
+
+    /// Wait for the worker to catch up with the frames pushed, failing after a long deadline
+    fn wait_until(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(Instant::now() < deadline, "Capture worker did not catch up");
+            std::thread::sleep(WORKER_POLL_INTERVAL);
+        }
+    }
+
+    fn end_in_memory(pipeline: &CapturePipeline) -> Vec<f32> {
+        match pipeline.end().unwrap().0 {
+            CapturedAudio::Memory(audio) => audio,
+            audio => panic!("Expected the recording in memory, got {:?}", audio),
+        }
+    }
+
+    #[test]
+    fn test_recording_without_pre_roll() {
+        let resampler = StreamingResampler::new(16000).unwrap();
+        let (pipeline, mut input) = CapturePipeline::start(resampler, CaptureOptions::default());
+
+        push_all(&mut input, 0.5, 1600); // before begin: discarded
+        pipeline.begin().unwrap();
+        push_all(&mut input, 0.25, 3200);
+        let audio = end_in_memory(&pipeline);
+
+        assert_eq!(audio.len(), 3200);
+        assert!(audio.iter().all(|&s| s == 0.25));
+        pipeline.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_pre_roll_is_prepended() {
+        let resampler = StreamingResampler::new(16000).unwrap();
+        let (pipeline, mut input) = CapturePipeline::start(
+            resampler,
+            CaptureOptions {
+                pre_roll_samples: 800,
+                ..CaptureOptions::default()
+            },
+        );
+
+        push_all(&mut input, 0.5, 1600); // only the last 800 are kept
+        pipeline.begin().unwrap();
+        push_all(&mut input, 0.25, 1600);
+        let audio = end_in_memory(&pipeline);
+
+        assert_eq!(audio.len(), 2400);
+        assert!(audio[..800].iter().all(|&s| s == 0.5));
+        assert!(audio[800..].iter().all(|&s| s == 0.25));
+
+        // A second recording starts from a fresh pre-roll
+        push_all(&mut input, 0.75, 400);
+        pipeline.begin().unwrap();
+        let audio = end_in_memory(&pipeline);
+        assert_eq!(audio.len(), 400);
+        pipeline.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_end_without_begin_fails() {
+        let resampler = StreamingResampler::new(16000).unwrap();
+        let (pipeline, _input) = CapturePipeline::start(resampler, CaptureOptions::default());
+        assert!(pipeline.end().is_err());
+        pipeline.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_long_recording_is_spooled() {
+        let dir = std::env::temp_dir().join(format!("flemme-capture-spool-{}", std::process::id()));
+        let options = CaptureOptions {
+            spool: Some(SpoolSettings {
+                dir: dir.clone(),
+                after_samples: 1000,
+            }),
+            ..CaptureOptions::default()
+        };
+        let resampler = StreamingResampler::new(16000).unwrap();
+        let (pipeline, mut input) = CapturePipeline::start(resampler, options);
+
+        pipeline.begin().unwrap();
+        push_all(&mut input, 0.5, 1600);
+        wait_until(|| dir.exists() && std::fs::read_dir(&dir).unwrap().count() == 1);
+
+        push_all(&mut input, -0.5, 1600);
+        let (CapturedAudio::Spooled { path, samples }, _) = pipeline.end().unwrap() else {
+            panic!("Expected the recording in its spool file");
+        };
+
+        // The file is handed over whole, with the tail that was still in memory
+        assert_eq!(samples, 3200);
+        let audio = crate::audio::spool::read_spool(&path).unwrap();
+        assert_eq!(audio.len(), 3200);
+        assert!((audio[0] - 0.5).abs() < 1e-3);
+        assert!((audio[3199] + 0.5).abs() < 1e-3);
+        std::fs::remove_file(&path).unwrap();
+
+        // A cancelled recording deletes its spool file without reading it back
+        pipeline.begin().unwrap();
+        push_all(&mut input, 0.5, 3200);
+        wait_until(|| std::fs::read_dir(&dir).unwrap().count() == 1);
+        pipeline.cancel().unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        assert!(pipeline.end().is_err());
+
+        pipeline.shutdown().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sample_listener_sees_the_recording() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let received = Arc::new(AtomicUsize::new(0));
+        let counter = received.clone();
+        let options = CaptureOptions {
+            sample_listener: Some(Arc::new(move |samples: &[f32]| {
+                counter.fetch_add(samples.len(), Ordering::SeqCst);
+            })),
+            ..CaptureOptions::default()
+        };
+        let resampler = StreamingResampler::new(16000).unwrap();
+        let (pipeline, mut input) = CapturePipeline::start(resampler, options);
+
+        push_all(&mut input, 0.5, 1600); // before begin: not forwarded
+        pipeline.begin().unwrap();
+        push_all(&mut input, 0.25, 3200);
+        pipeline.end().unwrap();
+
+        assert_eq!(received.load(Ordering::SeqCst), 3200);
+        pipeline.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+// Endpointing - finds the end of speech (auto-stop) and the utterances (continuous dictation)
+// in the audio streamed during a recording, from VAD decisions
+use super::resampler::TARGET_SAMPLE_RATE;
+use super::vad::SpeechSegment;
+use std::collections::VecDeque;
+
+/// Audio kept before and after each utterance of a continuous dictation
+const UTTERANCE_PADDING_MS: u32 = 200;
+
+/// Utterances are cut at this length even without a pause (Whisper works on 30s windows)
+const MAX_UTTERANCE_MS: u32 = 25_000;
+
+/// When an auto-stop recording ends
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EndpointSettings {
+    /// Silence after speech that ends the recording
+    pub trailing_silence_ms: u32,
+    /// Cancel the recording if nobody speaks for this long (0 waits forever)
+    pub no_speech_timeout_ms: u32,
+}
+
+/// Why an auto-stop recording is over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Speech was followed by the trailing silence: transcribe the recording
+    SpeechEnded,
+    /// Nobody spoke before the timeout: cancel the recording
+    NoSpeech,
+}
+
+/// Cuts the 16kHz audio arriving during a recording into VAD chunks and
+/// tracks speech and silence to find the end of the utterance
+pub struct Endpointer {
+    settings: EndpointSettings,
+    // Samples per VAD decision (`Vad::chunk_size`)
+    chunk_size: usize,
+    // Samples waiting for a full VAD chunk
+    pending: Vec<f32>,
+    speech_detected: bool,
+    silence_samples: usize,
+    elapsed_samples: usize,
+}
+
+impl Endpointer {
+    pub fn new(settings: EndpointSettings, chunk_size: usize) -> Self {
+        Self {
+            settings,
+            chunk_size,
+            pending: Vec::with_capacity(chunk_size * 2),
+            speech_detected: false,
+            silence_samples: 0,
+            elapsed_samples: 0,
+        }
+    }
+
+    /// Feed newly recorded samples; `is_speech` is asked about each complete
+    /// `chunk_size` chunk. Returns the endpoint once reached.
+    pub fn push(&mut self, samples: &[f32], mut is_speech: impl FnMut(&[f32]) -> bool) -> Option<Endpoint> {
+        self.pending.extend_from_slice(samples);
+
+        let mut offset = 0;
+        let mut endpoint = None;
+        while self.pending.len() - offset >= self.chunk_size {
+            let speech = is_speech(&self.pending[offset..offset + self.chunk_size]);
+            offset += self.chunk_size;
+            endpoint = self.update(speech);
+            if endpoint.is_some() {
+                break;
+            }
+        }
+        self.pending.drain(..offset);
+        endpoint
+    }
+
+    /// Whether speech has been heard since the recording started
+    pub fn speech_detected(&self) -> bool {
+        self.speech_detected
+    }
+
+    fn update(&mut self, speech: bool) -> Option<Endpoint> {
+        self.elapsed_samples += self.chunk_size;
+        if speech {
+            self.speech_detected = true;
+            self.silence_samples = 0;
+            return None;
+        }
+        self.silence_samples += self.chunk_size;
+
+        if self.speech_detected {
+            (self.silence_samples >= ms_to_samples(self.settings.trailing_silence_ms)).then_some(Endpoint::SpeechEnded)
+        } else {
+            let timeout = self.settings.no_speech_timeout_ms;
+            (timeout > 0 && self.elapsed_samples >= ms_to_samples(timeout)).then_some(Endpoint::NoSpeech)
+        }
+    }
+}
+
+/// A closed utterance of a continuous dictation
+pub struct Utterance {
+    /// Position in the recording, in 16kHz samples
+    pub segment: SpeechSegment,
+    pub samples: Vec<f32>,
+}
+
+/// Cuts the audio streamed during a continuous dictation into pause-delimited
+/// utterances, each padded with a little of the surrounding silence
+pub struct UtteranceSegmenter {
+    // Samples per VAD decision (`Vad::chunk_size`)
+    chunk_size: usize,
+    trailing_silence_samples: usize,
+    padding_samples: usize,
+    max_samples: usize,
+    // Samples waiting for a full VAD chunk
+    pending: Vec<f32>,
+    // Samples of the recording already analyzed
+    position: usize,
+    // Latest silence, prepended to the next utterance
+    pre_speech: VecDeque<f32>,
+    current: Option<Utterance>,
+    silence_samples: usize,
+}
+
+impl UtteranceSegmenter {
+    /// `trailing_silence_ms` of silence after speech closes an utterance
+    pub fn new(trailing_silence_ms: u32, chunk_size: usize) -> Self {
+        let padding_samples = ms_to_samples(UTTERANCE_PADDING_MS);
+        Self {
+            chunk_size,
+            trailing_silence_samples: ms_to_samples(trailing_silence_ms).max(padding_samples),
+            padding_samples,
+            max_samples: ms_to_samples(MAX_UTTERANCE_MS),
+            pending: Vec::with_capacity(chunk_size * 2),
+            position: 0,
+            pre_speech: VecDeque::with_capacity(padding_samples + chunk_size),
+            current: None,
+            silence_samples: 0,
+        }
+    }
+
+    /// Feed newly recorded samples; `is_speech` is asked about each complete
+    /// `chunk_size` chunk. Returns the utterances closed by this audio.
+    pub fn push(&mut self, samples: &[f32], mut is_speech: impl FnMut(&[f32]) -> bool) -> Vec<Utterance> {
+        // Taken out while the chunks borrow it
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.extend_from_slice(samples);
+
+        let mut utterances = Vec::new();
+        let mut chunks = pending.chunks_exact(self.chunk_size);
+        for chunk in &mut chunks {
+            let speech = is_speech(chunk);
+            utterances.extend(self.update(chunk, speech));
+            self.position += self.chunk_size;
+        }
+        let consumed = pending.len() - chunks.remainder().len();
+        pending.drain(..consumed);
+        self.pending = pending;
+        utterances
+    }
+
+    /// Close the utterance in progress at the end of the recording
+    pub fn finish(mut self) -> Option<Utterance> {
+        let mut utterance = self.current.take()?;
+        utterance.samples.extend_from_slice(&self.pending);
+        utterance.segment.end += self.pending.len();
+        Some(utterance)
+    }
+
+    fn update(&mut self, chunk: &[f32], speech: bool) -> Option<Utterance> {
+        let Some(utterance) = self.current.as_mut() else {
+            if speech {
+                let mut samples: Vec<f32> = self.pre_speech.drain(..).collect();
+                let start = self.position - samples.len();
+                samples.extend_from_slice(chunk);
+                self.current = Some(Utterance {
+                    segment: SpeechSegment {
+                        start,
+                        end: self.position + chunk.len(),
+                    },
+                    samples,
+                });
+                self.silence_samples = 0;
+            } else {
+                self.pre_speech.extend(chunk);
+                let excess = self.pre_speech.len().saturating_sub(self.padding_samples);
+                self.pre_speech.drain(..excess);
+            }
+            return None;
+        };
+
+        utterance.samples.extend_from_slice(chunk);
+        utterance.segment.end += chunk.len();
+        if speech {
+            self.silence_samples = 0;
+        } else {
+            self.silence_samples += chunk.len();
+        }
+
+        if self.silence_samples >= self.trailing_silence_samples {
+            // Keep only the padding of the trailing silence
+            let trim = self.silence_samples - self.padding_samples;
+            utterance.samples.truncate(utterance.samples.len() - trim);
+            utterance.segment.end -= trim;
+        } else if utterance.samples.len() < self.max_samples {
+            return None;
+        }
+        self.silence_samples = 0;
+        self.current.take()
+    }
+}
+
+fn ms_to_samples(ms: u32) -> usize {
+    ms as usize * TARGET_SAMPLE_RATE as usize / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Silero's default window
+    const VAD_CHUNK_SAMPLES: usize = 512;
+
+    const SETTINGS: EndpointSettings = EndpointSettings {
+        trailing_silence_ms: 320,
+        no_speech_timeout_ms: 1024,
+    };
+
+    /// Loud samples stand for speech
+    fn energy(chunk: &[f32]) -> bool {
+        chunk[0] > 0.5
+    }
+
+    #[test]
+    fn test_trailing_silence_ends_recording() {
+        let mut endpointer = Endpointer::new(SETTINGS, VAD_CHUNK_SAMPLES);
+
+        // Speech arriving in blocks that don't line up with VAD chunks
+        assert_eq!(endpointer.push(&[0.0; 3000], energy), None);
+        assert_eq!(endpointer.push(&[1.0; 5000], energy), None);
+        assert!(endpointer.speech_detected());
+
+        // 320ms = 10 chunks of silence
+        assert_eq!(endpointer.push(&[0.0; 4608], energy), None);
+        assert_eq!(endpointer.push(&[0.0; 1000], energy), Some(Endpoint::SpeechEnded));
+    }
+
+    #[test]
+    fn test_no_speech_timeout_cancels_recording() {
+        let mut endpointer = Endpointer::new(SETTINGS, VAD_CHUNK_SAMPLES);
+        assert_eq!(endpointer.push(&[0.0; 16000], energy), None);
+        assert_eq!(endpointer.push(&[0.0; 512], energy), Some(Endpoint::NoSpeech));
+
+        // Disabled timeout waits forever
+        let settings = EndpointSettings {
+            no_speech_timeout_ms: 0,
+            ..SETTINGS
+        };
+        let mut endpointer = Endpointer::new(settings, VAD_CHUNK_SAMPLES);
+        assert_eq!(endpointer.push(&[0.0; 160000], energy), None);
+    }
+
+    #[test]
+    fn test_utterances_are_split_on_pauses() {
+        // 320ms of silence closes an utterance
+        let mut segmenter = UtteranceSegmenter::new(320, VAD_CHUNK_SAMPLES);
+        let mut utterances = Vec::new();
+
+        // 1s silence, 1s speech, 0.5s pause, 0.5s speech, then the recording stops
+        utterances.extend(segmenter.push(&[0.0; 16384], energy));
+        utterances.extend(segmenter.push(&[1.0; 16384], energy));
+        utterances.extend(segmenter.push(&[0.0; 8192], energy));
+        assert_eq!(utterances.len(), 1);
+        utterances.extend(segmenter.push(&[1.0; 8192], energy));
+        assert_eq!(utterances.len(), 1);
+        utterances.extend(segmenter.finish());
+        assert_eq!(utterances.len(), 2);
+
+        // 200ms (3200 samples) of padding each side, rounded to whole VAD chunks
+        let first = &utterances[0];
+        assert_eq!(first.segment.start, 16384 - 3200);
+        assert_eq!(first.samples.len(), first.segment.end - first.segment.start);
+        assert!(first.segment.end >= 32768 + 3200 && first.segment.end < 32768 + 3200 + VAD_CHUNK_SAMPLES);
+        assert_eq!(first.samples[0], 0.0);
+        assert_eq!(first.samples[3200], 1.0);
+
+        let second = &utterances[1];
+        assert!(second.segment.start >= first.segment.end);
+        assert_eq!(second.segment.end, 16384 * 3);
+        assert_eq!(second.samples.len(), second.segment.end - second.segment.start);
+    }
+
+    #[test]
+    fn test_long_utterances_are_cut() {
+        let mut segmenter = UtteranceSegmenter::new(320, VAD_CHUNK_SAMPLES);
+        let utterances = segmenter.push(&vec![1.0; 16000 * 60], energy);
+        assert_eq!(utterances.len(), 2);
+        let max = ms_to_samples(MAX_UTTERANCE_MS) + VAD_CHUNK_SAMPLES;
+        assert!(utterances.iter().all(|u| u.samples.len() <= max));
+        assert!(segmenter.finish().is_some());
+    }
+}
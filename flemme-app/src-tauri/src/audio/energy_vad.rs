@@ -0,0 +1,215 @@
+// EnergyVad - pure Rust fallback voice activity detector (adaptive energy + zero-crossing rate)
+use super::vad::Vad;
+use crate::config::VadSettings;
+use std::collections::VecDeque;
+
+/// Chunks quieter than this are digital silence (padding, muted input), not room noise
+const SILENCE_FLOOR_DB: f32 = -60.0;
+
+/// The noise floor is the quietest chunk over this window: speech always
+/// leaves gaps within it, steady noise does not
+const NOISE_WINDOW_MS: usize = 1500;
+
+/// Signal-to-noise ratios mapped to a speech probability of 0 and 1
+const SNR_LOW_DB: f32 = 3.0;
+const SNR_HIGH_DB: f32 = 15.0;
+
+/// Zero crossings per sample above which a chunk sounds like hiss or clicks rather than voice
+const MAX_SPEECH_ZCR: f32 = 0.35;
+
+/// Detects speech as chunks standing out of an adaptive noise floor, with a
+/// low enough zero-crossing rate. Used when the Silero model is unavailable.
+pub struct EnergyVad {
+    settings: VadSettings,
+    // Energy of the latest chunks (dBFS), over `NOISE_WINDOW_MS`
+    recent_energy: VecDeque<f32>,
+}
+
+impl EnergyVad {
+    pub fn new(settings: VadSettings) -> Result<Self, String> {
+        settings.validate()?;
+        Ok(Self {
+            settings,
+            recent_energy: VecDeque::new(),
+        })
+    }
+
+    fn window_chunks(&self) -> usize {
+        (NOISE_WINDOW_MS * 16 / self.settings.chunk_size).max(1)
+    }
+}
+
+impl Vad for EnergyVad {
+    fn detect(&mut self, audio_data: &[f32]) -> Result<f32, String> {
+        if audio_data.is_empty() {
+            return Err("Audio chunk is empty".to_string());
+        }
+
+        // Silent chunks count at the floor, so a muted input doesn't leave the
+        // noise floor of the audio before it
+        let energy_db = energy_dbfs(audio_data).max(SILENCE_FLOOR_DB);
+        self.recent_energy.push_back(energy_db);
+        if self.recent_energy.len() > self.window_chunks() {
+            self.recent_energy.pop_front();
+        }
+        if energy_db <= SILENCE_FLOOR_DB {
+            return Ok(0.0);
+        }
+        let noise_floor_db = self.recent_energy.iter().copied().fold(f32::INFINITY, f32::min);
+        let snr_db = energy_db - noise_floor_db;
+
+        let probability = ((snr_db - SNR_LOW_DB) / (SNR_HIGH_DB - SNR_LOW_DB)).clamp(0.0, 1.0);
+        if zero_crossing_rate(audio_data) > MAX_SPEECH_ZCR {
+            return Ok(probability * 0.5);
+        }
+        Ok(probability)
+    }
+
+    fn reset(&mut self) {
+        self.recent_energy.clear();
+    }
+
+    fn settings(&self) -> &VadSettings {
+        &self.settings
+    }
+
+    fn set_settings(&mut self, settings: VadSettings) -> Result<(), String> {
+        settings.validate()?;
+        self.settings = settings;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "energy"
+    }
+}
+
+/// RMS level in dBFS
+fn energy_dbfs(samples: &[f32]) -> f32 {
+    let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+    10.0 * mean_square.max(1e-10).log10()
+}
+
+/// Share of consecutive samples changing sign
+fn zero_crossing_rate(samples: &[f32]) -> f32 {
+    let crossings = samples
+        .windows(2)
+        .filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0))
+        .count();
+    crossings as f32 / samples.len().max(2) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 100ms of silence between joined segments
+    const SEGMENT_JOIN: usize = 1600;
+
+    /// Deterministic noise in [-amplitude, amplitude]
+    fn noise(len: usize, amplitude: f32, seed: &mut u32) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                *seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                (*seed as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn tone(len: usize, amplitude: f32) -> Vec<f32> {
+        (0..len)
+            .map(|i| (i as f32 * 2.0 * std::f32::consts::PI * 200.0 / 16000.0).sin() * amplitude)
+            .collect()
+    }
+
+    #[test]
+    fn test_tone_is_speech_over_quiet_noise() {
+        let mut vad = EnergyVad::new(VadSettings::default()).unwrap();
+        let mut seed = 1;
+
+        // 1s of room noise, 1s of "voice", 1s of room noise
+        let mut audio = noise(16000, 0.003, &mut seed);
+        audio.extend(tone(16000, 0.3));
+        audio.extend(noise(16000, 0.003, &mut seed));
+
+        let segments = vad.speech_segments(&audio, 512);
+        assert_eq!(segments.len(), 1);
+        assert!(segments[0].start.abs_diff(16000) <= 512);
+        assert!(segments[0].end.abs_diff(32000) <= 512);
+    }
+
+    #[test]
+    fn test_steady_noise_is_not_speech() {
+        let mut vad = EnergyVad::new(VadSettings::default()).unwrap();
+        let mut seed = 7;
+
+        // Loud broadband noise (fan, air conditioning) and a low hum
+        let audio = noise(16000 * 5, 0.05, &mut seed);
+        assert_eq!(vad.speech_ratio(&audio, 512), 0.0);
+        let hum = tone(16000 * 5, 0.05);
+        assert_eq!(vad.speech_ratio(&hum, 512), 0.0);
+
+        // Digital silence is not speech either
+        assert_eq!(vad.speech_ratio(&[0.0; 16000], 512), 0.0);
+    }
+
+    #[test]
+    fn test_silence_resets_the_noise_floor() {
+        let mut vad = EnergyVad::new(VadSettings::default()).unwrap();
+        let mut seed = 9;
+
+        // Loud noise, then a muted input, then quieter speech
+        let mut audio = noise(16000 * 2, 0.05, &mut seed);
+        audio.extend([0.0; 16000 * 2]);
+        audio.extend(tone(16000, 0.02));
+
+        let segments = vad.speech_segments(&audio, 512);
+        assert_eq!(segments.len(), 1);
+        assert!(segments[0].start.abs_diff(64000) <= 512);
+    }
+
+    #[test]
+    fn test_filtered_segments_are_padded_and_spaced() {
+        let mut vad = EnergyVad::new(VadSettings::default()).unwrap();
+        let mut seed = 3;
+
+        // Two 0.5s "words" 1s apart in 4s of room noise
+        let mut audio = noise(16000, 0.003, &mut seed);
+        audio.extend(tone(8000, 0.3));
+        audio.extend(noise(16000, 0.003, &mut seed));
+        audio.extend(tone(8000, 0.3));
+        audio.extend(noise(16000, 0.003, &mut seed));
+
+        // 150ms (2400 samples) kept on both sides of each word
+        let segments = vad.padded_speech_segments(&audio, 512);
+        assert_eq!(segments.len(), 2);
+        assert!(segments[0].start <= 16000 - 2400 && segments[0].end >= 24000 + 2400);
+        assert!(segments[1].start <= 40000 - 2400 && segments[1].end >= 48000 + 2400);
+
+        // Joined with 100ms of silence in between
+        let filtered = vad.filter_silence(&audio, 512);
+        let first = segments[0].end - segments[0].start;
+        let second = segments[1].end - segments[1].start;
+        assert_eq!(filtered.len(), first + SEGMENT_JOIN + second);
+        assert_eq!(filtered[..first], audio[segments[0].start..segments[0].end]);
+        assert!(filtered[first..first + SEGMENT_JOIN].iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn test_words_close_together_are_one_segment() {
+        let mut vad = EnergyVad::new(VadSettings::default()).unwrap();
+        let mut seed = 5;
+
+        // A 400ms pause splits the speech, but not once padded
+        let mut audio = noise(16000, 0.003, &mut seed);
+        audio.extend(tone(8000, 0.3));
+        audio.extend(noise(6400, 0.003, &mut seed));
+        audio.extend(tone(8000, 0.3));
+        audio.extend(noise(16000, 0.003, &mut seed));
+
+        assert_eq!(vad.speech_segments(&audio, 512).len(), 2);
+        let segments = vad.padded_speech_segments(&audio, 512);
+        assert_eq!(segments.len(), 1);
+        assert!(segments[0].start <= 16000 - 2400 && segments[0].end >= 38400 + 2400);
+    }
+}
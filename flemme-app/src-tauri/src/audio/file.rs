@@ -0,0 +1,154 @@
+// AudioFileDecoder - decodes WAV/FLAC/OGG/MP3 files to 16kHz mono with Symphonia
+use super::resampler::StreamingResampler;
+use std::fs::File;
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Incremental decoder: reads packets on demand, downmixes them to mono and
+/// resamples to 16kHz, so long files never have to be held in memory at once.
+pub struct AudioFileDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    resampler: StreamingResampler,
+    sample_buffer: Option<SampleBuffer<f32>>,
+    duration_seconds: Option<f64>,
+    // 16kHz samples decoded but not yet returned
+    pending: Vec<f32>,
+    finished: bool,
+}
+
+impl AudioFileDecoder {
+    /// Open an audio file and prepare the decoder for its first audio track
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| format!("Failed to open audio file {:?}: {}", path, e))?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+        // Help the probe with the file extension
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(extension);
+        }
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+            .map_err(|e| format!("Unsupported audio file format: {}", e))?;
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or_else(|| "No audio track found in file".to_string())?;
+        let track_id = track.id;
+        let sample_rate = track
+            .codec_params
+            .sample_rate
+            .ok_or_else(|| "Unknown sample rate in audio file".to_string())?;
+
+        let duration_seconds = track
+            .codec_params
+            .n_frames
+            .map(|frames| frames as f64 / sample_rate as f64);
+
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(|e| format!("Unsupported audio codec: {}", e))?;
+
+        println!("Decoding audio file {:?} ({} Hz)", path, sample_rate);
+
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            resampler: StreamingResampler::new(sample_rate)?,
+            sample_buffer: None,
+            duration_seconds,
+            pending: Vec::new(),
+            finished: false,
+        })
+    }
+
+    /// Decode the whole file to 16kHz mono
+    pub fn decode_all<P: AsRef<Path>>(path: P) -> Result<Vec<f32>, String> {
+        let mut decoder = Self::open(path)?;
+        let mut output = Vec::new();
+        while let Some(block) = decoder.next_block(16000 * 30)? {
+            output.extend_from_slice(&block);
+        }
+        Ok(output)
+    }
+
+    /// Duration of the file in seconds, if the container declares it
+    pub fn duration_seconds(&self) -> Option<f64> {
+        self.duration_seconds
+    }
+
+    /// Return the next block of at most `max_samples` 16kHz mono samples,
+    /// or `None` at the end of the file
+    pub fn next_block(&mut self, max_samples: usize) -> Result<Option<Vec<f32>>, String> {
+        while self.pending.len() < max_samples && !self.finished {
+            self.decode_next_packet()?;
+        }
+
+        if self.pending.is_empty() {
+            return Ok(None);
+        }
+
+        let take = max_samples.min(self.pending.len());
+        Ok(Some(self.pending.drain(..take).collect()))
+    }
+
+    fn decode_next_packet(&mut self) -> Result<(), String> {
+        let packet = match self.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                self.finished = true;
+                return self.resampler.flush(&mut self.pending);
+            }
+            Err(e) => return Err(format!("Failed to read audio packet: {}", e)),
+        };
+
+        if packet.track_id() != self.track_id {
+            return Ok(());
+        }
+
+        let decoded = match self.decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Corrupted packets are skipped, like most players do
+            Err(SymphoniaError::DecodeError(e)) => {
+                eprintln!("Skipping undecodable audio packet: {}", e);
+                return Ok(());
+            }
+            Err(e) => return Err(format!("Failed to decode audio: {}", e)),
+        };
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+
+        let buffer = match self.sample_buffer.as_mut() {
+            Some(buffer) if buffer.capacity() >= decoded.capacity() * channels => buffer,
+            _ => self
+                .sample_buffer
+                .insert(SampleBuffer::<f32>::new(decoded.capacity() as u64, spec)),
+        };
+        buffer.copy_interleaved_ref(decoded);
+
+        // Downmix to mono (average all channels)
+        let mono: Vec<f32> = buffer
+            .samples()
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+
+        self.resampler.process(&mono, &mut self.pending)
+    }
+}
@@ -0,0 +1,231 @@
+// Input level metering - RMS/peak per block for live feedback and post-recording warnings
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Length of a metering block
+pub const LEVEL_BLOCK_MS: u32 = 50;
+
+/// Samples at or above this magnitude count as clipped
+const CLIPPING_THRESHOLD: f32 = 0.999;
+
+/// Floor used for silence, so dBFS values stay finite
+const MIN_DBFS: f32 = -100.0;
+
+/// A recording whose speech level stays under this RMS is considered too quiet
+const QUIET_RMS_DBFS: f32 = -40.0;
+
+/// Share of the loudest blocks taken as speech, the others being mostly pauses
+const SPEECH_BLOCKS_RATIO: f32 = 0.5;
+
+/// One bin per dBFS, from 0 down to `MIN_DBFS`
+const RMS_HISTOGRAM_BINS: usize = 101;
+
+/// Fraction of clipped blocks above which a recording is considered badly clipped
+const CLIPPING_WARNING_RATIO: f32 = 0.02;
+
+/// Level of one block of input audio
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct AudioLevel {
+    pub rms_dbfs: f32,
+    pub peak_dbfs: f32,
+    pub clipping: bool,
+}
+
+impl AudioLevel {
+    /// Combine two levels, keeping the loudest values
+    pub fn max(self, other: AudioLevel) -> AudioLevel {
+        AudioLevel {
+            rms_dbfs: self.rms_dbfs.max(other.rms_dbfs),
+            peak_dbfs: self.peak_dbfs.max(other.peak_dbfs),
+            clipping: self.clipping || other.clipping,
+        }
+    }
+}
+
+/// Receives the live input level while recording
+pub type LevelListener = Arc<dyn Fn(AudioLevel) + Send + Sync>;
+
+/// Convert a linear amplitude to dBFS
+pub fn to_dbfs(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return MIN_DBFS;
+    }
+    (20.0 * amplitude.log10()).max(MIN_DBFS)
+}
+
+/// Accumulates samples and produces an `AudioLevel` every `LEVEL_BLOCK_MS`
+///
+/// Allocation-free, so it can run in the audio callback.
+pub struct LevelMeter {
+    block_samples: usize,
+    count: usize,
+    sum_squares: f32,
+    peak: f32,
+    clipping: bool,
+}
+
+impl LevelMeter {
+    /// Create a meter for audio at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            block_samples: (sample_rate * LEVEL_BLOCK_MS / 1000).max(1) as usize,
+            count: 0,
+            sum_squares: 0.0,
+            peak: 0.0,
+            clipping: false,
+        }
+    }
+
+    /// Add one sample, returning the level when a block is complete
+    pub fn push(&mut self, sample: f32) -> Option<AudioLevel> {
+        let magnitude = sample.abs();
+        self.sum_squares += sample * sample;
+        self.peak = self.peak.max(magnitude);
+        self.clipping |= magnitude >= CLIPPING_THRESHOLD;
+        self.count += 1;
+
+        if self.count < self.block_samples {
+            return None;
+        }
+
+        let level = AudioLevel {
+            rms_dbfs: to_dbfs((self.sum_squares / self.count as f32).sqrt()),
+            peak_dbfs: to_dbfs(self.peak),
+            clipping: self.clipping,
+        };
+        self.count = 0;
+        self.sum_squares = 0.0;
+        self.peak = 0.0;
+        self.clipping = false;
+        Some(level)
+    }
+}
+
+/// Level statistics of a whole recording
+#[derive(Debug, Clone, Copy)]
+pub struct LevelSummary {
+    pub blocks: usize,
+    pub clipped_blocks: usize,
+    pub max_rms_dbfs: Option<f32>,
+    pub peak_dbfs: Option<f32>,
+    /// Blocks by RMS, rounded down to the dBFS (index 0 is 0 dBFS)
+    rms_histogram: [u32; RMS_HISTOGRAM_BINS],
+}
+
+impl Default for LevelSummary {
+    fn default() -> Self {
+        Self {
+            blocks: 0,
+            clipped_blocks: 0,
+            max_rms_dbfs: None,
+            peak_dbfs: None,
+            rms_histogram: [0; RMS_HISTOGRAM_BINS],
+        }
+    }
+}
+
+impl LevelSummary {
+    /// Account for one block of the recording
+    pub fn add(&mut self, level: &AudioLevel) {
+        self.blocks += 1;
+        if level.clipping {
+            self.clipped_blocks += 1;
+        }
+        self.max_rms_dbfs = Some(self.max_rms_dbfs.map_or(level.rms_dbfs, |v| v.max(level.rms_dbfs)));
+        self.peak_dbfs = Some(self.peak_dbfs.map_or(level.peak_dbfs, |v| v.max(level.peak_dbfs)));
+        let bin = (-level.rms_dbfs).clamp(0.0, (RMS_HISTOGRAM_BINS - 1) as f32) as usize;
+        self.rms_histogram[bin] += 1;
+    }
+
+    /// Median RMS of the loudest half of the blocks: the level of the speech,
+    /// which a single loud block (a cough, a desk knock) doesn't change
+    pub fn speech_rms_dbfs(&self) -> Option<f32> {
+        if self.blocks == 0 {
+            return None;
+        }
+        let rank = ((self.blocks as f32 * SPEECH_BLOCKS_RATIO / 2.0).ceil() as u32).max(1);
+        let mut louder = 0;
+        for (bin, &count) in self.rms_histogram.iter().enumerate() {
+            louder += count;
+            if louder >= rank {
+                return Some(-(bin as f32));
+            }
+        }
+        Some(MIN_DBFS)
+    }
+
+    /// Warning to show the user if the recording was too quiet or clipped badly
+    pub fn warning(&self) -> Option<String> {
+        if self.blocks == 0 {
+            return None;
+        }
+
+        let clipped_ratio = self.clipped_blocks as f32 / self.blocks as f32;
+        if clipped_ratio > CLIPPING_WARNING_RATIO {
+            return Some(format!(
+                "Input clipped in {:.0}% of the recording: lower the microphone gain",
+                clipped_ratio * 100.0
+            ));
+        }
+
+        let speech_rms = self.speech_rms_dbfs().unwrap_or(MIN_DBFS);
+        if speech_rms < QUIET_RMS_DBFS {
+            return Some(format!(
+                "Input level very low ({:.0} dBFS): check the selected microphone or speak closer to it",
+                speech_rms
+            ));
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_emits_one_level_per_block() {
+        let mut meter = LevelMeter::new(16000);
+        let levels: Vec<AudioLevel> = (0..1600).filter_map(|_| meter.push(0.5)).collect();
+
+        // 100ms at 16kHz = two 50ms blocks
+        assert_eq!(levels.len(), 2);
+        assert!((levels[0].rms_dbfs - to_dbfs(0.5)).abs() < 0.01);
+        assert!((levels[0].peak_dbfs + 6.02).abs() < 0.01);
+        assert!(!levels[0].clipping);
+    }
+
+    #[test]
+    fn test_meter_detects_clipping() {
+        let mut meter = LevelMeter::new(16000);
+        let level = (0..800)
+            .filter_map(|i| meter.push(if i == 10 { 1.0 } else { 0.1 }))
+            .next()
+            .unwrap();
+        assert!(level.clipping);
+        assert_eq!(level.peak_dbfs, 0.0);
+    }
+
+    #[test]
+    fn test_summary_warnings() {
+        let quiet = AudioLevel { rms_dbfs: -55.0, peak_dbfs: -45.0, clipping: false };
+        let normal = AudioLevel { rms_dbfs: -20.0, peak_dbfs: -6.0, clipping: false };
+        let clipped = AudioLevel { rms_dbfs: -3.0, peak_dbfs: 0.0, clipping: true };
+
+        let mut summary = LevelSummary::default();
+        (0..20).for_each(|_| summary.add(&quiet));
+        assert!(summary.warning().unwrap().contains("very low"));
+
+        // A cough in a quiet recording doesn't hide it
+        summary.add(&AudioLevel { rms_dbfs: -10.0, peak_dbfs: -2.0, clipping: false });
+        assert!(summary.warning().unwrap().contains("very low"));
+
+        // Normal speech with quiet pauses is fine
+        (0..20).for_each(|_| summary.add(&normal));
+        assert!(summary.warning().is_none());
+
+        (0..5).for_each(|_| summary.add(&clipped));
+        assert!(summary.warning().unwrap().contains("clipped"));
+    }
+}
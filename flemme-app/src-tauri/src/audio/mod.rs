@@ -1,6 +1,23 @@
 // Audio module - handles audio recording and processing
+pub mod capture;
+pub mod endpoint;
+pub mod energy_vad;
+pub mod file;
+pub mod level;
+pub mod processing;
 pub mod recorder;
+pub mod resampler;
+pub mod source;
+pub mod spool;
+pub mod system;
 pub mod vad;
 
-pub use recorder::AudioRecorder;
-pub use vad::{VoiceActivityDetector, SpeechSegment};
+pub use capture::SampleListener;
+pub use endpoint::{Endpoint, EndpointSettings, Endpointer, Utterance, UtteranceSegmenter};
+pub use level::{AudioLevel, LevelListener, LevelSummary};
+pub use recorder::{AudioRecorder, KeepWarm};
+pub use source::{AudioChannel, AudioSource, DualAudioSource, FileAudioSource, MemoryAudioSource, SpooledRecording};
+pub use spool::{OrphanedRecording, SpoolSettings};
+pub use system::{AudioDeviceInfo, DeviceKind};
+pub use energy_vad::EnergyVad;
+pub use vad::{SpeechSegment, Vad, VoiceActivityDetector};
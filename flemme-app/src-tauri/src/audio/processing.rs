@@ -0,0 +1,472 @@
+// Audio processing - preprocessing shared by every audio source before VAD/Whisper
+use super::level::to_dbfs;
+use super::resampler::{StreamingResampler, TARGET_SAMPLE_RATE};
+use crate::config::PreprocessingSettings;
+use realfft::num_complex::Complex;
+use realfft::RealFftPlanner;
+
+/// Peak ceiling of the limiter (and of peak normalization), to avoid clipping
+const PEAK_CEILING: f32 = 0.95;
+
+/// Limiter release time
+const LIMITER_RELEASE_MS: f32 = 50.0;
+
+/// Loudness measurement block (as in EBU R128 / BS.1770)
+const LOUDNESS_BLOCK_MS: usize = 400;
+
+/// Blocks quieter than this are ignored when measuring loudness
+const LOUDNESS_ABSOLUTE_GATE_DBFS: f32 = -70.0;
+
+/// Blocks more than this below the ungated loudness are ignored
+const LOUDNESS_RELATIVE_GATE_DB: f32 = 10.0;
+
+/// Never boost more than this, so near-silent recordings are not turned into loud noise
+const MAX_LOUDNESS_GAIN_DB: f32 = 30.0;
+
+/// Noise suppressor frame (32ms at 16kHz) and hop (50% overlap)
+const NOISE_FRAME_SIZE: usize = 512;
+const NOISE_HOP_SIZE: usize = NOISE_FRAME_SIZE / 2;
+
+/// Leading frames at most used for the noise profile (~1s)
+const NOISE_PROFILE_MAX_FRAMES: usize = 62;
+
+/// Minimum number of pre-speech frames needed to build a noise profile (~100ms)
+const NOISE_PROFILE_MIN_FRAMES: usize = 6;
+
+/// Speech onset: first frame this many dB above the quietest leading frame
+const SPEECH_ONSET_DB: f32 = 12.0;
+
+/// Bins above mean + this many standard deviations of the noise are kept
+const NOISE_THRESHOLD_STD: f32 = 2.0;
+
+/// Gain applied to the bins classified as noise (-20 dB)
+const NOISE_REDUCTION_GAIN: f32 = 0.1;
+
+/// Prepare 16kHz mono audio for VAD and Whisper: DC removal, then the
+/// configured preprocessing chain
+pub fn prepare_for_transcription(samples: &mut [f32], settings: &PreprocessingSettings) {
+    // Remove DC offset (improves VAD quality)
+    remove_dc_offset(samples);
+
+    if settings.high_pass_filter {
+        high_pass_filter(samples, settings.high_pass_cutoff_hz, TARGET_SAMPLE_RATE);
+    }
+
+    if settings.noise_suppression {
+        suppress_noise(samples);
+    }
+
+    // Bring the audio to a consistent level (critical for consistent VAD performance)
+    if settings.loudness_normalization {
+        normalize_loudness(samples, settings.target_loudness_dbfs, TARGET_SAMPLE_RATE);
+    } else {
+        normalize_peak(samples);
+    }
+}
+
+/// Resample mono audio from `sample_rate` to 16kHz in one pass
+pub fn resample_to_16k(samples: &[f32], sample_rate: u32) -> Result<Vec<f32>, String> {
+    let mut resampler = StreamingResampler::new(sample_rate)?;
+    let mut output = Vec::with_capacity(samples.len() * 16000 / sample_rate.max(1) as usize + 1);
+    resampler.process(samples, &mut output)?;
+    resampler.flush(&mut output)?;
+    Ok(output)
+}
+
+/// Mix several 16kHz recordings into one (the shorter ones are padded with
+/// silence), limiting the peaks where they overlap
+pub fn mix(tracks: &[&[f32]]) -> Vec<f32> {
+    let length = tracks.iter().map(|t| t.len()).max().unwrap_or(0);
+    let mut output = vec![0.0f32; length];
+    for track in tracks {
+        for (out, sample) in output.iter_mut().zip(track.iter()) {
+            *out += sample;
+        }
+    }
+    limit_peaks(&mut output, PEAK_CEILING, TARGET_SAMPLE_RATE);
+    output
+}
+
+/// Remove DC offset from audio signal
+pub fn remove_dc_offset(samples: &mut [f32]) {
+    if samples.is_empty() {
+        return;
+    }
+    let mean: f32 = samples.iter().sum::<f32>() / samples.len() as f32;
+    for sample in samples.iter_mut() {
+        *sample -= mean;
+    }
+}
+
+/// Normalize audio to use full dynamic range (-1.0 to 1.0)
+/// Uses peak normalization to ensure consistent amplitude for VAD
+pub fn normalize_peak(samples: &mut [f32]) {
+    if samples.is_empty() {
+        return;
+    }
+
+    // Find the peak (maximum absolute value)
+    let peak = samples
+        .iter()
+        .map(|s| s.abs())
+        .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .unwrap_or(1.0);
+
+    // Only normalize if peak is significant (avoid amplifying pure noise)
+    if peak > 0.001 {
+        // Normalize to 95% of full scale to avoid potential clipping
+        let factor = PEAK_CEILING / peak;
+        for sample in samples.iter_mut() {
+            *sample *= factor;
+        }
+        println!("Audio normalized: peak={:.4} -> normalized with factor={:.2}", peak, factor);
+    } else {
+        println!("Audio peak too low ({:.6}), skipping normalization (likely silence)", peak);
+    }
+}
+
+/// Second-order Butterworth high-pass filter (removes rumble, plosives and desk noise)
+pub fn high_pass_filter(samples: &mut [f32], cutoff_hz: f32, sample_rate: u32) {
+    if samples.is_empty() || cutoff_hz <= 0.0 || cutoff_hz >= sample_rate as f32 / 2.0 {
+        return;
+    }
+
+    // RBJ audio EQ cookbook coefficients, Q = 1/sqrt(2)
+    let omega = 2.0 * std::f32::consts::PI * cutoff_hz / sample_rate as f32;
+    let alpha = omega.sin() / std::f32::consts::SQRT_2;
+    let cos = omega.cos();
+    let a0 = 1.0 + alpha;
+    let b0 = (1.0 + cos) / 2.0 / a0;
+    let b1 = -(1.0 + cos) / a0;
+    let b2 = b0;
+    let a1 = -2.0 * cos / a0;
+    let a2 = (1.0 - alpha) / a0;
+
+    let (mut x1, mut x2, mut y1, mut y2) = (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+    for sample in samples.iter_mut() {
+        let x0 = *sample;
+        let y0 = b0 * x0 + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+        x2 = x1;
+        x1 = x0;
+        y2 = y1;
+        y1 = y0;
+        *sample = y0;
+    }
+}
+
+/// Measure the loudness of the audio in dBFS: the median RMS of the 400ms
+/// blocks that pass an absolute and a relative gate. Silences and pauses are
+/// gated out, and the median keeps a single cough or knock from affecting
+/// the result. Returns `None` if no block passes the gates.
+pub fn measure_loudness(samples: &[f32], sample_rate: u32) -> Option<f32> {
+    let block = (sample_rate as usize * LOUDNESS_BLOCK_MS / 1000).max(1);
+    let step = (block / 4).max(1); // 75% overlap
+
+    let mut powers = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + block).min(samples.len());
+        if end > start {
+            let window = &samples[start..end];
+            powers.push(window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32);
+        }
+        if end == samples.len() {
+            break;
+        }
+        start += step;
+    }
+
+    let power_to_db = |p: f32| to_dbfs(p.sqrt());
+
+    let absolute: Vec<f32> = powers
+        .into_iter()
+        .filter(|&p| power_to_db(p) > LOUDNESS_ABSOLUTE_GATE_DBFS)
+        .collect();
+    if absolute.is_empty() {
+        return None;
+    }
+
+    let ungated = absolute.iter().sum::<f32>() / absolute.len() as f32;
+    let relative_gate = power_to_db(ungated) - LOUDNESS_RELATIVE_GATE_DB;
+    let mut gated: Vec<f32> = absolute
+        .into_iter()
+        .map(power_to_db)
+        .filter(|&db| db > relative_gate)
+        .collect();
+    gated.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    Some(gated[gated.len() / 2])
+}
+
+/// Apply gain to reach `target_dbfs` loudness, then limit the peaks
+pub fn normalize_loudness(samples: &mut [f32], target_dbfs: f32, sample_rate: u32) {
+    let Some(loudness) = measure_loudness(samples, sample_rate) else {
+        println!("Audio too quiet to measure loudness, skipping normalization (likely silence)");
+        return;
+    };
+
+    let gain_db = (target_dbfs - loudness).min(MAX_LOUDNESS_GAIN_DB);
+    let gain = 10f32.powf(gain_db / 20.0);
+    for sample in samples.iter_mut() {
+        *sample *= gain;
+    }
+
+    limit_peaks(samples, PEAK_CEILING, sample_rate);
+    println!(
+        "Audio normalized: loudness={:.1} dBFS -> gain={:+.1} dB (target {:.1} dBFS)",
+        loudness, gain_db, target_dbfs
+    );
+}
+
+/// Peak limiter: instant attack, exponential release. No sample exceeds `ceiling`.
+pub fn limit_peaks(samples: &mut [f32], ceiling: f32, sample_rate: u32) {
+    let release = (-1000.0 / (LIMITER_RELEASE_MS * sample_rate as f32)).exp();
+    let mut gain = 1.0f32;
+
+    for sample in samples.iter_mut() {
+        let magnitude = sample.abs();
+        let target = if magnitude > ceiling { ceiling / magnitude } else { 1.0 };
+        // Recover towards unity, but never above the gain this sample needs
+        gain = (1.0 - (1.0 - gain) * release).min(target);
+        *sample *= gain;
+    }
+}
+
+/// Spectral gating noise suppressor
+///
+/// The noise profile (per-bin magnitude mean and deviation) is taken from the
+/// frames before speech starts; bins that do not rise clearly above it are
+/// attenuated. Does nothing if the recording has no usable pre-speech region.
+pub fn suppress_noise(samples: &mut [f32]) {
+    if samples.len() < NOISE_FRAME_SIZE * 2 {
+        return;
+    }
+
+    let mut planner = RealFftPlanner::<f32>::new();
+    let fft = planner.plan_fft_forward(NOISE_FRAME_SIZE);
+    let ifft = planner.plan_fft_inverse(NOISE_FRAME_SIZE);
+    let bins = NOISE_FRAME_SIZE / 2 + 1;
+
+    // sqrt-Hann analysis and synthesis windows: overlap-add at 50% is exact
+    let window: Vec<f32> = (0..NOISE_FRAME_SIZE)
+        .map(|i| (std::f32::consts::PI * i as f32 / NOISE_FRAME_SIZE as f32).sin())
+        .collect();
+
+    // Pad so every sample is covered by two frames
+    let mut padded = vec![0.0f32; NOISE_HOP_SIZE];
+    padded.extend_from_slice(samples);
+    padded.resize(padded.len().div_ceil(NOISE_HOP_SIZE) * NOISE_HOP_SIZE + NOISE_HOP_SIZE, 0.0);
+    let frame_count = (padded.len() - NOISE_FRAME_SIZE) / NOISE_HOP_SIZE + 1;
+
+    // Analysis
+    let mut input = fft.make_input_vec();
+    let mut spectra: Vec<Vec<Complex<f32>>> = Vec::with_capacity(frame_count);
+    for frame in 0..frame_count {
+        let start = frame * NOISE_HOP_SIZE;
+        for (i, value) in input.iter_mut().enumerate() {
+            *value = padded[start + i] * window[i];
+        }
+        let mut spectrum = fft.make_output_vec();
+        if fft.process(&mut input, &mut spectrum).is_err() {
+            return;
+        }
+        spectra.push(spectrum);
+    }
+
+    let frame_energy: Vec<f32> = spectra
+        .iter()
+        .map(|s| s.iter().map(|c| c.norm_sqr()).sum::<f32>())
+        .collect();
+    let noise_frames = pre_speech_frames(&frame_energy);
+    if noise_frames < NOISE_PROFILE_MIN_FRAMES {
+        println!("Noise suppression skipped: no pre-speech region to build a noise profile");
+        return;
+    }
+
+    // Magnitudes averaged over neighbouring bins: a steadier estimate, so fewer
+    // noise bins cross the threshold by chance
+    let magnitudes: Vec<Vec<f32>> = spectra.iter().map(|s| smoothed_magnitudes(s)).collect();
+
+    // Noise profile: per-bin magnitude mean and standard deviation
+    let mut mean = vec![0.0f32; bins];
+    let mut variance = vec![0.0f32; bins];
+    for frame in &magnitudes[..noise_frames] {
+        for (bin, m) in frame.iter().enumerate() {
+            mean[bin] += m / noise_frames as f32;
+        }
+    }
+    for frame in &magnitudes[..noise_frames] {
+        for (bin, m) in frame.iter().enumerate() {
+            variance[bin] += (m - mean[bin]).powi(2) / noise_frames as f32;
+        }
+    }
+    let threshold: Vec<f32> = mean
+        .iter()
+        .zip(&variance)
+        .map(|(m, v)| m + NOISE_THRESHOLD_STD * v.sqrt())
+        .collect();
+
+    // Binary gate per bin, then averaged over neighbouring bins and frames so
+    // isolated noise peaks crossing the threshold do not turn into musical noise
+    let mask: Vec<Vec<f32>> = magnitudes
+        .iter()
+        .map(|frame| {
+            frame
+                .iter()
+                .zip(&threshold)
+                .map(|(m, t)| if m > t { 1.0 } else { NOISE_REDUCTION_GAIN })
+                .collect()
+        })
+        .collect();
+
+    // Synthesis
+    let mut output = vec![0.0f32; padded.len()];
+    let mut frame_out = ifft.make_output_vec();
+    for (frame, spectrum) in spectra.iter_mut().enumerate() {
+        let frames = frame.saturating_sub(1)..(frame + 2).min(frame_count);
+        for (bin, c) in spectrum.iter_mut().enumerate() {
+            let bins_around = bin.saturating_sub(1)..(bin + 2).min(bins);
+            let (sum, count) = frames.clone().fold((0.0f32, 0usize), |(sum, count), f| {
+                (sum + mask[f][bins_around.clone()].iter().sum::<f32>(), count + bins_around.len())
+            });
+            *c *= sum / count as f32;
+        }
+        // The inverse transform needs purely real DC and Nyquist bins
+        spectrum[0].im = 0.0;
+        spectrum[bins - 1].im = 0.0;
+
+        if ifft.process(spectrum, &mut frame_out).is_err() {
+            return;
+        }
+        let start = frame * NOISE_HOP_SIZE;
+        for (i, value) in frame_out.iter().enumerate() {
+            output[start + i] += value * window[i] / NOISE_FRAME_SIZE as f32;
+        }
+    }
+
+    samples.copy_from_slice(&output[NOISE_HOP_SIZE..NOISE_HOP_SIZE + samples.len()]);
+    println!("Noise suppression applied (profile from {} ms before speech)", noise_frames * NOISE_HOP_SIZE * 1000 / 16000);
+}
+
+/// Magnitude of each bin averaged with its two neighbours
+fn smoothed_magnitudes(spectrum: &[Complex<f32>]) -> Vec<f32> {
+    let magnitudes: Vec<f32> = spectrum.iter().map(|c| c.norm()).collect();
+    (0..magnitudes.len())
+        .map(|bin| {
+            let start = bin.saturating_sub(1);
+            let end = (bin + 2).min(magnitudes.len());
+            magnitudes[start..end].iter().sum::<f32>() / (end - start) as f32
+        })
+        .collect()
+}
+
+/// Number of leading frames before speech starts (frames until one rises
+/// `SPEECH_ONSET_DB` above the quietest of the first frames), capped to
+/// `NOISE_PROFILE_MAX_FRAMES`. Returns 0 if no onset is found.
+fn pre_speech_frames(frame_energy: &[f32]) -> usize {
+    let floor = frame_energy
+        .iter()
+        .take(NOISE_PROFILE_MIN_FRAMES)
+        .cloned()
+        .fold(f32::INFINITY, f32::min)
+        .max(1e-12);
+    let onset = floor * 10f32.powf(SPEECH_ONSET_DB / 10.0);
+
+    frame_energy
+        .iter()
+        .position(|&e| e > onset)
+        .map_or(0, |frame| frame.min(NOISE_PROFILE_MAX_FRAMES))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, amplitude: f32, samples: usize) -> Vec<f32> {
+        (0..samples)
+            .map(|i| (i as f32 * frequency * 2.0 * std::f32::consts::PI / 16000.0).sin() * amplitude)
+            .collect()
+    }
+
+    /// Deterministic white noise in [-amplitude, amplitude]
+    fn noise(amplitude: f32, samples: usize) -> Vec<f32> {
+        let mut state = 0x1234_5678u32;
+        (0..samples)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_high_pass_removes_rumble_keeps_voice() {
+        let mut rumble = sine(20.0, 0.5, 16000);
+        high_pass_filter(&mut rumble, 80.0, 16000);
+        assert!(to_dbfs(rms(&rumble[8000..])) - to_dbfs(rms(&sine(20.0, 0.5, 8000))) < -20.0);
+
+        let mut voice = sine(1000.0, 0.5, 16000);
+        high_pass_filter(&mut voice, 80.0, 16000);
+        assert!((rms(&voice[8000..]) / rms(&sine(1000.0, 0.5, 8000)) - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_loudness_ignores_a_single_knock() {
+        // Quiet speech-like tone with one loud click in the middle
+        let mut audio = sine(300.0, 0.02, 48000);
+        for sample in &mut audio[24000..24040] {
+            *sample = 0.9;
+        }
+
+        normalize_loudness(&mut audio, -20.0, 16000);
+
+        // The tone is brought near the target instead of staying quiet
+        let tone_level = to_dbfs(rms(&audio[..16000]));
+        assert!((tone_level + 20.0).abs() < 1.5, "tone at {:.1} dBFS", tone_level);
+        // And the knock is limited
+        assert!(audio.iter().all(|s| s.abs() <= PEAK_CEILING + 1e-6));
+    }
+
+    #[test]
+    fn test_loudness_skips_silence() {
+        let mut silence = vec![0.0f32; 16000];
+        normalize_loudness(&mut silence, -20.0, 16000);
+        assert!(silence.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_limiter_respects_ceiling() {
+        let mut audio = sine(440.0, 2.0, 16000);
+        limit_peaks(&mut audio, 0.5, 16000);
+        assert!(audio.iter().all(|s| s.abs() <= 0.5 + 1e-6));
+    }
+
+    #[test]
+    fn test_noise_suppression_uses_pre_speech_profile() {
+        // 0.5s of noise, then noise + tone
+        let mut audio = noise(0.01, 32000);
+        let tone = sine(1000.0, 0.3, 24000);
+        for (sample, t) in audio[8000..].iter_mut().zip(&tone) {
+            *sample += t;
+        }
+        let noise_before = rms(&audio[1000..7000]);
+        let speech_before = rms(&audio[10000..30000]);
+
+        suppress_noise(&mut audio);
+
+        assert!(to_dbfs(rms(&audio[1000..7000])) - to_dbfs(noise_before) < -12.0);
+        assert!((to_dbfs(rms(&audio[10000..30000])) - to_dbfs(speech_before)).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_noise_suppression_without_pre_speech_region() {
+        let mut audio = sine(1000.0, 0.3, 16000);
+        let original = audio.clone();
+        suppress_noise(&mut audio);
+        assert_eq!(audio, original);
+    }
+}
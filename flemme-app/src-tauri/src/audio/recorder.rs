@@ -1,22 +1,62 @@
 // AudioRecorder - handles audio recording from microphone
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Stream, StreamConfig};
+use cpal::{Device, FromSample, Sample, SampleFormat, SampleRate, SizedSample, Stream, StreamConfig, SupportedStreamConfig};
+use super::capture::{CaptureInput, CaptureOptions, CapturePipeline, CapturedAudio, SampleListener};
+use super::level::{LevelListener, LevelSummary};
+use super::processing;
+use super::resampler::{StreamingResampler, TARGET_SAMPLE_RATE};
+use super::source::{AudioChannel, SpooledRecording};
+use super::spool::SpoolSettings;
+use super::system::{self, AudioDeviceInfo, DeviceKind};
+use crate::config::PreprocessingSettings;
 use std::sync::{Arc, Mutex};
-use std::cell::RefCell;
-use rubato::{FftFixedIn, Resampler};
+use std::time::{Duration, Instant};
+
+/// "Keep microphone warm" configuration: the stream stays open between
+/// recordings and the last `pre_roll_ms` are prepended to each recording
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeepWarm {
+    pub pre_roll_ms: u32,
+    /// Release the device after this long without recording
+    pub idle_timeout: Duration,
+}
 
 pub struct AudioRecorder {
     device: Device,
+    device_name: String,
+    // Output device recorded in loopback mode (system audio on Windows)
+    loopback: bool,
+    // PulseAudio source selected when opening the ALSA `pulse` device (system audio on Linux)
+    pulse_source: Option<String>,
     config: StreamConfig,
+    sample_format: SampleFormat,
+    // Record a single device channel instead of downmixing all of them
+    input_channel: Option<u16>,
     stream: Option<Stream>,
-    buffer: Arc<Mutex<Vec<f32>>>,
+    // Running resampling worker, present while the stream is open
+    pipeline: Option<CapturePipeline>,
+    // Resampler kept between streams (taken by the pipeline while the stream is open)
+    resampler: Option<StreamingResampler>,
+    recording: bool,
+    keep_warm: Option<KeepWarm>,
+    last_activity: Instant,
+    preprocessing: PreprocessingSettings,
+    // Long recordings are moved to a WAV file instead of memory
+    spool: Option<SpoolSettings>,
+    // Receives the live input level while recording
+    level_listener: Option<LevelListener>,
+    // Receives the 16kHz recording as it grows
+    sample_listener: Option<SampleListener>,
+    last_level_summary: Option<LevelSummary>,
+    // Last error reported by the cpal error callback (e.g. device unplugged)
+    stream_error: Arc<Mutex<Option<String>>>,
     sample_rate: u32,
-    resampler: Option<RefCell<FftFixedIn<f32>>>,
 }
 
 impl AudioRecorder {
-    /// List all available input devices
-    pub fn list_devices() -> Result<Vec<(String, bool)>, String> {
+    /// List all available input devices, including the monitor/loopback
+    /// devices that capture system audio
+    pub fn list_devices() -> Result<Vec<AudioDeviceInfo>, String> {
         let host = cpal::default_host();
         let default_device = host.default_input_device();
         let default_name = default_device
@@ -31,9 +71,15 @@ impl AudioRecorder {
         for device in devices {
             if let Ok(name) = device.name() {
                 let is_default = Some(&name) == default_name.as_ref();
-                result.push((name, is_default));
+                let kind = if system::is_monitor_name(&name) {
+                    DeviceKind::Monitor
+                } else {
+                    DeviceKind::Microphone
+                };
+                result.push(AudioDeviceInfo { name, is_default, kind });
             }
         }
+        result.extend(system::list_monitor_devices());
 
         if result.is_empty() {
             return Err("No input devices found".to_string());
@@ -50,44 +96,7 @@ impl AudioRecorder {
             .default_input_device()
             .ok_or("No microphone found")?;
 
-        let config = device
-            .default_input_config()
-            .map_err(|e| format!("Config error: {}", e))?;
-
-        let stream_config: StreamConfig = config.into();
-
-        // Create resampler if device sample rate differs from 16kHz
-        let resampler = if stream_config.sample_rate.0 != 16000 {
-            let chunk_size = 1024; // Process in 1024-sample chunks for good quality/performance balance
-            let sub_chunks = 2; // Number of subchunks for FFT processing
-            match FftFixedIn::<f32>::new(
-                stream_config.sample_rate.0 as usize,
-                16000,
-                chunk_size,
-                sub_chunks,
-                1, // mono (number of channels)
-            ) {
-                Ok(r) => {
-                    println!("Created high-quality FFT resampler: {} Hz -> 16000 Hz", stream_config.sample_rate.0);
-                    Some(RefCell::new(r))
-                }
-                Err(e) => {
-                    eprintln!("Failed to create resampler: {}, will use fallback", e);
-                    None
-                }
-            }
-        } else {
-            None
-        };
-
-        Ok(Self {
-            device,
-            config: stream_config,
-            stream: None,
-            buffer: Arc::new(Mutex::new(Vec::new())),
-            sample_rate: 16000, // Whisper requires 16kHz
-            resampler,
-        })
+        Self::from_device(device)
     }
 
     /// Create a new recorder with a specific device by name
@@ -112,195 +121,512 @@ impl AudioRecorder {
             .next()
             .ok_or_else(|| format!("Device '{}' not found", device_name))?;
 
-        let config = device
-            .default_input_config()
-            .map_err(|e| format!("Config error: {}", e))?;
-
-        let stream_config: StreamConfig = config.into();
-
-        // Create resampler if device sample rate differs from 16kHz
-        let resampler = if stream_config.sample_rate.0 != 16000 {
-            let chunk_size = 1024; // Process in 1024-sample chunks for good quality/performance balance
-            let sub_chunks = 2; // Number of subchunks for FFT processing
-            match FftFixedIn::<f32>::new(
-                stream_config.sample_rate.0 as usize,
-                16000,
-                chunk_size,
-                sub_chunks,
-                1, // mono (number of channels)
-            ) {
-                Ok(r) => {
-                    println!("Created high-quality FFT resampler: {} Hz -> 16000 Hz", stream_config.sample_rate.0);
-                    Some(RefCell::new(r))
-                }
-                Err(e) => {
-                    eprintln!("Failed to create resampler: {}, will use fallback", e);
-                    None
-                }
+        Self::from_device(device)
+    }
+
+    /// Create a recorder with the first available device of an ordered
+    /// preference list, falling back to the default microphone
+    pub fn new_with_preferred_devices(device_names: &[String]) -> Result<Self, String> {
+        for device_name in device_names {
+            match Self::new_with_device(device_name) {
+                Ok(recorder) => return Ok(recorder),
+                Err(e) => eprintln!("Audio device '{}' unavailable: {}", device_name, e),
             }
-        } else {
-            None
-        };
+        }
+
+        if !device_names.is_empty() {
+            println!("No preferred audio device available, using default device");
+        }
+        Self::new()
+    }
+
+    /// Create a recorder capturing what the machine plays (monitor or loopback
+    /// device), `None` meaning the monitor of the default output
+    pub fn new_system_audio(monitor_name: Option<&str>) -> Result<Self, String> {
+        let monitor = system::open_monitor(monitor_name)?;
+        println!("Using system audio device: {}", monitor.name);
+        Self::from_parts(monitor.device, monitor.name, monitor.loopback, monitor.pulse_source)
+    }
+
+    fn from_device(device: Device) -> Result<Self, String> {
+        let device_name = device.name().unwrap_or_else(|_| "Unknown device".to_string());
+        Self::from_parts(device, device_name, false, None)
+    }
+
+    fn from_parts(
+        device: Device,
+        device_name: String,
+        loopback: bool,
+        pulse_source: Option<String>,
+    ) -> Result<Self, String> {
+        let (stream_config, sample_format) = Self::negotiate_config(&device, !loopback, loopback)?;
+
+        println!(
+            "Negotiated input config: {} channels, {} Hz, {:?}",
+            stream_config.channels, stream_config.sample_rate.0, sample_format
+        );
+
+        // Resampler runs while recording; passthrough if the device is already at 16kHz
+        let resampler = StreamingResampler::new(stream_config.sample_rate.0)?;
 
         Ok(Self {
             device,
+            device_name,
+            loopback,
+            pulse_source,
             config: stream_config,
+            sample_format,
+            input_channel: None,
             stream: None,
-            buffer: Arc::new(Mutex::new(Vec::new())),
-            sample_rate: 16000, // Whisper requires 16kHz
-            resampler,
+            pipeline: None,
+            resampler: Some(resampler),
+            recording: false,
+            keep_warm: None,
+            last_activity: Instant::now(),
+            preprocessing: PreprocessingSettings::default(),
+            spool: None,
+            sample_listener: None,
+            level_listener: None,
+            last_level_summary: None,
+            stream_error: Arc::new(Mutex::new(None)),
+            sample_rate: TARGET_SAMPLE_RATE, // Whisper requires 16kHz
         })
     }
 
-    /// Start recording audio
-    pub fn start_recording(&mut self) -> Result<(), String> {
-        let buffer = Arc::clone(&self.buffer);
-        let channels = self.config.channels;
+    /// Pick the stream configuration for a device
+    ///
+    /// Prefers a native 16kHz mono config (no resampling or downmix needed) when
+    /// `prefer_native_mono` is set, otherwise uses the device default config
+    /// whatever its sample format. Loopback devices use their output config.
+    fn negotiate_config(
+        device: &Device,
+        prefer_native_mono: bool,
+        loopback: bool,
+    ) -> Result<(StreamConfig, SampleFormat), String> {
+        if prefer_native_mono {
+            if let Some(config) = Self::find_native_config(device) {
+                return Ok((config.config(), config.sample_format()));
+            }
+        }
 
-        println!("Recording with {} channels at {} Hz", channels, self.config.sample_rate.0);
+        let config = if loopback {
+            device.default_output_config()
+        } else {
+            device.default_input_config()
+        }
+        .map_err(|e| format!("Config error: {}", e))?;
 
-        // Clear the buffer
-        buffer.lock().unwrap().clear();
+        if !Self::is_supported_format(config.sample_format()) {
+            return Err(format!("Unsupported sample format: {:?}", config.sample_format()));
+        }
 
-        let stream = self.device
-            .build_input_stream(
-                &self.config,
-                move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                    // Callback called for each audio chunk
-                    let mut buf = buffer.lock().unwrap();
-
-                    // Convert to mono if stereo (average both channels for maximum info preservation)
-                    if channels == 2 {
-                        for i in (0..data.len()).step_by(2) {
-                            let left = data[i];
-                            let right = data[i + 1];
-                            buf.push((left + right) / 2.0);
-                        }
-                    } else {
-                        buf.extend_from_slice(data);
-                    }
-                },
-                |err| eprintln!("Stream error: {}", err),
-                None,
-            )
-            .map_err(|e| format!("Failed to create stream: {}", e))?;
+        Ok((config.config(), config.sample_format()))
+    }
 
-        stream.play().map_err(|e| format!("Failed to play stream: {}", e))?;
+    /// Find a 16kHz mono config among the device's supported configs
+    fn find_native_config(device: &Device) -> Option<SupportedStreamConfig> {
+        device
+            .supported_input_configs()
+            .ok()?
+            .filter(|c| {
+                c.channels() == 1
+                    && c.min_sample_rate().0 <= TARGET_SAMPLE_RATE
+                    && c.max_sample_rate().0 >= TARGET_SAMPLE_RATE
+                    && Self::is_supported_format(c.sample_format())
+            })
+            // Prefer float, then the widest integer format
+            .max_by_key(|c| (c.sample_format().is_float(), c.sample_format().sample_size()))
+            .map(|range| range.with_sample_rate(SampleRate(TARGET_SAMPLE_RATE)))
+    }
 
-        self.stream = Some(stream);
+    fn is_supported_format(format: SampleFormat) -> bool {
+        matches!(
+            format,
+            SampleFormat::I8
+                | SampleFormat::I16
+                | SampleFormat::I32
+                | SampleFormat::I64
+                | SampleFormat::U8
+                | SampleFormat::U16
+                | SampleFormat::U32
+                | SampleFormat::U64
+                | SampleFormat::F32
+                | SampleFormat::F64
+        )
+    }
+
+    /// Record a single device channel (0-based) instead of downmixing all channels
+    ///
+    /// If the current config has too few channels (e.g. native 16kHz mono was
+    /// picked), the device default config is negotiated instead.
+    pub fn set_input_channel(&mut self, channel: Option<u16>) -> Result<(), String> {
+        if self.recording {
+            return Err("Cannot change input channel while recording".to_string());
+        }
+        if channel == self.input_channel {
+            return Ok(());
+        }
+        // A warm stream was built for the previous channel
+        self.close_stream();
+
+        if let Some(ch) = channel {
+            if ch >= self.config.channels {
+                let (config, sample_format) = Self::negotiate_config(&self.device, false, self.loopback)?;
+                if ch >= config.channels {
+                    return Err(format!(
+                        "Invalid input channel {}: device has {} channels",
+                        ch, config.channels
+                    ));
+                }
+                if config.sample_rate != self.config.sample_rate {
+                    self.resampler = Some(StreamingResampler::new(config.sample_rate.0)?);
+                }
+                self.config = config;
+                self.sample_format = sample_format;
+            }
+        }
+
+        self.input_channel = channel;
         Ok(())
     }
 
-    /// Stop recording and return the audio samples
-    pub fn stop_recording(&mut self) -> Result<Vec<f32>, String> {
-        if let Some(stream) = self.stream.take() {
-            drop(stream); // Stop the stream
+    /// Keep the microphone open between recordings with a rolling pre-roll
+    /// buffer (`None` opens the device only while recording)
+    pub fn set_keep_warm(&mut self, keep_warm: Option<KeepWarm>) {
+        if keep_warm == self.keep_warm {
+            return;
         }
+        self.keep_warm = keep_warm;
+
+        // Reopened with the new pre-roll length on next use
+        if !self.recording {
+            self.close_stream();
+        }
+    }
+
+    /// Preprocessing chain applied to each recording
+    pub fn set_preprocessing(&mut self, preprocessing: PreprocessingSettings) {
+        self.preprocessing = preprocessing;
+    }
 
-        let buffer = self.buffer.lock().unwrap();
-        let audio = buffer.clone();
+    /// Spool recordings longer than `SpoolSettings::after_samples` to disk
+    ///
+    /// Applies from the next time the stream is opened.
+    pub fn set_spool(&mut self, spool: Option<SpoolSettings>) {
+        self.spool = spool;
+    }
 
-        println!("Audio recorded: {} samples at {} Hz", audio.len(), self.config.sample_rate.0);
+    /// Receive the live input level (RMS/peak every 50ms, throttled) while recording
+    ///
+    /// Applies from the next time the stream is opened.
+    pub fn set_level_listener(&mut self, listener: Option<LevelListener>) {
+        self.level_listener = listener;
+    }
 
-        // Remove DC offset (improves VAD quality)
-        let mut audio = audio;
-        Self::remove_dc_offset(&mut audio);
+    /// Receive the 16kHz audio of each recording while it is captured
+    ///
+    /// Applies from the next time the stream is opened.
+    pub fn set_sample_listener(&mut self, listener: Option<SampleListener>) {
+        self.sample_listener = listener;
+    }
 
-        // Normalize audio to full dynamic range (critical for consistent VAD performance)
-        Self::normalize_peak(&mut audio);
+    /// Open the device ahead of the next recording when keep-warm is enabled,
+    /// so the pre-roll buffer starts filling
+    pub fn warm_up(&mut self) -> Result<(), String> {
+        if self.keep_warm.is_some() && self.stream.is_none() {
+            self.open_stream(false)?;
+            self.last_activity = Instant::now();
+            println!("Microphone kept warm");
+        }
+        Ok(())
+    }
 
-        // Resample to 16kHz if needed using high-quality FFT resampler
-        if self.config.sample_rate.0 != 16000 {
-            println!("Resampling from {} Hz to 16000 Hz using FFT resampler", self.config.sample_rate.0);
-            let resampled = self.resample_with_rubato(&audio)?;
-            println!("Resampled to {} samples", resampled.len());
-            Ok(resampled)
-        } else {
-            Ok(audio)
+    /// Release a warm device that has not been used for the idle timeout
+    pub fn release_if_idle(&mut self) {
+        if let Some(keep_warm) = self.keep_warm {
+            if !self.recording
+                && self.stream.is_some()
+                && self.last_activity.elapsed() >= keep_warm.idle_timeout
+            {
+                println!("Microphone idle for {:?}, releasing device", keep_warm.idle_timeout);
+                self.close_stream();
+            }
         }
     }
 
-    /// Remove DC offset from audio signal
-    fn remove_dc_offset(samples: &mut [f32]) {
-        if samples.is_empty() {
-            return;
+    /// Start recording audio
+    ///
+    /// Frames are converted to f32 and downmixed in the stream callback, then
+    /// resampled to 16kHz by a worker thread as they arrive, so stopping only
+    /// has to flush the tail. When the microphone is kept warm the stream is
+    /// already open and the pre-roll is prepended to the recording.
+    pub fn start_recording(&mut self) -> Result<(), String> {
+        if self.recording {
+            return Err("Already recording".to_string());
         }
-        let mean: f32 = samples.iter().sum::<f32>() / samples.len() as f32;
-        for sample in samples.iter_mut() {
-            *sample -= mean;
+
+        // A warm stream released while idle is opened warm again
+        self.warm_up()?;
+        match self.pipeline.as_ref() {
+            Some(pipeline) if self.stream.is_some() => pipeline.begin()?,
+            _ => self.open_stream(true)?,
         }
+
+        println!(
+            "Recording with {} channels at {} Hz ({:?}, channel: {})",
+            self.config.channels,
+            self.config.sample_rate.0,
+            self.sample_format,
+            self.input_channel.map_or("mix".to_string(), |c| c.to_string())
+        );
+        self.recording = true;
+        Ok(())
     }
 
-    /// Normalize audio to use full dynamic range (-1.0 to 1.0)
-    /// Uses peak normalization to ensure consistent amplitude for VAD
-    fn normalize_peak(samples: &mut [f32]) {
-        if samples.is_empty() {
-            return;
+    /// Open the input stream and its capture pipeline, optionally recording
+    /// from the very first frame
+    fn open_stream(&mut self, begin_recording: bool) -> Result<(), String> {
+        let resampler = match self.resampler.take() {
+            Some(r) => r,
+            None => StreamingResampler::new(self.config.sample_rate.0)?,
+        };
+        let pre_roll_samples = self.keep_warm.map_or(0, |k| {
+            k.pre_roll_ms as usize * self.config.sample_rate.0 as usize / 1000
+        });
+        let options = CaptureOptions {
+            pre_roll_samples,
+            level_listener: self.level_listener.clone(),
+            sample_listener: self.sample_listener.clone(),
+            spool: self.spool.clone(),
+        };
+        let (pipeline, input) = CapturePipeline::start(resampler, options);
+
+        if begin_recording {
+            if let Err(e) = pipeline.begin() {
+                self.resampler = pipeline.shutdown().ok();
+                return Err(e);
+            }
         }
 
-        // Find the peak (maximum absolute value)
-        let peak = samples
-            .iter()
-            .map(|s| s.abs())
-            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-            .unwrap_or(1.0);
-
-        // Only normalize if peak is significant (avoid amplifying pure noise)
-        if peak > 0.001 {
-            // Normalize to 95% of full scale to avoid potential clipping
-            let factor = 0.95 / peak;
-            for sample in samples.iter_mut() {
-                *sample *= factor;
+        let stream_result = match self.sample_format {
+            SampleFormat::I8 => self.build_stream::<i8>(input),
+            SampleFormat::I16 => self.build_stream::<i16>(input),
+            SampleFormat::I32 => self.build_stream::<i32>(input),
+            SampleFormat::I64 => self.build_stream::<i64>(input),
+            SampleFormat::U8 => self.build_stream::<u8>(input),
+            SampleFormat::U16 => self.build_stream::<u16>(input),
+            SampleFormat::U32 => self.build_stream::<u32>(input),
+            SampleFormat::U64 => self.build_stream::<u64>(input),
+            SampleFormat::F32 => self.build_stream::<f32>(input),
+            SampleFormat::F64 => self.build_stream::<f64>(input),
+            format => Err(format!("Unsupported sample format: {:?}", format)),
+        };
+
+        let stream = match stream_result {
+            Ok(stream) => stream,
+            Err(e) => {
+                self.resampler = pipeline.shutdown().ok();
+                return Err(e);
+            }
+        };
+
+        if let Err(e) = stream.play() {
+            drop(stream);
+            self.resampler = pipeline.shutdown().ok();
+            return Err(format!("Failed to play stream: {}", e));
+        }
+
+        if let Some(source) = &self.pulse_source {
+            if let Err(e) = system::move_to_pulse_source(source) {
+                drop(stream);
+                self.resampler = pipeline.shutdown().ok();
+                return Err(e);
+            }
+        }
+
+        self.stream = Some(stream);
+        self.pipeline = Some(pipeline);
+        Ok(())
+    }
+
+    /// Close the input stream and stop its capture pipeline
+    fn close_stream(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            drop(stream); // Stop the stream
+        }
+        if let Some(pipeline) = self.pipeline.take() {
+            match pipeline.shutdown() {
+                Ok(resampler) => self.resampler = Some(resampler),
+                Err(e) => eprintln!("Failed to stop capture pipeline: {}", e),
+            }
+        }
+    }
+
+    /// Build the input stream for sample type `T`, converting every frame to a
+    /// single f32 sample before handing it to the capture pipeline
+    fn build_stream<T>(&self, mut input: CaptureInput) -> Result<Stream, String>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let channels = self.config.channels as usize;
+        let input_channel = self.input_channel.map(|c| c as usize);
+        let stream_error = self.stream_error.clone();
+
+        self.device
+            .build_input_stream(
+                &self.config,
+                move |data: &[T], _: &cpal::InputCallbackInfo| {
+                    // Callback called for each audio chunk: queue and meter every frame
+                    for frame in data.chunks_exact(channels) {
+                        input.push(Self::frame_to_mono(frame, input_channel));
+                    }
+                },
+                move |err| {
+                    eprintln!("Stream error: {}", err);
+                    if let Ok(mut slot) = stream_error.lock() {
+                        slot.get_or_insert_with(|| err.to_string());
+                    }
+                },
+                None,
+            )
+            .map_err(|e| format!("Failed to create stream: {}", e))
+    }
+
+    /// Convert one interleaved frame to a mono f32 sample: either the selected
+    /// channel, or the average of all channels for maximum info preservation
+    fn frame_to_mono<T>(frame: &[T], input_channel: Option<usize>) -> f32
+    where
+        T: Sample,
+        f32: FromSample<T>,
+    {
+        match input_channel {
+            Some(ch) => frame[ch].to_sample::<f32>(),
+            None => {
+                let sum: f32 = frame.iter().map(|s| s.to_sample::<f32>()).sum();
+                sum / frame.len() as f32
             }
-            println!("Audio normalized: peak={:.4} -> normalized with factor={:.2}", peak, factor);
-        } else {
-            println!("Audio peak too low ({:.6}), skipping normalization (likely silence)", peak);
         }
     }
 
-    /// High-quality resampling using Rubato FFT
-    fn resample_with_rubato(&self, input: &[f32]) -> Result<Vec<f32>, String> {
-        if let Some(ref resampler_cell) = self.resampler {
-            let mut resampler = resampler_cell.borrow_mut();
-            let chunk_size = resampler.input_frames_next();
-            let mut output = Vec::new();
+    /// Stop recording and return the audio samples (16kHz mono)
+    pub fn stop_recording(&mut self) -> Result<Vec<f32>, String> {
+        let mut channel = self.stop_recording_channel()?;
+        channel.load()?;
+        Ok(channel.samples)
+    }
 
-            // Process audio in chunks
-            let mut pos = 0;
-            while pos < input.len() {
-                let end = (pos + chunk_size).min(input.len());
-                let chunk = &input[pos..end];
+    /// Stop recording, leaving a spooled recording on disk
+    pub fn stop_recording_channel(&mut self) -> Result<AudioChannel, String> {
+        let (audio, levels) = self.end_recording(CapturePipeline::end)?;
 
-                // Rubato requires Vec<Vec<f32>> format (one vec per channel)
-                let mut chunk_vec = vec![chunk.to_vec()];
+        if let Some(warning) = levels.warning() {
+            println!("Recording level warning: {}", warning);
+        }
+        self.last_level_summary = Some(levels);
 
-                // Handle partial chunks by padding with zeros if needed
-                if chunk.len() < chunk_size {
-                    chunk_vec[0].resize(chunk_size, 0.0);
-                }
+        match audio {
+            CapturedAudio::Memory(mut audio) => {
+                println!("Audio recorded: {} samples at {} Hz", audio.len(), self.sample_rate);
+                processing::prepare_for_transcription(&mut audio, &self.preprocessing);
+                Ok(AudioChannel::mono(audio))
+            }
+            CapturedAudio::Spooled { path, samples } => {
+                println!("Audio recorded: {} samples at {} Hz in {:?}", samples, self.sample_rate, path);
+                Ok(AudioChannel {
+                    label: None,
+                    samples: Vec::new(),
+                    spooled: Some(SpooledRecording {
+                        path,
+                        samples,
+                        preprocessing: self.preprocessing.clone(),
+                    }),
+                })
+            }
+        }
+    }
 
-                let resampled = resampler
-                    .process(&chunk_vec, None)
-                    .map_err(|e| format!("Resample error: {}", e))?;
+    /// Stop recording and throw the audio away (the sample listener got all of it)
+    pub fn cancel_recording(&mut self) -> Result<(), String> {
+        self.end_recording(CapturePipeline::cancel)
+    }
 
-                output.extend_from_slice(&resampled[0]);
-                pos = end;
+    /// Stop the stream unless it is kept warm and let `end` close the recording
+    fn end_recording<T>(&mut self, end: impl FnOnce(&CapturePipeline) -> Result<T, String>) -> Result<T, String> {
+        if !self.recording {
+            return Err("Not recording".to_string());
+        }
+        self.recording = false;
+        self.last_activity = Instant::now();
+
+        // Without keep-warm, stop the stream first so every captured frame is queued
+        if self.keep_warm.is_none() {
+            if let Some(stream) = self.stream.take() {
+                drop(stream); // Stop the stream
             }
+        }
 
-            Ok(output)
-        } else {
-            // Fallback to simple copy if no resampler
-            Ok(input.to_vec())
+        // Only the resampler tail is left to process at this point
+        let result = match self.pipeline.as_ref() {
+            Some(pipeline) => end(pipeline),
+            None => Err("Capture pipeline not running".to_string()),
+        };
+
+        if self.keep_warm.is_none() {
+            self.close_stream();
         }
+
+        result
     }
 
     /// Check if currently recording
     pub fn is_recording(&self) -> bool {
-        self.stream.is_some()
+        self.recording
+    }
+
+    /// Name of the input device
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+
+    /// Level statistics of the last recording
+    pub fn last_level_summary(&self) -> Option<LevelSummary> {
+        self.last_level_summary
+    }
+
+    /// Take the error reported by the stream since the last call, if any.
+    /// The stream is unusable after an error and the recorder should be rebuilt.
+    pub fn take_stream_error(&mut self) -> Option<String> {
+        self.stream_error.lock().ok().and_then(|mut slot| slot.take())
     }
 
     /// Get the sample rate (16kHz for Whisper)
     pub fn sample_rate(&self) -> u32 {
         self.sample_rate
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_to_mono_downmixes_all_channels() {
+        let frame = [0.2f32, 0.4, 0.6, 0.8];
+        let mono = AudioRecorder::frame_to_mono(&frame, None);
+        assert!((mono - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_frame_to_mono_selects_channel() {
+        let frame = [0i16, i16::MIN, 0, 0, 0, 0];
+        assert_eq!(AudioRecorder::frame_to_mono(&frame, Some(1)), -1.0);
+    }
+
+    #[test]
+    fn test_frame_to_mono_converts_unsigned() {
+        // u16 midpoint is silence
+        let frame = [32768u16, 32768u16];
+        assert!(AudioRecorder::frame_to_mono(&frame, None).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,226 @@
+// StreamingResampler - incremental resampling to 16kHz while audio is being captured
+use rubato::{FftFixedIn, Resampler};
+
+/// Sample rate expected by Whisper and Silero VAD
+pub const TARGET_SAMPLE_RATE: u32 = 16000;
+
+/// Wraps an `FftFixedIn` so audio can be fed in arbitrary-sized blocks as it arrives
+/// from the device, instead of resampling the whole recording after stop.
+///
+/// The resampler delay is compensated: the first `output_delay()` frames are dropped
+/// and the tail is flushed with zeros, so the output is aligned with the input and
+/// has exactly `input_len * 16000 / input_rate` frames.
+pub struct StreamingResampler {
+    resampler: Option<FftFixedIn<f32>>,
+    input_rate: u32,
+    // Input frames waiting for a full resampler chunk
+    pending: Vec<f32>,
+    output_buffer: Vec<Vec<f32>>,
+    // Output frames still to discard to compensate the resampler delay
+    delay_remaining: usize,
+    input_frames: usize,
+    output_frames: usize,
+}
+
+impl StreamingResampler {
+    /// Create a resampler from `input_rate` to 16kHz (passthrough if already 16kHz)
+    pub fn new(input_rate: u32) -> Result<Self, String> {
+        let resampler = if input_rate != TARGET_SAMPLE_RATE {
+            let chunk_size = 1024; // Process in 1024-sample chunks for good quality/performance balance
+            let sub_chunks = 2; // Number of subchunks for FFT processing
+            let r = FftFixedIn::<f32>::new(
+                input_rate as usize,
+                TARGET_SAMPLE_RATE as usize,
+                chunk_size,
+                sub_chunks,
+                1, // mono (number of channels)
+            )
+            .map_err(|e| format!("Failed to create resampler: {}", e))?;
+            println!("Created high-quality FFT resampler: {} Hz -> {} Hz", input_rate, TARGET_SAMPLE_RATE);
+            Some(r)
+        } else {
+            None
+        };
+
+        let output_buffer = resampler
+            .as_ref()
+            .map(|r| r.output_buffer_allocate(true))
+            .unwrap_or_default();
+        let delay_remaining = resampler.as_ref().map_or(0, |r| r.output_delay());
+
+        Ok(Self {
+            resampler,
+            input_rate,
+            pending: Vec::new(),
+            output_buffer,
+            delay_remaining,
+            input_frames: 0,
+            output_frames: 0,
+        })
+    }
+
+    /// Input sample rate of this resampler
+    pub fn input_rate(&self) -> u32 {
+        self.input_rate
+    }
+
+    /// Feed mono samples at the input rate, appending resampled 16kHz samples to `output`
+    pub fn process(&mut self, samples: &[f32], output: &mut Vec<f32>) -> Result<(), String> {
+        self.input_frames += samples.len();
+
+        let Some(resampler) = self.resampler.as_mut() else {
+            output.extend_from_slice(samples);
+            self.output_frames += samples.len();
+            return Ok(());
+        };
+
+        self.pending.extend_from_slice(samples);
+
+        let mut consumed = 0;
+        loop {
+            let needed = resampler.input_frames_next();
+            if self.pending.len() - consumed < needed {
+                break;
+            }
+
+            let chunk = [&self.pending[consumed..consumed + needed]];
+            let (frames_in, frames_out) = resampler
+                .process_into_buffer(&chunk, &mut self.output_buffer, None)
+                .map_err(|e| format!("Resample error: {}", e))?;
+            consumed += frames_in;
+
+            Self::emit(
+                &self.output_buffer[0][..frames_out],
+                &mut self.delay_remaining,
+                &mut self.output_frames,
+                output,
+            );
+        }
+
+        self.pending.drain(..consumed);
+        Ok(())
+    }
+
+    /// Push the remaining samples through the resampler and trim the output to the
+    /// exact expected length. Call once at the end of a recording.
+    pub fn flush(&mut self, output: &mut Vec<f32>) -> Result<(), String> {
+        let Some(resampler) = self.resampler.as_mut() else {
+            return Ok(());
+        };
+
+        let expected = (self.input_frames as u64 * TARGET_SAMPLE_RATE as u64)
+            .div_ceil(self.input_rate as u64) as usize;
+
+        // Last partial chunk (zero-padded by rubato)
+        if !self.pending.is_empty() {
+            let chunk = [self.pending.as_slice()];
+            let (_, frames_out) = resampler
+                .process_partial_into_buffer(Some(&chunk), &mut self.output_buffer, None)
+                .map_err(|e| format!("Resample error: {}", e))?;
+            Self::emit(
+                &self.output_buffer[0][..frames_out],
+                &mut self.delay_remaining,
+                &mut self.output_frames,
+                output,
+            );
+            self.pending.clear();
+        }
+
+        // Push out the frames still held back by the resampler delay
+        while self.output_frames < expected {
+            let (_, frames_out) = resampler
+                .process_partial_into_buffer(None::<&[&[f32]]>, &mut self.output_buffer, None)
+                .map_err(|e| format!("Resample error: {}", e))?;
+            if frames_out == 0 {
+                break;
+            }
+            Self::emit(
+                &self.output_buffer[0][..frames_out],
+                &mut self.delay_remaining,
+                &mut self.output_frames,
+                output,
+            );
+        }
+
+        // Drop the zero padding that went past the real end of the signal
+        if self.output_frames > expected {
+            let excess = (self.output_frames - expected).min(output.len());
+            output.truncate(output.len() - excess);
+            self.output_frames = expected;
+        }
+
+        Ok(())
+    }
+
+    /// Clear internal state so the next recording starts from silence
+    pub fn reset(&mut self) {
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.reset();
+            self.delay_remaining = resampler.output_delay();
+        }
+        self.pending.clear();
+        self.input_frames = 0;
+        self.output_frames = 0;
+    }
+
+    fn emit(frames: &[f32], delay_remaining: &mut usize, output_frames: &mut usize, output: &mut Vec<f32>) {
+        let skip = (*delay_remaining).min(frames.len());
+        *delay_remaining -= skip;
+        output.extend_from_slice(&frames[skip..]);
+        *output_frames += frames.len() - skip;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resample_in_blocks(input_rate: u32, input: &[f32], block: usize) -> Vec<f32> {
+        let mut resampler = StreamingResampler::new(input_rate).unwrap();
+        let mut output = Vec::new();
+        for chunk in input.chunks(block) {
+            resampler.process(chunk, &mut output).unwrap();
+        }
+        resampler.flush(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_output_length_matches_ratio() {
+        let input = vec![0.0f32; 48000 + 123];
+        let output = resample_in_blocks(48000, &input, 480);
+        assert_eq!(output.len(), (48123 * 16000usize).div_ceil(48000));
+    }
+
+    #[test]
+    fn test_delay_is_compensated() {
+        // Impulse at 100ms must come out at 100ms
+        let mut input = vec![0.0f32; 44100];
+        input[4410] = 1.0;
+        let output = resample_in_blocks(44100, &input, 441);
+
+        let peak_index = output
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).unwrap())
+            .map(|(i, _)| i)
+            .unwrap();
+        assert!((peak_index as i64 - 1600).abs() <= 1, "peak at {}", peak_index);
+    }
+
+    #[test]
+    fn test_reset_between_recordings() {
+        let mut resampler = StreamingResampler::new(48000).unwrap();
+        let mut first = Vec::new();
+        resampler.process(&vec![0.5f32; 4800], &mut first).unwrap();
+        resampler.flush(&mut first).unwrap();
+
+        resampler.reset();
+        let mut second = Vec::new();
+        resampler.process(&vec![0.0f32; 4800], &mut second).unwrap();
+        resampler.flush(&mut second).unwrap();
+
+        assert_eq!(second.len(), 1600);
+        assert!(second.iter().all(|s| s.abs() < 1e-3));
+    }
+}
@@ -0,0 +1,528 @@
+// AudioSource - abstraction over where recorded audio comes from
+use super::file::AudioFileDecoder;
+use super::capture::SampleListener;
+use super::level::LevelListener;
+use super::processing;
+use super::recorder::AudioRecorder;
+use super::spool::read_spool;
+use crate::config::PreprocessingSettings;
+use std::path::PathBuf;
+
+/// A long recording left in its spool file (16kHz WAV), so it can be
+/// transcribed block by block instead of being read back whole
+#[derive(Debug, Clone)]
+pub struct SpooledRecording {
+    pub path: PathBuf,
+    pub samples: usize,
+    /// Preprocessing not applied to the file yet
+    pub preprocessing: PreprocessingSettings,
+}
+
+impl SpooledRecording {
+    /// Read the whole recording, preprocess it and delete the file
+    pub fn load(self) -> Result<Vec<f32>, String> {
+        let mut audio = read_spool(&self.path)?;
+        processing::prepare_for_transcription(&mut audio, &self.preprocessing);
+        self.delete();
+        Ok(audio)
+    }
+
+    pub fn delete(&self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            eprintln!("Failed to delete spool file {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// One channel of a recording, labeled when several are kept separate
+/// (e.g. "Me" for the microphone and "Others" for the system audio)
+#[derive(Debug, Clone)]
+pub struct AudioChannel {
+    pub label: Option<String>,
+    pub samples: Vec<f32>,
+    /// Recording still on disk, `samples` being empty until `load`
+    pub spooled: Option<SpooledRecording>,
+}
+
+impl AudioChannel {
+    /// A single unlabeled channel
+    pub fn mono(samples: Vec<f32>) -> Self {
+        Self { label: None, samples, spooled: None }
+    }
+
+    /// Number of samples, in memory or on disk
+    pub fn len(&self) -> usize {
+        self.spooled.as_ref().map_or(self.samples.len(), |spooled| spooled.samples)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read a spooled recording back into `samples`. On failure the file is
+    /// kept, to be recovered on the next start.
+    pub fn load(&mut self) -> Result<(), String> {
+        if let Some(spooled) = self.spooled.take() {
+            self.samples = spooled.load()?;
+        }
+        Ok(())
+    }
+}
+
+/// Read spooled channels back, keeping the others when one fails
+fn load_channels(channels: &mut [AudioChannel]) {
+    for channel in channels {
+        if let Err(e) = channel.load() {
+            eprintln!("Failed to read back the spooled recording: {}", e);
+        }
+    }
+}
+
+/// Mix channels down to a single recording
+pub fn mix_channels(mut channels: Vec<AudioChannel>) -> Vec<f32> {
+    load_channels(&mut channels);
+    if channels.len() == 1 {
+        return channels.remove(0).samples;
+    }
+    let tracks: Vec<&[f32]> = channels.iter().map(|c| c.samples.as_slice()).collect();
+    processing::mix(&tracks)
+}
+
+/// Append `rest` to a recording split by a device failure, channel by channel
+/// when both have the same layout (mixed down otherwise)
+pub fn concat_channels(mut first: Vec<AudioChannel>, mut rest: Vec<AudioChannel>) -> Vec<AudioChannel> {
+    load_channels(&mut first);
+    load_channels(&mut rest);
+    let same_layout = first.len() == rest.len()
+        && first.iter().zip(rest.iter()).all(|(a, b)| a.label == b.label);
+    if same_layout {
+        for (channel, more) in first.iter_mut().zip(rest) {
+            channel.samples.extend_from_slice(&more.samples);
+        }
+        return first;
+    }
+
+    let mut samples = mix_channels(first);
+    samples.extend_from_slice(&mix_channels(rest));
+    vec![AudioChannel::mono(samples)]
+}
+
+/// A source of audio for the record → VAD → Whisper → LLM pipeline
+///
+/// Every source returns 16kHz mono samples that already went through the shared
+/// preprocessing (`processing::prepare_for_transcription`).
+pub trait AudioSource {
+    /// Start capturing audio
+    fn start(&mut self) -> Result<(), String>;
+
+    /// Stop capturing and return everything captured since `start`
+    fn stop_and_drain(&mut self) -> Result<Vec<f32>, String>;
+
+    /// Check if the source is currently capturing
+    fn is_active(&self) -> bool;
+
+    /// Stop capturing and return the recording as separate channels when the
+    /// source keeps them apart (a single channel otherwise)
+    fn stop_and_drain_channels(&mut self) -> Result<Vec<AudioChannel>, String> {
+        self.stop_and_drain().map(|samples| vec![AudioChannel::mono(samples)])
+    }
+
+    /// Stop capturing and throw the recording away
+    fn cancel(&mut self) -> Result<(), String> {
+        self.stop_and_drain().map(|_| ())
+    }
+
+    /// Get ready ahead of the next `start` (e.g. open the device)
+    fn prepare(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called periodically while idle so the source can release resources
+    fn on_idle(&mut self) {}
+
+    /// Receive the live input level while recording (ignored by non-live sources)
+    fn set_level_listener(&mut self, _listener: LevelListener) {}
+
+    /// Receive the 16kHz recording as it is captured (ignored by non-live sources)
+    fn set_sample_listener(&mut self, _listener: SampleListener) {}
+
+    /// Warning about the level of the last recording (too quiet, clipped)
+    fn level_warning(&self) -> Option<String> {
+        None
+    }
+
+    /// Name of the underlying device, if any
+    fn device_name(&self) -> Option<String> {
+        None
+    }
+
+    /// Take a fatal error reported asynchronously (e.g. device unplugged).
+    /// The source must be rebuilt after an error.
+    fn take_error(&mut self) -> Option<String> {
+        None
+    }
+}
+
+impl AudioSource for AudioRecorder {
+    fn start(&mut self) -> Result<(), String> {
+        self.start_recording()
+    }
+
+    fn stop_and_drain(&mut self) -> Result<Vec<f32>, String> {
+        self.stop_recording()
+    }
+
+    fn stop_and_drain_channels(&mut self) -> Result<Vec<AudioChannel>, String> {
+        self.stop_recording_channel().map(|channel| vec![channel])
+    }
+
+    fn cancel(&mut self) -> Result<(), String> {
+        self.cancel_recording()
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_recording()
+    }
+
+    fn prepare(&mut self) -> Result<(), String> {
+        self.warm_up()
+    }
+
+    fn on_idle(&mut self) {
+        self.release_if_idle();
+    }
+
+    fn set_level_listener(&mut self, listener: LevelListener) {
+        AudioRecorder::set_level_listener(self, Some(listener));
+    }
+
+    fn set_sample_listener(&mut self, listener: SampleListener) {
+        AudioRecorder::set_sample_listener(self, Some(listener));
+    }
+
+    fn level_warning(&self) -> Option<String> {
+        self.last_level_summary().and_then(|summary| summary.warning())
+    }
+
+    fn device_name(&self) -> Option<String> {
+        Some(AudioRecorder::device_name(self).to_string())
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.take_stream_error()
+    }
+}
+
+/// Records the microphone and the system audio at the same time, either mixed
+/// together or kept as two labeled channels ("Me" and "Others")
+pub struct DualAudioSource {
+    microphone: AudioRecorder,
+    system: AudioRecorder,
+    separate: bool,
+}
+
+impl DualAudioSource {
+    pub const MICROPHONE_LABEL: &'static str = "Me";
+    pub const SYSTEM_LABEL: &'static str = "Others";
+
+    /// Combine a microphone recorder and a system audio recorder
+    pub fn new(microphone: AudioRecorder, system: AudioRecorder, separate: bool) -> Self {
+        Self {
+            microphone,
+            system,
+            separate,
+        }
+    }
+}
+
+impl AudioSource for DualAudioSource {
+    fn start(&mut self) -> Result<(), String> {
+        self.microphone.start_recording()?;
+        if let Err(e) = self.system.start_recording() {
+            let _ = self.microphone.stop_recording();
+            return Err(format!("Failed to record system audio: {}", e));
+        }
+        Ok(())
+    }
+
+    fn stop_and_drain(&mut self) -> Result<Vec<f32>, String> {
+        self.stop_and_drain_channels().map(mix_channels)
+    }
+
+    fn stop_and_drain_channels(&mut self) -> Result<Vec<AudioChannel>, String> {
+        let microphone = self.microphone.stop_recording();
+        let system = self.system.stop_recording();
+
+        // Keep what could be recorded if one of the devices failed
+        let (microphone, system) = match (microphone, system) {
+            (Ok(microphone), Ok(system)) => (microphone, system),
+            (Ok(microphone), Err(e)) => {
+                eprintln!("System audio recording failed: {}", e);
+                (microphone, Vec::new())
+            }
+            (Err(e), Ok(system)) => {
+                eprintln!("Microphone recording failed: {}", e);
+                (Vec::new(), system)
+            }
+            (Err(e), Err(_)) => return Err(e),
+        };
+
+        if self.separate {
+            Ok(vec![
+                AudioChannel {
+                    label: Some(Self::MICROPHONE_LABEL.to_string()),
+                    samples: microphone,
+                    spooled: None,
+                },
+                AudioChannel {
+                    label: Some(Self::SYSTEM_LABEL.to_string()),
+                    samples: system,
+                    spooled: None,
+                },
+            ])
+        } else {
+            Ok(vec![AudioChannel::mono(processing::mix(&[&microphone, &system]))])
+        }
+    }
+
+    fn cancel(&mut self) -> Result<(), String> {
+        let microphone = self.microphone.cancel_recording();
+        let system = self.system.cancel_recording();
+        microphone.and(system)
+    }
+
+    fn is_active(&self) -> bool {
+        self.microphone.is_recording()
+    }
+
+    fn prepare(&mut self) -> Result<(), String> {
+        self.microphone.warm_up()?;
+        self.system.warm_up()
+    }
+
+    fn on_idle(&mut self) {
+        self.microphone.release_if_idle();
+        self.system.release_if_idle();
+    }
+
+    fn set_level_listener(&mut self, listener: LevelListener) {
+        // The live level reflects the user's own voice
+        self.microphone.set_level_listener(Some(listener));
+    }
+
+    fn set_sample_listener(&mut self, listener: SampleListener) {
+        // Speech is detected on the user's side
+        self.microphone.set_sample_listener(Some(listener));
+    }
+
+    fn level_warning(&self) -> Option<String> {
+        self.microphone.last_level_summary().and_then(|summary| summary.warning())
+    }
+
+    fn device_name(&self) -> Option<String> {
+        Some(self.microphone.device_name().to_string())
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.microphone
+            .take_stream_error()
+            .or_else(|| self.system.take_stream_error())
+    }
+}
+
+/// Plays back an audio file (WAV/FLAC/OGG/MP3) as if it had been recorded
+pub struct FileAudioSource {
+    path: PathBuf,
+    preprocessing: PreprocessingSettings,
+    active: bool,
+}
+
+impl FileAudioSource {
+    /// Create a source reading the given file
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            preprocessing: PreprocessingSettings::default(),
+            active: false,
+        }
+    }
+
+    /// Use a custom preprocessing chain
+    pub fn with_preprocessing(mut self, preprocessing: PreprocessingSettings) -> Self {
+        self.preprocessing = preprocessing;
+        self
+    }
+}
+
+impl AudioSource for FileAudioSource {
+    fn start(&mut self) -> Result<(), String> {
+        if !self.path.exists() {
+            return Err(format!("Audio file not found: {:?}", self.path));
+        }
+        self.active = true;
+        Ok(())
+    }
+
+    fn stop_and_drain(&mut self) -> Result<Vec<f32>, String> {
+        if !self.active {
+            return Err("Not recording".to_string());
+        }
+        self.active = false;
+
+        let mut audio = AudioFileDecoder::decode_all(&self.path)?;
+        println!("Audio decoded: {} samples at 16000 Hz from {:?}", audio.len(), self.path);
+        processing::prepare_for_transcription(&mut audio, &self.preprocessing);
+        Ok(audio)
+    }
+
+    fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+/// Replays in-memory mono samples, e.g. test fixtures
+pub struct MemoryAudioSource {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    preprocessing: PreprocessingSettings,
+    active: bool,
+}
+
+impl MemoryAudioSource {
+    /// Create a source from mono samples at `sample_rate`
+    pub fn new(samples: Vec<f32>, sample_rate: u32) -> Self {
+        Self {
+            samples,
+            sample_rate,
+            preprocessing: PreprocessingSettings::default(),
+            active: false,
+        }
+    }
+
+    /// Use a custom preprocessing chain
+    pub fn with_preprocessing(mut self, preprocessing: PreprocessingSettings) -> Self {
+        self.preprocessing = preprocessing;
+        self
+    }
+}
+
+impl AudioSource for MemoryAudioSource {
+    fn start(&mut self) -> Result<(), String> {
+        self.active = true;
+        Ok(())
+    }
+
+    fn stop_and_drain(&mut self) -> Result<Vec<f32>, String> {
+        if !self.active {
+            return Err("Not recording".to_string());
+        }
+        self.active = false;
+
+        // Samples are kept so the same fixture can be replayed several times
+        let mut audio = processing::resample_to_16k(&self.samples, self.sample_rate)?;
+        processing::prepare_for_transcription(&mut audio, &self.preprocessing);
+        Ok(audio)
+    }
+
+    fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_source_resamples_to_16k() {
+        let tone: Vec<f32> = (0..48000)
+            .map(|i| (i as f32 * 440.0 * 2.0 * std::f32::consts::PI / 48000.0).sin() * 0.1)
+            .collect();
+        let mut source = MemoryAudioSource::new(tone, 48000);
+
+        source.start().unwrap();
+        assert!(source.is_active());
+        let audio = source.stop_and_drain().unwrap();
+        assert!(!source.is_active());
+
+        assert_eq!(audio.len(), 16000);
+        let loudness = processing::measure_loudness(&audio, 16000).unwrap();
+        assert!((loudness + 20.0).abs() < 0.5, "loudness normalized to {}", loudness);
+    }
+
+    #[test]
+    fn test_memory_source_with_peak_normalization() {
+        let tone: Vec<f32> = (0..16000)
+            .map(|i| (i as f32 * 440.0 * 2.0 * std::f32::consts::PI / 16000.0).sin() * 0.1)
+            .collect();
+        let preprocessing = PreprocessingSettings {
+            high_pass_filter: false,
+            loudness_normalization: false,
+            ..PreprocessingSettings::default()
+        };
+        let mut source = MemoryAudioSource::new(tone, 16000).with_preprocessing(preprocessing);
+
+        source.start().unwrap();
+        let audio = source.stop_and_drain().unwrap();
+        let peak = audio.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        assert!((peak - 0.95).abs() < 0.01, "peak normalized to {}", peak);
+    }
+
+    #[test]
+    fn test_drain_without_start_fails() {
+        let mut source = MemoryAudioSource::new(vec![0.0; 16000], 16000);
+        assert!(source.stop_and_drain().is_err());
+    }
+
+    #[test]
+    fn test_file_source_missing_file() {
+        let mut source = FileAudioSource::new("does-not-exist.wav");
+        assert!(source.start().is_err());
+    }
+
+    #[test]
+    fn test_channels_are_concatenated_and_mixed() {
+        let labeled = |label: &str, value: f32, len: usize| AudioChannel {
+            label: Some(label.to_string()),
+            samples: vec![value; len],
+            spooled: None,
+        };
+
+        // Same layout: appended channel by channel
+        let channels = concat_channels(
+            vec![labeled("Me", 0.1, 100), labeled("Others", 0.2, 50)],
+            vec![labeled("Me", 0.3, 100), labeled("Others", 0.4, 100)],
+        );
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].samples.len(), 200);
+        assert_eq!(channels[1].samples[49..51], [0.2, 0.4]);
+
+        // Mixing pads the shorter channel with silence
+        let mixed = mix_channels(channels);
+        assert_eq!(mixed.len(), 200);
+        assert!((mixed[0] - 0.3).abs() < 1e-6);
+        assert!((mixed[175] - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_spooled_channel_is_read_back_when_mixed() {
+        let dir = std::env::temp_dir().join(format!("flemme-source-spool-{}", std::process::id()));
+        let mut writer = super::super::spool::SpoolWriter::create(&dir).unwrap();
+        writer.append(&vec![0.1; 1600]).unwrap();
+        let (path, samples) = writer.finish().unwrap();
+        let channel = AudioChannel {
+            label: None,
+            samples: Vec::new(),
+            spooled: Some(SpooledRecording {
+                path: path.clone(),
+                samples,
+                preprocessing: PreprocessingSettings::default(),
+            }),
+        };
+        assert_eq!(channel.len(), 1600);
+
+        assert_eq!(mix_channels(vec![channel]).len(), 1600);
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,308 @@
+// Spool - writes long recordings to a WAV file so they survive a crash and stay out of memory
+use super::resampler::TARGET_SAMPLE_RATE;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Size of the header written by `SpoolWriter` (canonical 16-bit PCM WAV)
+const WAV_HEADER_SIZE: u64 = 44;
+
+/// How often the WAV header is updated, bounding what a crash can lose
+const SPOOL_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Prefix of spool file names, followed by the process id and start time
+const SPOOL_PREFIX: &str = "recording-";
+
+/// Tells apart the spool files created within the same millisecond
+static SPOOL_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// When and where the capture worker moves a recording to disk
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpoolSettings {
+    pub dir: PathBuf,
+    /// Recordings are spooled once they reach this many 16kHz samples
+    pub after_samples: usize,
+}
+
+/// A spool file left behind by a previous run (crash or forced quit)
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanedRecording {
+    pub path: String,
+    pub duration_seconds: f64,
+    /// Last write, in seconds since the Unix epoch
+    pub recorded_at: u64,
+}
+
+/// Prefix of the spool files of this process. The start time tells them apart
+/// from those of an earlier process that had the same id.
+fn session_prefix() -> &'static str {
+    static PREFIX: OnceLock<String> = OnceLock::new();
+    PREFIX.get_or_init(|| {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        format!("{}{}-{}-", SPOOL_PREFIX, std::process::id(), started)
+    })
+}
+
+/// Directory holding the spool files
+pub fn spool_dir() -> Result<PathBuf, String> {
+    dirs::data_dir()
+        .ok_or_else(|| "Failed to get data directory".to_string())
+        .map(|d| d.join("Flemme").join("recordings"))
+}
+
+/// 16kHz mono 16-bit WAV file growing while the user speaks
+///
+/// The header is rewritten every `SPOOL_FLUSH_INTERVAL`, so the file stays
+/// readable if the app dies; `read_spool` ignores the header sizes anyway.
+pub struct SpoolWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    samples: usize,
+    last_flush: Instant,
+}
+
+impl SpoolWriter {
+    /// Create a new spool file in `dir`
+    pub fn create(dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create spool directory: {}", e))?;
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let name = format!(
+            "{}{}-{}.wav",
+            session_prefix(),
+            millis,
+            SPOOL_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+
+        let file = File::create(&path).map_err(|e| format!("Failed to create spool file {:?}: {}", path, e))?;
+        let mut writer = Self {
+            path,
+            file: BufWriter::new(file),
+            samples: 0,
+            last_flush: Instant::now(),
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of samples written so far
+    pub fn len(&self) -> usize {
+        self.samples
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples == 0
+    }
+
+    /// Append 16kHz samples
+    pub fn append(&mut self, samples: &[f32]) -> Result<(), String> {
+        for &sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.file
+                .write_all(&value.to_le_bytes())
+                .map_err(|e| format!("Failed to write spool file: {}", e))?;
+        }
+        self.samples += samples.len();
+
+        if self.last_flush.elapsed() >= SPOOL_FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Update the header and push everything written so far to disk
+    pub fn flush(&mut self) -> Result<(), String> {
+        let end = self.file.stream_position().map_err(|e| format!("Failed to flush spool file: {}", e))?;
+        self.write_header()?;
+        self.file
+            .seek(SeekFrom::Start(end))
+            .and_then(|_| self.file.flush())
+            .map_err(|e| format!("Failed to flush spool file: {}", e))?;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    /// Close the file, keeping the recording on disk. Returns its path and
+    /// number of samples.
+    pub fn finish(mut self) -> Result<(PathBuf, usize), String> {
+        self.flush()?;
+        Ok((self.path.clone(), self.samples))
+    }
+
+    /// Read the whole recording back and delete the file
+    pub fn read_back(self) -> Result<Vec<f32>, String> {
+        let (path, _) = self.finish()?;
+        let samples = read_spool(&path)?;
+        if let Err(e) = fs::remove_file(&path) {
+            eprintln!("Failed to delete spool file {:?}: {}", path, e);
+        }
+        Ok(samples)
+    }
+
+    /// Delete the file without reading it
+    pub fn discard(self) {
+        let path = self.path.clone();
+        drop(self);
+        let _ = fs::remove_file(path);
+    }
+
+    fn write_header(&mut self) -> Result<(), String> {
+        let data_size = (self.samples * 2) as u32;
+        let mut header = Vec::with_capacity(WAV_HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(36 + data_size).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&1u16.to_le_bytes()); // mono
+        header.extend_from_slice(&TARGET_SAMPLE_RATE.to_le_bytes());
+        header.extend_from_slice(&(TARGET_SAMPLE_RATE * 2).to_le_bytes()); // byte rate
+        header.extend_from_slice(&2u16.to_le_bytes()); // block align
+        header.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_size.to_le_bytes());
+
+        self.file
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.file.write_all(&header))
+            .map_err(|e| format!("Failed to write spool header: {}", e))
+    }
+}
+
+/// Read a spool file, including audio written after the last header update
+pub fn read_spool(path: &Path) -> Result<Vec<f32>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read spool file {:?}: {}", path, e))?;
+    if bytes.len() < WAV_HEADER_SIZE as usize || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(format!("{:?} is not a spool file", path));
+    }
+
+    Ok(bytes[WAV_HEADER_SIZE as usize..]
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32)
+        .collect())
+}
+
+/// Fix the header sizes of a spool file cut short by a crash, so regular
+/// decoders read all of it
+pub fn repair_spool(path: &Path) -> Result<(), String> {
+    let length = fs::metadata(path)
+        .map_err(|e| format!("Failed to read spool file {:?}: {}", path, e))?
+        .len();
+    if length < WAV_HEADER_SIZE {
+        return Err(format!("{:?} is not a spool file", path));
+    }
+    let data_size = ((length - WAV_HEADER_SIZE) & !1) as u32;
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open spool file {:?}: {}", path, e))?;
+    file.seek(SeekFrom::Start(4))
+        .and_then(|_| file.write_all(&(36 + data_size).to_le_bytes()))
+        .and_then(|_| file.seek(SeekFrom::Start(40)))
+        .and_then(|_| file.write_all(&data_size.to_le_bytes()))
+        .map_err(|e| format!("Failed to repair spool file {:?}: {}", path, e))
+}
+
+/// Spool files in `dir` left by other processes (the current one may be
+/// spooling a recording right now)
+pub fn list_orphans(dir: &Path) -> Vec<OrphanedRecording> {
+    let current_prefix = session_prefix();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut orphans: Vec<OrphanedRecording> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.starts_with(SPOOL_PREFIX) && name.ends_with(".wav") && !name.starts_with(current_prefix)
+        })
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let samples = metadata.len().saturating_sub(WAV_HEADER_SIZE) / 2;
+            let recorded_at = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            Some(OrphanedRecording {
+                path: entry.path().to_string_lossy().to_string(),
+                duration_seconds: samples as f64 / TARGET_SAMPLE_RATE as f64,
+                recorded_at,
+            })
+        })
+        .collect();
+
+    orphans.sort_by_key(|o| o.recorded_at);
+    orphans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spool_roundtrip_and_crash_recovery() {
+        let dir = std::env::temp_dir().join(format!("flemme-spool-test-{}", std::process::id()));
+        let mut writer = SpoolWriter::create(&dir).unwrap();
+        writer.append(&vec![0.5; 1600]).unwrap();
+        writer.flush().unwrap();
+        writer.append(&vec![-0.25; 800]).unwrap();
+        let path = writer.path().to_path_buf();
+
+        // Not listed as an orphan while this process owns it
+        assert!(list_orphans(&dir).is_empty());
+
+        // Unlike the file of an earlier process that had the same id
+        let earlier = dir.join(format!("{}{}-1-0-0.wav", SPOOL_PREFIX, std::process::id()));
+        fs::write(&earlier, [0u8; 44]).unwrap();
+        assert_eq!(list_orphans(&dir).len(), 1);
+        fs::remove_file(&earlier).unwrap();
+
+        // Audio written after the last header update is still read back
+        drop(writer);
+        let samples = read_spool(&path).unwrap();
+        assert_eq!(samples.len(), 2400);
+        assert!((samples[0] - 0.5).abs() < 1e-3);
+        assert!((samples[2399] + 0.25).abs() < 1e-3);
+
+        repair_spool(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 4800);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_finish_keeps_and_read_back_deletes_the_spool_file() {
+        let dir = std::env::temp_dir().join(format!("flemme-spool-finish-{}", std::process::id()));
+        let mut writer = SpoolWriter::create(&dir).unwrap();
+        writer.append(&vec![0.1; 320]).unwrap();
+        let (path, samples) = writer.finish().unwrap();
+        assert_eq!(samples, 320);
+        assert_eq!(read_spool(&path).unwrap().len(), 320);
+
+        let mut writer = SpoolWriter::create(&dir).unwrap();
+        writer.append(&vec![0.1; 320]).unwrap();
+        let second = writer.path().to_path_buf();
+        assert_eq!(writer.read_back().unwrap().len(), 320);
+        assert!(!second.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,287 @@
+// System audio - discovery of monitor/loopback devices to capture what the machine plays
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::Device;
+use serde::Serialize;
+
+/// What an audio device captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    /// A microphone or line input
+    Microphone,
+    /// What the machine plays (PulseAudio/PipeWire monitor, WASAPI loopback, virtual loopback driver)
+    Monitor,
+}
+
+/// An audio device that can be recorded from
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioDeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+    pub kind: DeviceKind,
+}
+
+/// A monitor device ready to be opened by the recorder
+pub struct MonitorDevice {
+    pub device: Device,
+    pub name: String,
+    /// Output device recorded in WASAPI loopback mode (Windows)
+    pub loopback: bool,
+    /// PulseAudio source to select through the ALSA `pulse` device (Linux)
+    pub pulse_source: Option<String>,
+}
+
+/// Check if an input device name looks like a loopback/monitor device
+/// (e.g. "alsa_output.pci-0000_00_1f.3.analog-stereo.monitor", "Stereo Mix", "BlackHole 2ch")
+pub fn is_monitor_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    // PulseAudio/PipeWire monitor sources, not any device with "monitor" in its name
+    if name.ends_with(".monitor") {
+        return true;
+    }
+    ["loopback", "stereo mix", "what u hear", "blackhole", "soundflower"]
+        .iter()
+        .any(|pattern| name.contains(pattern))
+}
+
+/// List the monitor devices that are not regular input devices
+/// (PulseAudio/PipeWire monitor sources on Linux, output devices on Windows)
+pub fn list_monitor_devices() -> Vec<AudioDeviceInfo> {
+    platform::list_monitor_devices()
+}
+
+/// Find the monitor device to record from, `None` meaning the monitor of
+/// the default output
+pub fn open_monitor(name: Option<&str>) -> Result<MonitorDevice, String> {
+    platform::open_monitor(name)
+}
+
+/// Move the stream just opened on the ALSA `pulse` device to a PulseAudio
+/// source (streams open on the default source)
+pub fn move_to_pulse_source(source: &str) -> Result<(), String> {
+    platform::move_to_pulse_source(source)
+}
+
+/// Find an input device by name
+fn find_input_device(name: &str) -> Option<Device> {
+    cpal::default_host()
+        .input_devices()
+        .ok()?
+        .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::*;
+    use std::process::Command;
+
+    /// Run `pactl` (PulseAudio, or PipeWire through pipewire-pulse)
+    fn pactl(args: &[&str]) -> Option<String> {
+        let output = Command::new("pactl").args(args).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Monitor source of the default sink
+    fn default_monitor() -> Option<String> {
+        pactl(&["get-default-sink"]).map(|sink| format!("{}.monitor", sink))
+    }
+
+    fn monitor_sources() -> Vec<String> {
+        // Each line: index, name, driver, sample spec, state
+        pactl(&["list", "short", "sources"])
+            .map(|output| {
+                output
+                    .lines()
+                    .filter_map(|line| line.split('\t').nth(1))
+                    .filter(|name| name.ends_with(".monitor"))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Index of the newest recording stream of this process
+    fn own_source_output() -> Option<u32> {
+        // Blocks of "Source Output #<index>" followed by their properties
+        let output = pactl(&["list", "source-outputs"])?;
+        let pid = format!("application.process.id = \"{}\"", std::process::id());
+        let mut index = None;
+        let mut own = None;
+        for line in output.lines().map(str::trim) {
+            if let Some(number) = line.strip_prefix("Source Output #") {
+                index = number.parse::<u32>().ok();
+            } else if line == pid {
+                own = own.max(index);
+            }
+        }
+        own
+    }
+
+    pub fn move_to_pulse_source(source: &str) -> Result<(), String> {
+        // The stream shows up once the plugin has connected it
+        for _ in 0..10 {
+            if let Some(index) = own_source_output() {
+                return pactl(&["move-source-output", &index.to_string(), source])
+                    .map(|_| ())
+                    .ok_or_else(|| format!("Failed to record from monitor source '{}'", source));
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        Err("Recording stream not found in PulseAudio (is pactl installed?)".to_string())
+    }
+
+    pub fn list_monitor_devices() -> Vec<AudioDeviceInfo> {
+        let default_monitor = default_monitor();
+        monitor_sources()
+            .into_iter()
+            .map(|name| AudioDeviceInfo {
+                is_default: Some(&name) == default_monitor.as_ref(),
+                name,
+                kind: DeviceKind::Monitor,
+            })
+            .collect()
+    }
+
+    pub fn open_monitor(name: Option<&str>) -> Result<MonitorDevice, String> {
+        // Monitor-like ALSA devices (e.g. snd-aloop) are opened directly
+        if let Some(name) = name {
+            if let Some(device) = find_input_device(name) {
+                return Ok(MonitorDevice {
+                    device,
+                    name: name.to_string(),
+                    loopback: false,
+                    pulse_source: None,
+                });
+            }
+        }
+
+        let source = match name {
+            Some(name) => name.to_string(),
+            None => default_monitor().ok_or("No PulseAudio/PipeWire default output found (is pactl installed?)")?,
+        };
+        if !monitor_sources().contains(&source) {
+            return Err(format!("Monitor source '{}' not found", source));
+        }
+
+        let device = find_input_device("pulse")
+            .ok_or("ALSA 'pulse' device not found (install the PulseAudio ALSA plugin)")?;
+
+        Ok(MonitorDevice {
+            device,
+            name: source.clone(),
+            loopback: false,
+            pulse_source: Some(source),
+        })
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::*;
+
+    pub fn list_monitor_devices() -> Vec<AudioDeviceInfo> {
+        let host = cpal::default_host();
+        let default_name = host.default_output_device().and_then(|d| d.name().ok());
+
+        host.output_devices()
+            .map(|devices| {
+                devices
+                    .filter_map(|d| d.name().ok())
+                    .map(|name| AudioDeviceInfo {
+                        is_default: Some(&name) == default_name.as_ref(),
+                        name,
+                        kind: DeviceKind::Monitor,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn open_monitor(name: Option<&str>) -> Result<MonitorDevice, String> {
+        // Monitor-like input devices (e.g. "Stereo Mix") are opened directly
+        if let Some(name) = name {
+            if let Some(device) = find_input_device(name) {
+                return Ok(MonitorDevice {
+                    device,
+                    name: name.to_string(),
+                    loopback: false,
+                    pulse_source: None,
+                });
+            }
+        }
+
+        // Otherwise record an output device in loopback mode
+        let host = cpal::default_host();
+        let device = match name {
+            Some(name) => host
+                .output_devices()
+                .map_err(|e| format!("Failed to get output devices: {}", e))?
+                .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+                .ok_or_else(|| format!("Output device '{}' not found", name))?,
+            None => host.default_output_device().ok_or("No output device found")?,
+        };
+        let name = device.name().unwrap_or_else(|_| "Unknown device".to_string());
+
+        Ok(MonitorDevice {
+            device,
+            name,
+            loopback: true,
+            pulse_source: None,
+        })
+    }
+
+    pub fn move_to_pulse_source(_source: &str) -> Result<(), String> {
+        Err("PulseAudio sources are only available on Linux".to_string())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+mod platform {
+    use super::*;
+
+    // Monitors are regular input devices here (virtual loopback drivers such as
+    // BlackHole), already listed with the input devices
+    pub fn list_monitor_devices() -> Vec<AudioDeviceInfo> {
+        Vec::new()
+    }
+
+    pub fn open_monitor(name: Option<&str>) -> Result<MonitorDevice, String> {
+        let device = match name {
+            Some(name) => find_input_device(name).ok_or_else(|| format!("Device '{}' not found", name))?,
+            None => cpal::default_host()
+                .input_devices()
+                .map_err(|e| format!("Failed to get input devices: {}", e))?
+                .find(|d| d.name().map(|n| is_monitor_name(&n)).unwrap_or(false))
+                .ok_or("No loopback device found (install a virtual audio driver such as BlackHole)")?,
+        };
+        let name = device.name().unwrap_or_else(|_| "Unknown device".to_string());
+
+        Ok(MonitorDevice {
+            device,
+            name,
+            loopback: false,
+            pulse_source: None,
+        })
+    }
+
+    pub fn move_to_pulse_source(_source: &str) -> Result<(), String> {
+        Err("PulseAudio sources are only available on Linux".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monitor_names() {
+        assert!(is_monitor_name("alsa_output.pci-0000_00_1f.3.analog-stereo.monitor"));
+        assert!(is_monitor_name("Stereo Mix (Realtek High Definition Audio)"));
+        assert!(is_monitor_name("BlackHole 2ch"));
+        assert!(!is_monitor_name("USB Headset Microphone"));
+        assert!(!is_monitor_name("Studio Monitor USB Microphone"));
+    }
+}
@@ -1,17 +1,229 @@
-// VoiceActivityDetector - detects voice activity using Silero VAD ONNX model
+// Voice activity detection - the `Vad` trait and its Silero VAD (ONNX model) implementation
 use ndarray::{Array0, Array2, Array3};
 use ort::session::builder::GraphOptimizationLevel;
 use ort::session::Session;
-use ort::value::TensorRef;
+use ort::tensor::TensorElementType;
+use ort::value::{Tensor, ValueType};
+use crate::config::VadSettings;
 use std::path::Path;
 
+/// Silero VAD release whose model signature the detector is written for
+pub const SILERO_MODEL_VERSION: &str = "v5";
+
+/// Inputs of the Silero v5 model, in the order `infer` passes them
+const SILERO_INPUTS: [(&str, TensorElementType); 3] = [
+    ("input", TensorElementType::Float32),
+    ("state", TensorElementType::Float32),
+    ("sr", TensorElementType::Int64),
+];
+
+/// Silence put between the speech segments joined by `filter_silence`, so
+/// Whisper hears a pause rather than words running into each other
+const SEGMENT_JOIN_SILENCE_SAMPLES: usize = 1600; // 100ms at 16kHz
+
+/// Voice activity detection on 16kHz mono audio, chunk by chunk. The
+/// segmentation built on the per-chunk decisions is shared by all detectors.
+pub trait Vad {
+    /// Probability of speech (0.0 to 1.0) in a chunk of 512, 1024 or 1536 samples
+    fn detect(&mut self, audio_data: &[f32]) -> Result<f32, String>;
+
+    /// Reset the internal state (call between different recordings)
+    fn reset(&mut self);
+
+    fn settings(&self) -> &VadSettings;
+
+    /// Apply new tuning (threshold, chunk size, durations)
+    fn set_settings(&mut self, settings: VadSettings) -> Result<(), String>;
+
+    /// Name of the detector, for logs and diagnostics
+    fn name(&self) -> &'static str;
+
+    /// Get current threshold
+    fn threshold(&self) -> f32 {
+        self.settings().threshold
+    }
+
+    /// Samples per VAD decision
+    fn chunk_size(&self) -> usize {
+        self.settings().chunk_size
+    }
+
+    /// Speech probability of a chunk, 0.0 when detection fails
+    fn probability(&mut self, audio_data: &[f32]) -> f32 {
+        self.detect(audio_data).unwrap_or_else(|e| {
+            eprintln!("VAD detection error: {}", e);
+            0.0
+        })
+    }
+
+    /// Check if audio contains speech above threshold
+    fn is_speech(&mut self, audio_data: &[f32]) -> bool {
+        self.probability(audio_data) > self.threshold()
+    }
+
+    /// Run the detector once over `audio_data`: the speech ratio and the
+    /// segments are then derived from these probabilities. Chunks go through
+    /// one by one, each depending on the state left by the previous one.
+    fn probabilities(&mut self, audio_data: &[f32], chunk_size: usize) -> SpeechProbabilities {
+        // Reset state before processing
+        self.reset();
+
+        let mut values = Vec::with_capacity(audio_data.len().div_ceil(chunk_size));
+        let mut chunks = audio_data.chunks_exact(chunk_size);
+        for chunk in &mut chunks {
+            values.push(self.probability(chunk));
+        }
+
+        // Handle the incomplete chunk at the end by padding with zeros
+        let remainder = chunks.remainder();
+        if !remainder.is_empty() {
+            let mut padded = vec![0.0f32; chunk_size];
+            padded[..remainder.len()].copy_from_slice(remainder);
+            values.push(self.probability(&padded));
+        }
+
+        SpeechProbabilities {
+            values,
+            chunk_size,
+            len: audio_data.len(),
+        }
+    }
+
+    /// Process audio and return only segments with speech
+    ///
+    /// Uses a smart detection strategy:
+    /// - First, analyze all chunks to detect speech ratio
+    /// - If speech ratio > `keep_all_speech_ratio` (30%), keep entire audio (short utterances/phrases)
+    /// - Otherwise, extract the padded segments and join them with a short silence
+    ///
+    /// # Arguments
+    /// * `audio_data` - Full audio buffer
+    /// * `chunk_size` - Size of chunks to analyze (512, 1024, or 1536 samples)
+    ///
+    /// # Returns
+    /// * `Vec<f32>` - Audio with silence removed
+    fn filter_silence(&mut self, audio_data: &[f32], chunk_size: usize) -> Vec<f32> {
+        let start_time = std::time::Instant::now();
+
+        // Single pass: the ratio and the segments come from the same probabilities
+        let probabilities = self.probabilities(audio_data, chunk_size);
+        println!(
+            "[TIMING] VAD filter_silence: {:.0}ms (processed {} chunks)",
+            start_time.elapsed().as_millis(),
+            probabilities.values.len()
+        );
+        probabilities.filter(audio_data, self.settings())
+    }
+
+    /// Share of the chunks of `audio_data` detected as speech (0.0 to 1.0)
+    fn speech_ratio(&mut self, audio_data: &[f32], chunk_size: usize) -> f32 {
+        let threshold = self.threshold();
+        self.probabilities(audio_data, chunk_size).speech_ratio(threshold)
+    }
+
+    /// Speech segments with the minimum speech and silence durations from settings
+    fn speech_segments(&mut self, audio_data: &[f32], chunk_size: usize) -> Vec<SpeechSegment> {
+        self.probabilities(audio_data, chunk_size).segments(self.settings())
+    }
+
+    /// Speech segments padded by `speech_pad_ms` and merged when closer than
+    /// `merge_gap_ms`, as cut out by `filter_silence`
+    fn padded_speech_segments(&mut self, audio_data: &[f32], chunk_size: usize) -> Vec<SpeechSegment> {
+        self.probabilities(audio_data, chunk_size).padded_segments(self.settings())
+    }
+
+    /// Get speech segments with minimum duration constraints
+    ///
+    /// # Arguments
+    /// * `audio_data` - Full audio buffer
+    /// * `chunk_size` - Size of chunks to analyze (512, 1024, or 1536)
+    /// * `min_speech_duration_ms` - Minimum duration for a speech segment
+    /// * `min_silence_duration_ms` - Minimum silence duration to split segments
+    ///
+    /// # Returns
+    /// * `Vec<SpeechSegment>` - List of speech segments meeting duration constraints
+    fn get_speech_segments_with_duration(
+        &mut self,
+        audio_data: &[f32],
+        chunk_size: usize,
+        min_speech_duration_ms: usize,
+        min_silence_duration_ms: usize,
+    ) -> Vec<SpeechSegment> {
+        let threshold = self.threshold();
+        self.probabilities(audio_data, chunk_size)
+            .segments_with_duration(threshold, min_speech_duration_ms, min_silence_duration_ms)
+    }
+
+    /// Analyze full audio and return speech segments with timestamps
+    ///
+    /// # Arguments
+    /// * `audio_data` - Full audio buffer
+    /// * `chunk_size` - Size of chunks to analyze (512, 1024, or 1536)
+    ///
+    /// # Returns
+    /// * `Vec<SpeechSegment>` - List of speech segments with start/end indices
+    fn get_speech_segments(
+        &mut self,
+        audio_data: &[f32],
+        chunk_size: usize,
+    ) -> Vec<SpeechSegment> {
+        let mut segments = Vec::new();
+        let mut current_segment: Option<SpeechSegment> = None;
+
+        // Reset state before processing
+        self.reset();
+
+        for (chunk_idx, chunk) in audio_data.chunks(chunk_size).enumerate() {
+            // Skip incomplete chunks
+            if chunk.len() != chunk_size {
+                break;
+            }
+
+            let start_sample = chunk_idx * chunk_size;
+            let is_speech = self.is_speech(chunk);
+
+            match (&mut current_segment, is_speech) {
+                // Start new segment
+                (None, true) => {
+                    current_segment = Some(SpeechSegment {
+                        start: start_sample,
+                        end: start_sample + chunk.len(),
+                    });
+                }
+                // Continue segment
+                (Some(seg), true) => {
+                    seg.end = start_sample + chunk.len();
+                }
+                // End segment
+                (Some(_), false) => {
+                    if let Some(seg) = current_segment.take() {
+                        segments.push(seg);
+                    }
+                }
+                // No speech, no segment
+                (None, false) => {}
+            }
+        }
+
+        // Add last segment if exists
+        if let Some(seg) = current_segment {
+            segments.push(seg);
+        }
+
+        segments
+    }
+}
+
 pub struct VoiceActivityDetector {
     session: Session,
-    threshold: f32,
+    settings: VadSettings,
+    // Model inputs, allocated once and overwritten for every chunk
+    // Audio chunk (1, chunk_size)
+    input: Tensor<f32>,
     // Internal state for the model (batch=2, 1, hidden=128)
-    state: Array3<f32>,
+    state: Tensor<f32>,
     // Sample rate (must be 16000 for Whisper compatibility) - scalar int64
-    sr: i64,
+    sr: Tensor<i64>,
 }
 
 impl VoiceActivityDetector {
@@ -21,13 +233,15 @@ impl VoiceActivityDetector {
     /// * `model_path` - Path to silero_vad.onnx model
     /// * `threshold` - Detection threshold (0.0 to 1.0, recommended: 0.5)
     pub fn new<P: AsRef<Path>>(model_path: P, threshold: f32) -> Result<Self, String> {
-        // Validate threshold
-        if !(0.0..=1.0).contains(&threshold) {
-            return Err(format!(
-                "Invalid threshold: {}. Must be between 0.0 and 1.0",
-                threshold
-            ));
-        }
+        Self::with_settings(model_path, VadSettings {
+            threshold,
+            ..VadSettings::default()
+        })
+    }
+
+    /// Create with the tuning from settings (threshold, chunk size, durations)
+    pub fn with_settings<P: AsRef<Path>>(model_path: P, settings: VadSettings) -> Result<Self, String> {
+        settings.validate()?;
 
         // Load ONNX model with ort 2.0 API
         let session = Session::builder()
@@ -37,16 +251,29 @@ impl VoiceActivityDetector {
             .commit_from_file(model_path)
             .map_err(|e| format!("Failed to load ONNX model: {}", e))?;
 
+        let inputs: Vec<(&str, &ValueType)> = session
+            .inputs
+            .iter()
+            .map(|input| (input.name.as_str(), &input.input_type))
+            .collect();
+        let outputs: Vec<&ValueType> = session.outputs.iter().map(|output| &output.output_type).collect();
+        check_signature(&inputs, &outputs)?;
+
+        let input = input_tensor(settings.chunk_size)?;
         // Initialize internal state (required by Silero VAD model)
         // state is the combined LSTM state (batch=2, 1, hidden=128)
-        let state = Array3::<f32>::zeros((2, 1, 128));
-        let sr = 16000i64; // Sample rate as scalar int64
+        let state = Tensor::from_array(Array3::<f32>::zeros((2, 1, 128)))
+            .map_err(|e| format!("Failed to create state tensor: {}", e))?;
+        // Sample rate as scalar int64
+        let sr = Tensor::from_array(Array0::from_elem((), 16000i64))
+            .map_err(|e| format!("Failed to create sr tensor: {}", e))?;
 
-        println!("Silero VAD initialized: threshold={}", threshold);
+        println!("Silero VAD initialized: threshold={}", settings.threshold);
 
         Ok(Self {
             session,
-            threshold,
+            settings,
+            input,
             state,
             sr,
         })
@@ -54,7 +281,7 @@ impl VoiceActivityDetector {
 
     /// Create with default settings (16kHz, 0.3 threshold)
     pub fn new_default<P: AsRef<Path>>(model_path: P) -> Result<Self, String> {
-        Self::new(model_path, 0.3)
+        Self::with_settings(model_path, VadSettings::default())
     }
 
     /// Detect voice activity in an audio chunk
@@ -65,7 +292,7 @@ impl VoiceActivityDetector {
     ///
     /// # Returns
     /// * `f32` - Probability of speech (0.0 to 1.0)
-    pub fn detect(&mut self, audio_data: &[f32]) -> Result<f32, String> {
+    fn infer(&mut self, audio_data: &[f32]) -> Result<f32, String> {
         // Validate input size
         let valid_sizes = [512, 1024, 1536];
         if !valid_sizes.contains(&audio_data.len()) {
@@ -75,25 +302,20 @@ impl VoiceActivityDetector {
             ));
         }
 
-        // Prepare input tensor: (1, num_samples) for ort 2.0
-        let input = Array2::from_shape_vec((1, audio_data.len()), audio_data.to_vec())
-            .map_err(|e| format!("Failed to create input array: {}", e))?;
-
-        // Create TensorRef from array views for ort 2.0
-        let input_tensor = TensorRef::from_array_view(input.view())
-            .map_err(|e| format!("Failed to create input tensor: {}", e))?;
-        let state_tensor = TensorRef::from_array_view(self.state.view())
-            .map_err(|e| format!("Failed to create state tensor: {}", e))?;
-
-        // Create scalar tensor for sr (int64)
-        let sr_scalar = Array0::from_elem((), self.sr);
-        let sr_tensor = TensorRef::from_array_view(sr_scalar.view())
-            .map_err(|e| format!("Failed to create sr tensor: {}", e))?;
+        // Copy the chunk into the input tensor, reallocated only when the chunk size changes
+        if self.input.shape().num_elements() != audio_data.len() {
+            self.input = input_tensor(audio_data.len())?;
+        }
+        let (_, input) = self
+            .input
+            .try_extract_tensor_mut::<f32>()
+            .map_err(|e| format!("Failed to write input tensor: {}", e))?;
+        input.copy_from_slice(audio_data);
 
         // Run inference - ort v2.0 API (Silero VAD expects: input, state, sr)
         let outputs = self
             .session
-            .run(ort::inputs![input_tensor, state_tensor, sr_tensor])
+            .run(ort::inputs![&self.input, &self.state, &self.sr])
             .map_err(|e| format!("Failed to run inference: {}", e))?;
 
         // Extract output probability (ort 2.0 API)
@@ -102,127 +324,181 @@ impl VoiceActivityDetector {
             .map_err(|e| format!("Failed to extract output: {}", e))?;
         let probability = output.1[0];
 
-        // Update state for next prediction (ort 2.0 API)
-        let new_state = outputs[1]
+        // Update state for next prediction, in place (ort 2.0 API)
+        let (_, new_state) = outputs[1]
             .try_extract_tensor::<f32>()
             .map_err(|e| format!("Failed to extract new state: {}", e))?;
-
-        // Convert back to fixed dimensions (2, 1, 128)
-        self.state = Array3::from_shape_vec((2, 1, 128), new_state.1.to_vec())
-            .map_err(|e| format!("Failed to convert state dimensions: {}", e))?;
+        let (_, state) = self
+            .state
+            .try_extract_tensor_mut::<f32>()
+            .map_err(|e| format!("Failed to write state tensor: {}", e))?;
+        if new_state.len() != state.len() {
+            return Err(format!("Unexpected VAD state size: {}", new_state.len()));
+        }
+        state.copy_from_slice(new_state);
 
         Ok(probability)
     }
 
-    /// Check if audio contains speech above threshold
-    pub fn is_speech(&mut self, audio_data: &[f32]) -> bool {
-        match self.detect(audio_data) {
-            Ok(probability) => probability > self.threshold,
-            Err(e) => {
-                eprintln!("VAD detection error: {}", e);
-                false
-            }
+    /// Set new threshold
+    pub fn set_threshold(&mut self, threshold: f32) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(format!("Invalid threshold: {}", threshold));
         }
+        self.settings.threshold = threshold;
+        Ok(())
     }
+}
 
-    /// Process audio and return only segments with speech
-    ///
-    /// Uses a smart detection strategy:
-    /// - First, analyze all chunks to detect speech ratio
-    /// - If speech ratio > 30%, keep entire audio (short utterances/phrases)
-    /// - Otherwise, extract segments with duration constraints
-    ///
-    /// # Arguments
-    /// * `audio_data` - Full audio buffer
-    /// * `chunk_size` - Size of chunks to analyze (512, 1024, or 1536 samples)
-    ///
-    /// # Returns
-    /// * `Vec<f32>` - Audio with silence removed
-    pub fn filter_silence(&mut self, audio_data: &[f32], chunk_size: usize) -> Vec<f32> {
-        let start_time = std::time::Instant::now();
+impl Vad for VoiceActivityDetector {
+    fn detect(&mut self, audio_data: &[f32]) -> Result<f32, String> {
+        self.infer(audio_data)
+    }
 
-        // Reset state before processing
-        self.reset();
+    /// Reset VAD internal state (call between different recordings)
+    fn reset(&mut self) {
+        if let Ok((_, state)) = self.state.try_extract_tensor_mut::<f32>() {
+            state.fill(0.0);
+        }
+    }
 
-        // First pass: detect speech ratio
-        let num_chunks = (audio_data.len() + chunk_size - 1) / chunk_size;
-        let mut speech_chunks = 0;
-
-        for chunk in audio_data.chunks(chunk_size) {
-            let chunk_to_process = if chunk.len() != chunk_size {
-                let mut padded = vec![0.0f32; chunk_size];
-                padded[..chunk.len()].copy_from_slice(chunk);
-                padded
-            } else {
-                chunk.to_vec()
-            };
-
-            if self.is_speech(&chunk_to_process) {
-                speech_chunks += 1;
-            }
+    fn settings(&self) -> &VadSettings {
+        &self.settings
+    }
+
+    /// Apply new tuning without reloading the model
+    fn set_settings(&mut self, settings: VadSettings) -> Result<(), String> {
+        settings.validate()?;
+        self.settings = settings;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "silero"
+    }
+}
+
+/// Reject a model whose inputs and outputs are not those of Silero v5, e.g. v4
+/// (`input`, `sr`, `h`, `c`) or a later release, rather than failing on every chunk
+fn check_signature(inputs: &[(&str, &ValueType)], outputs: &[&ValueType]) -> Result<(), String> {
+    let inputs_match = inputs.len() == SILERO_INPUTS.len()
+        && inputs
+            .iter()
+            .zip(SILERO_INPUTS)
+            .all(|((name, ty), (expected_name, expected_ty))| *name == expected_name && ty.tensor_type() == Some(expected_ty));
+    // The state goes back in as is: (2, batch, 128) both ways
+    let is_state = |ty: &ValueType| ty.tensor_shape().is_some_and(|shape| matches!(**shape, [2 | -1, _, 128 | -1]));
+    let state_matches = inputs_match && is_state(inputs[1].1) && outputs.len() == 2 && is_state(outputs[1]);
+
+    if !state_matches {
+        let found: Vec<String> = inputs.iter().map(|(name, ty)| format!("{}: {}", name, ty)).collect();
+        return Err(format!(
+            "Unsupported Silero VAD model: expected the {} inputs (input, state, sr), found ({}). It is downloaded again on the next start, or from the model list",
+            SILERO_MODEL_VERSION,
+            found.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Zeroed (1, `chunk_size`) tensor for the audio chunks
+fn input_tensor(chunk_size: usize) -> Result<Tensor<f32>, String> {
+    Tensor::from_array(Array2::<f32>::zeros((1, chunk_size)))
+        .map_err(|e| format!("Failed to create input tensor: {}", e))
+}
+
+/// Speech probability of every chunk of an audio buffer, from one pass of a detector
+pub struct SpeechProbabilities {
+    pub values: Vec<f32>,
+    pub chunk_size: usize,
+    /// Length of the analyzed audio, in samples
+    pub len: usize,
+}
+
+impl SpeechProbabilities {
+    /// Share of the chunks above `threshold` (0.0 to 1.0)
+    pub fn speech_ratio(&self, threshold: f32) -> f32 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        let speech_chunks = self.values.iter().filter(|&&p| p > threshold).count();
+        speech_chunks as f32 / self.values.len() as f32
+    }
+
+    /// Speech segments with the threshold and minimum durations from settings
+    pub fn segments(&self, settings: &VadSettings) -> Vec<SpeechSegment> {
+        self.segments_with_duration(
+            settings.threshold,
+            settings.min_speech_duration_ms as usize,
+            settings.min_silence_duration_ms as usize,
+        )
+    }
+
+    /// Probabilities of the first `len` samples only
+    pub fn truncated(&self, len: usize) -> SpeechProbabilities {
+        let len = len.min(self.len);
+        SpeechProbabilities {
+            values: self.values[..len.div_ceil(self.chunk_size)].to_vec(),
+            chunk_size: self.chunk_size,
+            len,
         }
+    }
 
-        let speech_ratio = speech_chunks as f32 / num_chunks as f32;
-        let elapsed = start_time.elapsed().as_millis();
+    /// The audio these probabilities were computed on, without its silences:
+    /// - If speech ratio > `keep_all_speech_ratio` (30%), keep entire audio (short utterances/phrases)
+    /// - Otherwise, extract the padded segments and join them with a short silence
+    pub fn filter(&self, audio_data: &[f32], settings: &VadSettings) -> Vec<f32> {
+        let speech_ratio = self.speech_ratio(settings.threshold);
 
-        // If significant speech detected (>30%), keep entire audio
+        // If significant speech detected (>30% by default), keep entire audio
         // This handles short phrases/utterances better than aggressive filtering
-        if speech_ratio > 0.3 {
+        if speech_ratio > settings.keep_all_speech_ratio {
             println!(
-                "[TIMING] VAD filter_silence: {:.0}ms (processed {} chunks, {} speech chunks, {:.1}% speech)",
-                elapsed, num_chunks, speech_chunks, speech_ratio * 100.0
-            );
-            println!(
-                "VAD: Speech ratio {:.1}% > 30%, keeping entire audio ({:.2}s)",
+                "VAD: Speech ratio {:.1}% > {:.0}%, keeping entire audio ({:.2}s)",
                 speech_ratio * 100.0,
+                settings.keep_all_speech_ratio * 100.0,
                 audio_data.len() as f32 / 16000.0
             );
             return audio_data.to_vec();
         }
 
         // Low speech ratio - use segment extraction
-        let segments = self.get_speech_segments_with_duration(
-            audio_data,
-            chunk_size,
-            100, // min_speech_duration_ms
-            300, // min_silence_duration_ms
-        );
+        let segments = self.padded_segments(settings);
 
         let mut filtered = Vec::new();
-        for segment in &segments {
+        for (i, segment) in segments.iter().enumerate() {
+            if i > 0 {
+                filtered.resize(filtered.len() + SEGMENT_JOIN_SILENCE_SAMPLES, 0.0);
+            }
             filtered.extend_from_slice(segment.extract(audio_data));
         }
 
-        println!(
-            "[TIMING] VAD filter_silence: {:.0}ms (processed {} chunks, found {} speech segments, {:.1}% speech)",
-            elapsed, num_chunks, segments.len(), speech_ratio * 100.0
-        );
-
         let original_duration = audio_data.len() as f32 / 16000.0;
         let filtered_duration = filtered.len() as f32 / 16000.0;
         println!(
-            "VAD segments: {} segments, {:.2}s -> {:.2}s ({:.1}% kept)",
-            segments.len(), original_duration, filtered_duration,
+            "VAD segments: {} segments, {:.1}% speech, {:.2}s -> {:.2}s ({:.1}% kept)",
+            segments.len(), speech_ratio * 100.0, original_duration, filtered_duration,
             (filtered_duration / original_duration) * 100.0
         );
 
         filtered
     }
 
-    /// Get speech segments with minimum duration constraints
-    ///
-    /// # Arguments
-    /// * `audio_data` - Full audio buffer
-    /// * `chunk_size` - Size of chunks to analyze (512, 1024, or 1536)
-    /// * `min_speech_duration_ms` - Minimum duration for a speech segment
-    /// * `min_silence_duration_ms` - Minimum silence duration to split segments
-    ///
-    /// # Returns
-    /// * `Vec<SpeechSegment>` - List of speech segments meeting duration constraints
-    fn get_speech_segments_with_duration(
-        &mut self,
-        audio_data: &[f32],
-        chunk_size: usize,
+    /// `segments` padded and merged with the settings, see `pad_segments`
+    pub fn padded_segments(&self, settings: &VadSettings) -> Vec<SpeechSegment> {
+        pad_segments(
+            &self.segments(settings),
+            settings.speech_pad_samples(),
+            settings.merge_gap_samples(),
+            self.len,
+        )
+    }
+
+    /// Speech segments of at least `min_speech_duration_ms`, split by
+    /// silences of at least `min_silence_duration_ms`
+    pub fn segments_with_duration(
+        &self,
+        threshold: f32,
         min_speech_duration_ms: usize,
         min_silence_duration_ms: usize,
     ) -> Vec<SpeechSegment> {
@@ -234,22 +510,10 @@ impl VoiceActivityDetector {
         let mut current_segment: Option<SpeechSegment> = None;
         let mut silence_start: Option<usize> = None;
 
-        // Reset state before processing
-        self.reset();
-
-        for (chunk_idx, chunk) in audio_data.chunks(chunk_size).enumerate() {
-            // Handle incomplete chunks at the end by padding with zeros
-            let chunk_to_process = if chunk.len() != chunk_size {
-                let mut padded = vec![0.0f32; chunk_size];
-                padded[..chunk.len()].copy_from_slice(chunk);
-                padded
-            } else {
-                chunk.to_vec()
-            };
-
-            let start_sample = chunk_idx * chunk_size;
-            let end_sample = start_sample + chunk.len();
-            let is_speech = self.is_speech(&chunk_to_process);
+        for (chunk_idx, &probability) in self.values.iter().enumerate() {
+            let start_sample = chunk_idx * self.chunk_size;
+            let end_sample = (start_sample + self.chunk_size).min(self.len);
+            let is_speech = probability > threshold;
 
             match (&mut current_segment, is_speech) {
                 // Start new segment
@@ -303,88 +567,35 @@ impl VoiceActivityDetector {
 
         segments
     }
+}
 
-    /// Analyze full audio and return speech segments with timestamps
-    ///
-    /// # Arguments
-    /// * `audio_data` - Full audio buffer
-    /// * `chunk_size` - Size of chunks to analyze (512, 1024, or 1536)
-    ///
-    /// # Returns
-    /// * `Vec<SpeechSegment>` - List of speech segments with start/end indices
-    pub fn get_speech_segments(
-        &mut self,
-        audio_data: &[f32],
-        chunk_size: usize,
-    ) -> Vec<SpeechSegment> {
-        let mut segments = Vec::new();
-        let mut current_segment: Option<SpeechSegment> = None;
-
-        // Reset state before processing
-        self.reset();
-
-        for (chunk_idx, chunk) in audio_data.chunks(chunk_size).enumerate() {
-            // Skip incomplete chunks
-            if chunk.len() != chunk_size {
-                break;
-            }
-
-            let start_sample = chunk_idx * chunk_size;
-            let is_speech = self.is_speech(chunk);
-
-            match (&mut current_segment, is_speech) {
-                // Start new segment
-                (None, true) => {
-                    current_segment = Some(SpeechSegment {
-                        start: start_sample,
-                        end: start_sample + chunk.len(),
-                    });
-                }
-                // Continue segment
-                (Some(seg), true) => {
-                    seg.end = start_sample + chunk.len();
-                }
-                // End segment
-                (Some(_), false) => {
-                    if let Some(seg) = current_segment.take() {
-                        segments.push(seg);
-                    }
-                }
-                // No speech, no segment
-                (None, false) => {}
-            }
-        }
-
-        // Add last segment if exists
-        if let Some(seg) = current_segment {
-            segments.push(seg);
-        }
-
-        segments
-    }
-
-    /// Reset VAD internal state (call between different recordings)
-    pub fn reset(&mut self) {
-        self.state = Array3::<f32>::zeros((2, 1, 128));
-    }
-
-    /// Get current threshold
-    pub fn threshold(&self) -> f32 {
-        self.threshold
-    }
+/// Extend segments by `padding` samples on both sides, within the buffer (`len`)
+/// and without overlapping a neighbour (the gap between two segments is shared),
+/// then merge the segments separated by less than `merge_gap` samples
+pub fn pad_segments(segments: &[SpeechSegment], padding: usize, merge_gap: usize, len: usize) -> Vec<SpeechSegment> {
+    let mut padded: Vec<SpeechSegment> = Vec::with_capacity(segments.len());
+    for (i, segment) in segments.iter().enumerate() {
+        let room_before = match i.checked_sub(1) {
+            Some(previous) => (segment.start - segments[previous].end) / 2,
+            None => segment.start,
+        };
+        let room_after = match segments.get(i + 1) {
+            Some(next) => (next.start - segment.end).div_ceil(2),
+            None => len.saturating_sub(segment.end),
+        };
+        let start = segment.start - padding.min(room_before);
+        let end = segment.end + padding.min(room_after);
 
-    /// Set new threshold
-    pub fn set_threshold(&mut self, threshold: f32) -> Result<(), String> {
-        if !(0.0..=1.0).contains(&threshold) {
-            return Err(format!("Invalid threshold: {}", threshold));
+        match padded.last_mut() {
+            Some(last) if start - last.end < merge_gap => last.end = end,
+            _ => padded.push(SpeechSegment { start, end }),
         }
-        self.threshold = threshold;
-        Ok(())
     }
+    padded
 }
 
 /// Represents a segment of speech in an audio buffer
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SpeechSegment {
     pub start: usize, // Start sample index
     pub end: usize,   // End sample index
@@ -408,6 +619,14 @@ impl SpeechSegment {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_truncated_probabilities() {
+        let probabilities = SpeechProbabilities { values: vec![0.9, 0.1, 0.9], chunk_size: 512, len: 1300 };
+        let truncated = probabilities.truncated(600);
+        assert_eq!((truncated.values.len(), truncated.len), (2, 600));
+        assert_eq!(probabilities.truncated(5000).len, 1300);
+    }
+
     #[test]
     fn test_speech_segment_duration() {
         let segment = SpeechSegment {
@@ -422,4 +641,87 @@ mod tests {
         let result = VoiceActivityDetector::new("dummy.onnx", 1.5);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_invalid_chunk_size() {
+        let settings = VadSettings {
+            chunk_size: 700,
+            ..VadSettings::default()
+        };
+        let result = VoiceActivityDetector::with_settings("dummy.onnx", settings);
+        assert!(matches!(result, Err(e) if e.contains("chunk size")));
+    }
+
+    fn tensor(ty: TensorElementType, shape: &[i64]) -> ValueType {
+        ValueType::Tensor {
+            ty,
+            shape: ort::tensor::Shape::new(shape.iter().copied()),
+            dimension_symbols: ort::tensor::SymbolicDimensions::empty(shape.len()),
+        }
+    }
+
+    #[test]
+    fn test_only_silero_v5_models_are_accepted() {
+        use TensorElementType::{Float32, Int64};
+        let (audio, state, sr) = (tensor(Float32, &[-1, -1]), tensor(Float32, &[2, -1, 128]), tensor(Int64, &[]));
+        let output = tensor(Float32, &[-1, 1]);
+        let v5_inputs = [("input", &audio), ("state", &state), ("sr", &sr)];
+        assert!(check_signature(&v5_inputs, &[&output, &state]).is_ok());
+
+        // Silero v4 keeps its LSTM state in two tensors of 64
+        let half_state = tensor(Float32, &[2, -1, 64]);
+        let v4_inputs = [("input", &audio), ("sr", &sr), ("h", &half_state), ("c", &half_state)];
+        let result = check_signature(&v4_inputs, &[&output, &half_state, &half_state]);
+        assert!(matches!(result, Err(e) if e.contains("Unsupported Silero VAD model") && e.contains("h: ")));
+
+        // Same names, different state size
+        let v5_like = [("input", &audio), ("state", &half_state), ("sr", &sr)];
+        assert!(check_signature(&v5_like, &[&output, &half_state]).is_err());
+    }
+
+    fn segment(start: usize, end: usize) -> SpeechSegment {
+        SpeechSegment { start, end }
+    }
+
+    #[test]
+    fn test_segments_are_padded_within_buffer_and_neighbours() {
+        // Padding of 100: the first segment is clamped to the buffer start, the
+        // 60-sample gap is shared, the last segment is clamped to the buffer end
+        let segments = [segment(50, 200), segment(260, 400), segment(1000, 1950)];
+        assert_eq!(
+            pad_segments(&segments, 100, 0, 2000),
+            vec![segment(0, 230), segment(230, 500), segment(900, 2000)]
+        );
+    }
+
+    #[test]
+    fn test_close_segments_are_merged() {
+        let segments = [segment(1000, 2000), segment(2500, 3000), segment(6000, 7000)];
+
+        // 300 samples left between the first two once padded by 100
+        assert_eq!(
+            pad_segments(&segments, 100, 400, 10000),
+            vec![segment(900, 3100), segment(5900, 7100)]
+        );
+        assert_eq!(pad_segments(&segments, 100, 300, 10000).len(), 3);
+    }
+
+    #[test]
+    fn test_ratio_and_segments_from_one_pass() {
+        // 10 chunks of 100 samples, the last one incomplete
+        let probabilities = SpeechProbabilities {
+            values: vec![0.1, 0.9, 0.8, 0.1, 0.9, 0.1, 0.1, 0.1, 0.9, 0.9],
+            chunk_size: 100,
+            len: 950,
+        };
+        assert_eq!(probabilities.speech_ratio(0.5), 0.5);
+
+        // A one-chunk pause doesn't split speech, two chunks (12.5ms) do
+        let segments = probabilities.segments_with_duration(0.5, 0, 12);
+        assert_eq!(segments, vec![segment(100, 500), segment(800, 950)]);
+
+        // Segments shorter than 20ms (320 samples) are dropped
+        let segments = probabilities.segments_with_duration(0.5, 20, 12);
+        assert_eq!(segments, vec![segment(100, 500)]);
+    }
 }
\ No newline at end of file
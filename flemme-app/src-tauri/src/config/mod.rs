@@ -1,4 +1,6 @@
 // Config module - handles application settings and configuration
 pub mod settings;
+pub mod vocabulary;
 
-pub use settings::AppSettings;
+pub use settings::{AppSettings, PreprocessingSettings, SystemAudioMode, VadSettings, WhisperDecodeOptions};
+pub use vocabulary::{VocabularyList, VocabularyTerm};
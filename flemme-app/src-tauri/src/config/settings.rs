@@ -1,6 +1,8 @@
 // AppSettings - application configuration and settings
 
 use serde::{Deserialize, Serialize};
+use super::vocabulary::{self, VocabularyList};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -67,59 +69,481 @@ pub struct ExecutionMode {
     pub name: String,
     pub llm_model_id: Option<String>, // None for "Standard" mode
     pub system_prompt: String,
+    /// Whisper decoding for this mode, instead of the global `decode` settings
+    #[serde(default)]
+    pub decode: Option<WhisperDecodeOptions>,
+    /// Put in the Whisper prompt of this mode (e.g. "Compte rendu médical."), as `{style}` in the templates
+    #[serde(default)]
+    pub style_hint: String,
+}
+
+/// Whisper decoding parameters (whisper.cpp defaults), e.g. beam search for a
+/// careful mode that trades speed for accuracy
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct WhisperDecodeOptions {
+    /// Beams kept by beam search; 1 decodes greedily
+    pub beam_size: u32,
+    /// Sampling temperature of the first decoding (0.0 picks the most likely tokens)
+    pub temperature: f32,
+    /// Added to the temperature to decode again when a segment fails the thresholds below (0.0 disables the fallback)
+    pub temperature_increment: f32,
+    /// Segments more likely than this to be silence are dropped (when their logprob is also low)
+    pub no_speech_threshold: f32,
+    /// Average token log probability below which a segment is decoded again
+    pub logprob_threshold: f32,
+    /// Token entropy above which a segment is decoded again (repetitions)
+    pub entropy_threshold: f32,
+    /// Never start a segment with a blank
+    pub suppress_blank: bool,
+    /// Suppress non-speech tokens (music notes, sound effect annotations)
+    pub suppress_non_speech_tokens: bool,
+    /// Maximum segment length in characters (0 means no limit)
+    pub max_segment_length: u32,
+    /// Decode the whole audio as one segment (short dictations)
+    pub single_segment: bool,
+}
+
+impl Default for WhisperDecodeOptions {
+    fn default() -> Self {
+        Self {
+            beam_size: 1,
+            temperature: 0.0,
+            temperature_increment: 0.2,
+            no_speech_threshold: 0.6,
+            logprob_threshold: -1.0,
+            entropy_threshold: 2.4,
+            suppress_blank: true,
+            suppress_non_speech_tokens: false,
+            max_segment_length: 0,
+            single_segment: false,
+        }
+    }
+}
+
+impl WhisperDecodeOptions {
+    /// Check the values whisper.cpp can work with
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=16).contains(&self.beam_size) {
+            return Err(format!("Invalid beam size: {}. Must be between 1 and 16", self.beam_size));
+        }
+        if !(0.0..=1.0).contains(&self.temperature) || !(0.0..=1.0).contains(&self.temperature_increment) {
+            return Err(format!(
+                "Invalid temperature: {} (+{}). Must be between 0.0 and 1.0",
+                self.temperature, self.temperature_increment
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.no_speech_threshold) {
+            return Err(format!(
+                "Invalid no-speech threshold: {}. Must be between 0.0 and 1.0",
+                self.no_speech_threshold
+            ));
+        }
+        if self.logprob_threshold > 0.0 || self.entropy_threshold < 0.0 {
+            return Err(format!(
+                "Invalid logprob/entropy thresholds: {}/{}. Logprob must be negative and entropy positive",
+                self.logprob_threshold, self.entropy_threshold
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Whether to record what the machine plays (monitor/loopback device)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SystemAudioMode {
+    /// Microphone only
+    #[default]
+    Off,
+    /// System audio instead of the microphone
+    Only,
+    /// Microphone and system audio mixed together
+    Mix,
+    /// Microphone and system audio transcribed separately, labeled "Me" and "Others"
+    Separate,
+}
+
+/// Audio preprocessing chain applied before VAD and Whisper (after DC removal)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct PreprocessingSettings {
+    /// Remove rumble and handling noise below `high_pass_cutoff_hz`
+    pub high_pass_filter: bool,
+    pub high_pass_cutoff_hz: f32,
+    /// Gain to a target loudness (gated RMS) with a peak limiter, instead of peak normalization
+    pub loudness_normalization: bool,
+    pub target_loudness_dbfs: f32,
+    /// Spectral gating with a noise profile taken before speech starts (CPU heavy)
+    pub noise_suppression: bool,
+}
+
+impl Default for PreprocessingSettings {
+    fn default() -> Self {
+        Self {
+            high_pass_filter: true,
+            high_pass_cutoff_hz: 80.0,
+            loudness_normalization: true,
+            target_loudness_dbfs: -20.0,
+            noise_suppression: false,
+        }
+    }
+}
+
+/// Voice activity detection tuning (Silero VAD), e.g. for noisy offices
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct VadSettings {
+    /// Speech probability above which a chunk counts as speech (0.0 to 1.0)
+    pub threshold: f32,
+    /// Samples per VAD decision: 512, 1024 or 1536 (32, 64 or 96ms at 16kHz)
+    pub chunk_size: usize,
+    /// Recordings with a larger share of speech chunks are kept whole instead of cut into segments
+    pub keep_all_speech_ratio: f32,
+    /// Shorter speech segments are dropped (clicks, keyboard noise)
+    pub min_speech_duration_ms: u32,
+    /// Shorter pauses don't split a speech segment
+    pub min_silence_duration_ms: u32,
+    /// Audio kept around speech so word onsets and endings are not clipped
+    pub speech_pad_ms: u32,
+    /// Padded speech segments closer than this are kept as one
+    pub merge_gap_ms: u32,
+}
+
+impl Default for VadSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 0.3,
+            chunk_size: 512,
+            keep_all_speech_ratio: 0.3,
+            min_speech_duration_ms: 100,
+            min_silence_duration_ms: 300,
+            speech_pad_ms: 150,
+            merge_gap_ms: 200,
+        }
+    }
+}
+
+impl VadSettings {
+    /// Check the values the Silero model can work with
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.threshold) {
+            return Err(format!("Invalid threshold: {}. Must be between 0.0 and 1.0", self.threshold));
+        }
+        if ![512, 1024, 1536].contains(&self.chunk_size) {
+            return Err(format!(
+                "Invalid VAD chunk size: {}. Must be 512, 1024, or 1536 samples",
+                self.chunk_size
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.keep_all_speech_ratio) {
+            return Err(format!(
+                "Invalid speech ratio: {}. Must be between 0.0 and 1.0",
+                self.keep_all_speech_ratio
+            ));
+        }
+        Ok(())
+    }
+
+    /// `speech_pad_ms` in 16kHz samples
+    pub fn speech_pad_samples(&self) -> usize {
+        self.speech_pad_ms as usize * 16
+    }
+
+    /// `merge_gap_ms` in 16kHz samples
+    pub fn merge_gap_samples(&self) -> usize {
+        self.merge_gap_ms as usize * 16
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppSettings {
     pub hotkey: String,
+    /// ISO 639-1 code, or "auto" to detect the language among `allowed_languages`
     pub language: String,
+    /// Languages "auto" can pick from, so accented speech is not taken for another language
+    #[serde(default = "default_allowed_languages")]
+    pub allowed_languages: Vec<String>,
+    /// Shortcut cycling the language of the next recording through `allowed_languages`
+    #[serde(default)]
+    pub language_switch_key: Option<String>,
     pub auto_paste: bool,
     pub model_name: String,
     pub push_to_talk: bool,
     pub cancel_key: String,
     pub device_name: Option<String>,
+    /// Devices to try, in order, when `device_name` is unavailable (before the default device)
     #[serde(default)]
+    pub fallback_devices: Vec<String>,
+    /// Record a single channel (0-based) of the input device instead of downmixing all channels
+    #[serde(default)]
+    pub input_channel: Option<u16>,
+    /// Former flat word list, moved to the default vocabulary list on load
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub custom_words: Vec<String>,
+    /// Named word lists for the Whisper prompt, by mode and language
+    #[serde(default)]
+    pub vocabulary_lists: Vec<VocabularyList>,
     #[serde(default)]
     pub llm_models: Vec<LlmModel>,
     #[serde(default)]
     pub execution_modes: Vec<ExecutionMode>,
     #[serde(default = "default_active_mode")]
     pub active_mode: String,
+    /// Keep the microphone open between recordings so speech right at the hotkey press is not clipped
+    #[serde(default)]
+    pub keep_microphone_warm: bool,
+    /// Audio kept from before the hotkey press when the microphone is kept warm
+    #[serde(default = "default_pre_roll_ms")]
+    pub pre_roll_ms: u32,
+    /// Release the warm microphone after this many seconds without recording
+    #[serde(default = "default_microphone_idle_timeout_secs")]
+    pub microphone_idle_timeout_secs: u64,
+    #[serde(default)]
+    pub preprocessing: PreprocessingSettings,
+    #[serde(default)]
+    pub vad: VadSettings,
+    #[serde(default)]
+    pub system_audio: SystemAudioMode,
+    /// Monitor device to record the system audio from (None means the default output)
+    #[serde(default)]
+    pub system_audio_device: Option<String>,
+    /// Stop a recording automatically after this many seconds (0 means no limit)
+    #[serde(default = "default_max_recording_secs")]
+    pub max_recording_secs: u64,
+    /// Move recordings longer than this many seconds to a WAV file on disk (0 keeps them in memory)
+    #[serde(default = "default_spool_after_secs")]
+    pub spool_after_secs: u64,
+    /// Toggle mode only: stop the recording by itself when the user stops speaking
+    #[serde(default)]
+    pub auto_stop: bool,
+    /// Silence after speech that ends an auto-stop recording
+    #[serde(default = "default_auto_stop_silence_ms")]
+    pub auto_stop_silence_ms: u32,
+    /// Cancel an auto-stop recording if nobody speaks for this long (0 waits forever)
+    #[serde(default = "default_no_speech_timeout_ms")]
+    pub no_speech_timeout_ms: u32,
+    /// Toggle mode only: transcribe and insert each utterance while the recording goes on
+    #[serde(default)]
+    pub continuous_dictation: bool,
+    /// Pause that ends an utterance in continuous dictation
+    #[serde(default = "default_utterance_silence_ms")]
+    pub utterance_silence_ms: u32,
+    /// JSON manifest listing the downloadable models (None uses the one bundled with the app)
+    #[serde(default)]
+    pub model_manifest_url: Option<String>,
+    /// Whisper decoding of the modes that don't set their own
+    #[serde(default)]
+    pub decode: WhisperDecodeOptions,
+    /// Whisper initial prompt by language code, with the `{words}`, `{previous}` and `{style}` placeholders
+    #[serde(default = "crate::transcription::prompt::default_templates")]
+    pub prompt_templates: BTreeMap<String, String>,
+}
+
+fn default_allowed_languages() -> Vec<String> {
+    vec![String::from("fr"), String::from("en")]
 }
 
 fn default_active_mode() -> String {
     String::from("standard")
 }
 
+fn default_pre_roll_ms() -> u32 {
+    300
+}
+
+fn default_microphone_idle_timeout_secs() -> u64 {
+    300
+}
+
+fn default_max_recording_secs() -> u64 {
+    900
+}
+
+fn default_spool_after_secs() -> u64 {
+    60
+}
+
+fn default_auto_stop_silence_ms() -> u32 {
+    1200
+}
+
+fn default_no_speech_timeout_ms() -> u32 {
+    8000
+}
+
+fn default_utterance_silence_ms() -> u32 {
+    700
+}
+
 impl Default for AppSettings {
     fn default() -> Self {
         Self {
             hotkey: String::from("Ctrl+Shift+R"),
             language: String::from("fr"),
+            allowed_languages: default_allowed_languages(),
+            language_switch_key: None,
             auto_paste: true,
             model_name: String::from("ggml-small-q5_1.bin"),
             push_to_talk: false, // Default to toggle mode
             cancel_key: String::from("Escape"),
             device_name: None, // None means use default device
-            custom_words: vec![
-                String::from("Aymeric Bellavoine"),
-                String::from("PPAT"),
-                String::from("Harmonie Mutuelle"),
-            ],
+            fallback_devices: Vec::new(),
+            input_channel: None, // None means downmix all channels
+            custom_words: Vec::new(),
+            vocabulary_lists: Vec::new(),
             llm_models: vec![],
             execution_modes: vec![ExecutionMode {
                 id: String::from("standard"),
                 name: String::from("Standard"),
                 llm_model_id: None,
                 system_prompt: String::new(),
+                decode: None,
+                style_hint: String::new(),
             }],
             active_mode: String::from("standard"),
+            keep_microphone_warm: false,
+            pre_roll_ms: default_pre_roll_ms(),
+            microphone_idle_timeout_secs: default_microphone_idle_timeout_secs(),
+            preprocessing: PreprocessingSettings::default(),
+            vad: VadSettings::default(),
+            system_audio: SystemAudioMode::Off,
+            system_audio_device: None,
+            max_recording_secs: default_max_recording_secs(),
+            spool_after_secs: default_spool_after_secs(),
+            auto_stop: false,
+            auto_stop_silence_ms: default_auto_stop_silence_ms(),
+            no_speech_timeout_ms: default_no_speech_timeout_ms(),
+            continuous_dictation: false,
+            utterance_silence_ms: default_utterance_silence_ms(),
+            model_manifest_url: None,
+            decode: WhisperDecodeOptions::default(),
+            prompt_templates: crate::transcription::prompt::default_templates(),
         }
     }
 }
 
 impl AppSettings {
+    /// Keep-warm configuration for the recorder, if enabled
+    pub fn keep_warm(&self) -> Option<crate::audio::KeepWarm> {
+        self.keep_microphone_warm.then(|| crate::audio::KeepWarm {
+            pre_roll_ms: self.pre_roll_ms,
+            idle_timeout: std::time::Duration::from_secs(self.microphone_idle_timeout_secs),
+        })
+    }
+
+    /// Spooling configuration for the recorder, if enabled
+    pub fn spool(&self) -> Option<crate::audio::SpoolSettings> {
+        if self.spool_after_secs == 0 {
+            return None;
+        }
+        let dir = crate::audio::spool::spool_dir().ok()?;
+        Some(crate::audio::SpoolSettings {
+            dir,
+            after_samples: self.spool_after_secs as usize * 16000,
+        })
+    }
+
+    /// Longest recording before it is stopped automatically, if limited
+    /// (a continuous dictation is transcribed as it goes, so it can last)
+    pub fn max_recording_duration(&self) -> Option<std::time::Duration> {
+        (self.max_recording_secs > 0 && self.dictation_silence_ms().is_none())
+            .then(|| std::time::Duration::from_secs(self.max_recording_secs))
+    }
+
+    /// End-of-speech detection for auto-stop recordings, if enabled
+    pub fn endpointing(&self) -> Option<crate::audio::EndpointSettings> {
+        (self.auto_stop && self.dictation_silence_ms().is_none() && !self.push_to_talk).then(|| crate::audio::EndpointSettings {
+            trailing_silence_ms: self.auto_stop_silence_ms,
+            no_speech_timeout_ms: self.no_speech_timeout_ms,
+        })
+    }
+
+    /// Pause that ends an utterance, if recordings are continuous dictations
+    pub fn dictation_silence_ms(&self) -> Option<u32> {
+        (self.continuous_dictation && !self.push_to_talk).then_some(self.utterance_silence_ms)
+    }
+
+    /// Language recordings are transcribed in
+    pub fn language_selection(&self) -> crate::transcription::LanguageSelection {
+        crate::transcription::LanguageSelection::from_setting(&self.language, &self.allowed_languages)
+    }
+
+    /// Whisper decoding of the active execution mode
+    pub fn decode_options(&self) -> WhisperDecodeOptions {
+        self.execution_modes
+            .iter()
+            .find(|mode| mode.id == self.active_mode)
+            .and_then(|mode| mode.decode.clone())
+            .unwrap_or_else(|| self.decode.clone())
+    }
+
+    /// Initial prompt parts of the active execution mode, after `previous_text` if any
+    pub fn initial_prompt(&self, previous_text: Option<String>) -> crate::transcription::InitialPrompt {
+        crate::transcription::InitialPrompt {
+            templates: self.prompt_templates.clone(),
+            custom_words: vocabulary::prompt_words(&self.vocabulary_lists, &self.active_mode),
+            previous_text,
+            style: self
+                .execution_modes
+                .iter()
+                .find(|mode| mode.id == self.active_mode)
+                .map(|mode| mode.style_hint.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The list of the words added one at a time, created if missing
+    pub fn default_vocabulary_list(&mut self) -> &mut VocabularyList {
+        let index = match self.vocabulary_lists.iter().position(|list| list.id == vocabulary::DEFAULT_LIST_ID) {
+            Some(index) => index,
+            None => {
+                let list = VocabularyList::new(vocabulary::DEFAULT_LIST_ID.to_string(), String::from("Mots personnalisés"));
+                self.vocabulary_lists.insert(0, list);
+                0
+            }
+        };
+        &mut self.vocabulary_lists[index]
+    }
+
+    /// Move the flat `custom_words` to the default vocabulary list, returns whether there were any
+    pub fn migrate_custom_words(&mut self) -> bool {
+        if self.custom_words.is_empty() {
+            return false;
+        }
+        let words = std::mem::take(&mut self.custom_words);
+        let list = self.default_vocabulary_list();
+        for word in &words {
+            list.add_term(word, 1);
+        }
+        println!("Moved {} custom words to the default vocabulary list", words.len());
+        true
+    }
+
+    /// Check if the recorder must be rebuilt to apply `self` over `previous`
+    pub fn audio_input_changed(&self, previous: &AppSettings) -> bool {
+        self.device_name != previous.device_name
+            || self.fallback_devices != previous.fallback_devices
+            || self.input_channel != previous.input_channel
+            || self.keep_warm() != previous.keep_warm()
+            || self.preprocessing != previous.preprocessing
+            || self.system_audio != previous.system_audio
+            || self.system_audio_device != previous.system_audio_device
+            || self.spool_after_secs != previous.spool_after_secs
+    }
+
+    /// Input devices to try in order: the selected device then the fallbacks.
+    /// Empty means the default device.
+    pub fn preferred_devices(&self, device_name: Option<&str>) -> Vec<String> {
+        let mut devices: Vec<String> = device_name.map(String::from).into_iter().collect();
+        for fallback in &self.fallback_devices {
+            if !devices.contains(fallback) {
+                devices.push(fallback.clone());
+            }
+        }
+        devices
+    }
+
     /// Get the path to the settings file
     fn settings_path() -> Result<PathBuf, String> {
         let mut path = dirs::data_dir()
@@ -161,11 +585,8 @@ impl AppSettings {
             }
         }
 
-        // If custom_words is empty and this is a fresh migration, initialize with defaults
-        if settings.custom_words.is_empty() {
-            let defaults = Self::default();
-            settings.custom_words = defaults.custom_words;
-            println!("Initialized custom_words with defaults");
+        // Move the flat custom words to the default vocabulary list
+        if settings.migrate_custom_words() {
             needs_save = true;
         }
 
@@ -180,6 +601,8 @@ impl AppSettings {
                 name: String::from("Standard"),
                 llm_model_id: None,
                 system_prompt: String::new(),
+                decode: None,
+                style_hint: String::new(),
             });
             println!("Initialized standard execution mode");
             let _ = settings.save();
@@ -189,6 +612,20 @@ impl AppSettings {
         Ok(settings)
     }
 
+    /// These settings with the fields of `changes` (a JSON object) replaced, so
+    /// a caller that only knows some of the fields leaves the others as they are
+    pub fn with_changes(&self, changes: serde_json::Value) -> Result<Self, String> {
+        let serde_json::Value::Object(changes) = changes else {
+            return Err("Settings must be a JSON object".to_string());
+        };
+        let mut merged = serde_json::to_value(self)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+        if let serde_json::Value::Object(fields) = &mut merged {
+            fields.extend(changes);
+        }
+        serde_json::from_value(merged).map_err(|e| format!("Invalid settings: {}", e))
+    }
+
     /// Save settings to disk
     pub fn save(&self) -> Result<(), String> {
         let path = Self::settings_path()?;
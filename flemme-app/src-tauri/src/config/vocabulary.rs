@@ -0,0 +1,292 @@
+// Vocabulary - named lists of words Whisper is biased towards through its initial prompt,
+// attached to execution modes and languages, imported from and exported to TXT/CSV files
+use crate::transcription::prompt::PromptWord;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// List of the words added one at a time (formerly the flat `custom_words` setting)
+pub const DEFAULT_LIST_ID: &str = "default";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VocabularyTerm {
+    pub text: String,
+    /// Importance within the list: heavier terms are kept first when the prompt is full
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VocabularyList {
+    pub id: String,
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Terms of higher priority lists go in the prompt first
+    #[serde(default)]
+    pub priority: i32,
+    /// Execution mode ids the list is used in, empty for all modes
+    #[serde(default)]
+    pub modes: Vec<String>,
+    /// Language codes the list is used for, empty for all languages
+    #[serde(default)]
+    pub languages: Vec<String>,
+    #[serde(default)]
+    pub terms: Vec<VocabularyTerm>,
+}
+
+impl VocabularyList {
+    pub fn new(id: String, name: String) -> Self {
+        Self {
+            id,
+            name,
+            enabled: true,
+            priority: 0,
+            modes: Vec::new(),
+            languages: Vec::new(),
+            terms: Vec::new(),
+        }
+    }
+
+    /// Add a term, unless the list already has it (whatever the case)
+    pub fn add_term(&mut self, text: &str, weight: u32) -> bool {
+        let text = text.trim();
+        if text.is_empty() || self.terms.iter().any(|term| term.text.to_lowercase() == text.to_lowercase()) {
+            return false;
+        }
+        self.terms.push(VocabularyTerm { text: text.to_string(), weight });
+        true
+    }
+
+    /// Remove a term, whatever its case, like `add_term` finds duplicates
+    pub fn remove_term(&mut self, text: &str) -> bool {
+        let text = text.trim().to_lowercase();
+        let count = self.terms.len();
+        self.terms.retain(|term| term.text.to_lowercase() != text);
+        self.terms.len() != count
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Vocabulary list name cannot be empty".to_string());
+        }
+        if let Some(language) = self
+            .languages
+            .iter()
+            .find(|language| !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_lowercase()))
+        {
+            return Err(format!("Invalid language code in vocabulary list '{}': '{}'", self.name, language));
+        }
+        if self.terms.iter().any(|term| term.text.trim().is_empty()) {
+            return Err(format!("Vocabulary list '{}' has an empty term", self.name));
+        }
+        Ok(())
+    }
+
+    fn applies_to_mode(&self, mode_id: &str) -> bool {
+        self.enabled && (self.modes.is_empty() || self.modes.iter().any(|mode| mode == mode_id))
+    }
+}
+
+/// Words of the lists used in a mode, most important first: by list priority,
+/// then by weight, then in list order
+pub fn prompt_words(lists: &[VocabularyList], mode_id: &str) -> Vec<PromptWord> {
+    let mut lists: Vec<&VocabularyList> = lists.iter().filter(|list| list.applies_to_mode(mode_id)).collect();
+    lists.sort_by_key(|list| std::cmp::Reverse(list.priority));
+
+    lists
+        .into_iter()
+        .flat_map(|list| {
+            let mut terms: Vec<&VocabularyTerm> = list.terms.iter().collect();
+            terms.sort_by_key(|term| std::cmp::Reverse(term.weight));
+            terms.into_iter().map(|term| PromptWord {
+                text: term.text.clone(),
+                languages: list.languages.clone(),
+            })
+        })
+        .collect()
+}
+
+/// File format of a vocabulary list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VocabularyFormat {
+    /// One term a line, `#` starts a comment
+    Txt,
+    /// `term,weight` rows (or `;`-separated), the weight being optional
+    Csv,
+}
+
+impl VocabularyFormat {
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+            Some("txt") => Ok(Self::Txt),
+            Some("csv") => Ok(Self::Csv),
+            _ => Err(format!("Unsupported vocabulary file {:?}. Use a .txt or .csv file", path)),
+        }
+    }
+}
+
+/// Terms of a TXT or CSV vocabulary file
+pub fn parse_terms(contents: &str, format: VocabularyFormat) -> Result<Vec<VocabularyTerm>, String> {
+    let lines = contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+
+    match format {
+        VocabularyFormat::Txt => Ok(lines
+            .filter(|(_, line)| !line.starts_with('#'))
+            .map(|(_, line)| VocabularyTerm { text: line.to_string(), weight: default_weight() })
+            .collect()),
+        VocabularyFormat::Csv => {
+            // Spreadsheets in French locales separate the columns with semicolons
+            let separator = match contents.lines().find(|line| !line.trim().is_empty()) {
+                Some(line) if line.contains(';') && !line.contains(',') => ';',
+                _ => ',',
+            };
+            let mut terms = Vec::new();
+            for (number, line) in lines {
+                let cells = split_csv_line(line, separator);
+                let text = cells[0].trim();
+                let weight = cells.get(1).map(|cell| cell.trim()).filter(|cell| !cell.is_empty());
+                if terms.is_empty() && ["term", "word", "mot", "terme"].contains(&text.to_lowercase().as_str()) {
+                    continue; // Header
+                }
+                if text.is_empty() {
+                    continue;
+                }
+                let weight = match weight {
+                    Some(weight) => weight
+                        .parse()
+                        .map_err(|_| format!("Invalid weight '{}' on line {}", weight, number))?,
+                    None => default_weight(),
+                };
+                terms.push(VocabularyTerm { text: text.to_string(), weight });
+            }
+            Ok(terms)
+        }
+    }
+}
+
+/// A TXT or CSV vocabulary file of the terms
+pub fn format_terms(terms: &[VocabularyTerm], format: VocabularyFormat) -> String {
+    match format {
+        VocabularyFormat::Txt => terms.iter().map(|term| format!("{}\n", term.text)).collect(),
+        VocabularyFormat::Csv => {
+            let mut csv = String::from("term,weight\n");
+            for term in terms {
+                if term.text.contains([',', '"', ';']) {
+                    csv.push_str(&format!("\"{}\",{}\n", term.text.replace('"', "\"\""), term.weight));
+                } else {
+                    csv.push_str(&format!("{},{}\n", term.text, term.weight));
+                }
+            }
+            csv
+        }
+    }
+}
+
+/// Read the terms of a vocabulary file, in the format of its extension
+pub fn import_file(path: &Path) -> Result<Vec<VocabularyTerm>, String> {
+    let format = VocabularyFormat::from_path(path)?;
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read vocabulary file {:?}: {}", path, e))?;
+    parse_terms(contents.trim_start_matches('\u{feff}'), format)
+}
+
+pub fn export_file(list: &VocabularyList, path: &Path) -> Result<(), String> {
+    let format = VocabularyFormat::from_path(path)?;
+    std::fs::write(path, format_terms(&list.terms, format))
+        .map_err(|e| format!("Failed to write vocabulary file {:?}: {}", path, e))
+}
+
+/// Cells of a CSV line, with double-quoted cells that may hold the separator
+fn split_csv_line(line: &str, separator: char) -> Vec<String> {
+    let mut cells = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cells.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => cells.push(String::new()),
+            c => cells.last_mut().unwrap().push(c),
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_words_order() {
+        let mut names = VocabularyList::new("names".to_string(), "Noms".to_string());
+        names.add_term("Aymeric", 1);
+        names.add_term("Harmonie Mutuelle", 5);
+        let mut medical = VocabularyList::new("medical".to_string(), "Médical".to_string());
+        medical.priority = 2;
+        medical.modes = vec!["mode_medical".to_string()];
+        medical.languages = vec!["fr".to_string()];
+        medical.add_term("anamnèse", 1);
+        let mut disabled = VocabularyList::new("old".to_string(), "Ancien".to_string());
+        disabled.enabled = false;
+        disabled.add_term("PPAT", 9);
+        let lists = [names, medical, disabled];
+
+        let words: Vec<String> = prompt_words(&lists, "mode_medical").into_iter().map(|word| word.text).collect();
+        assert_eq!(words, ["anamnèse", "Harmonie Mutuelle", "Aymeric"]);
+        let words: Vec<String> = prompt_words(&lists, "standard").into_iter().map(|word| word.text).collect();
+        assert_eq!(words, ["Harmonie Mutuelle", "Aymeric"]);
+        assert_eq!(prompt_words(&lists, "mode_medical")[0].languages, ["fr"]);
+    }
+
+    #[test]
+    fn test_add_term_ignores_case_duplicates() {
+        let mut list = VocabularyList::new(DEFAULT_LIST_ID.to_string(), "Mots personnalisés".to_string());
+        assert!(list.add_term(" PPAT ", 1));
+        assert!(!list.add_term("ppat", 3));
+        assert!(!list.add_term("  ", 1));
+        assert_eq!(list.terms, [VocabularyTerm { text: "PPAT".to_string(), weight: 1 }]);
+        assert!(list.remove_term("Ppat"));
+        assert!(list.terms.is_empty());
+        assert!(!list.remove_term("PPAT"));
+    }
+
+    #[test]
+    fn test_csv_and_txt_round_trip() {
+        let csv = "\
+Mot;Poids
+Harmonie Mutuelle;5
+\"Dupont; Durand\";2
+PPAT
+";
+        let terms = parse_terms(csv, VocabularyFormat::Csv).unwrap();
+        assert_eq!(
+            terms,
+            [
+                VocabularyTerm { text: "Harmonie Mutuelle".to_string(), weight: 5 },
+                VocabularyTerm { text: "Dupont; Durand".to_string(), weight: 2 },
+                VocabularyTerm { text: "PPAT".to_string(), weight: 1 },
+            ]
+        );
+        assert_eq!(parse_terms(&format_terms(&terms, VocabularyFormat::Csv), VocabularyFormat::Csv).unwrap(), terms);
+        assert!(parse_terms("PPAT,lots", VocabularyFormat::Csv).is_err());
+
+        let txt = "# Clients\nHarmonie Mutuelle\n\nPPAT\n";
+        let terms = parse_terms(txt, VocabularyFormat::Txt).unwrap();
+        assert_eq!(terms.iter().map(|term| term.text.as_str()).collect::<Vec<_>>(), ["Harmonie Mutuelle", "PPAT"]);
+        assert_eq!(format_terms(&terms, VocabularyFormat::Txt), "Harmonie Mutuelle\nPPAT\n");
+    }
+}
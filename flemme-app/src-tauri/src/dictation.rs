@@ -0,0 +1,144 @@
+// Continuous dictation - transcribes each utterance while the user keeps talking
+// and inserts the texts in the order they were spoken
+use crate::clipboard::ClipboardManager;
+use crate::transcription::LanguageSelection;
+use crate::{audio, config, TranscriptionCommand};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use tauri::{AppHandle, Emitter, Manager};
+
+#[derive(Clone, serde::Serialize)]
+struct DictationUtteranceEvent {
+    index: usize,
+    text: String,
+}
+
+/// Texts of a dictation so far
+#[derive(Default)]
+struct DictationOutput {
+    inserted: usize,
+    // Everything dictated so far, copied as a whole at the end when auto-paste is off
+    text: String,
+}
+
+impl DictationOutput {
+    /// Add the text of an utterance, returned with the space separating it from the previous one
+    fn push(&mut self, text: &str) -> String {
+        let text = if self.inserted > 0 { format!(" {}", text) } else { text.to_string() };
+        self.text.push_str(&text);
+        self.inserted += 1;
+        text
+    }
+}
+
+/// One continuous dictation: a worker thread transcribes the utterances one
+/// after the other and inserts each text at the cursor as soon as it is ready
+pub struct DictationSession {
+    utterances: Sender<Vec<f32>>,
+    worker: JoinHandle<()>,
+    app: AppHandle,
+}
+
+impl DictationSession {
+    /// Start the worker, with the settings and language of the whole session
+    pub fn new(transcription_tx: Sender<TranscriptionCommand>, app: AppHandle, language: LanguageSelection) -> Self {
+        let settings = config::AppSettings::load().unwrap_or_default();
+        let (utterances, utterances_rx) = mpsc::channel();
+        let worker_app = app.clone();
+        let worker = thread::spawn(move || {
+            transcribe_utterances(utterances_rx, &transcription_tx, &worker_app, &settings, &language)
+        });
+        Self { utterances, worker, app }
+    }
+
+    /// Queue an utterance for transcription
+    pub fn submit(&mut self, audio: Vec<f32>) {
+        println!("Dictation: utterance ({:.1}s)", audio.len() as f32 / 16000.0);
+        if self.utterances.send(audio).is_err() {
+            eprintln!("Dictation: worker stopped, utterance dropped");
+        }
+    }
+
+    /// No more utterances: hide the indicator once the queued ones are inserted
+    pub fn finish(self) {
+        let app = self.app;
+        let worker = self.worker;
+        drop(self.utterances);
+        thread::spawn(move || {
+            let _ = worker.join();
+            println!("Dictation finished");
+            let _ = app.emit("dictation-finished", ());
+            if let Some(window) = app.get_webview_window("indicator") {
+                let _ = window.hide();
+            }
+        });
+    }
+}
+
+/// Transcribe the utterances in the order they were spoken until the session
+/// ends, each with the previous one as context
+fn transcribe_utterances(
+    utterances: Receiver<Vec<f32>>,
+    transcription_tx: &Sender<TranscriptionCommand>,
+    app: &AppHandle,
+    settings: &config::AppSettings,
+    language: &LanguageSelection,
+) {
+    let mut output = DictationOutput::default();
+    let mut previous_text: Option<String> = None;
+
+    for (sequence, mut audio) in utterances.into_iter().enumerate() {
+        audio::processing::prepare_for_transcription(&mut audio, &settings.preprocessing);
+
+        let result = crate::request_transcription(transcription_tx, audio, previous_text.clone(), language.clone());
+        let text = result.map(|result| result.text).unwrap_or_else(|e| {
+            eprintln!("Dictation: transcription of utterance {} failed: {}", sequence, e);
+            String::new()
+        });
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        previous_text = Some(text.to_string());
+
+        let index = output.inserted;
+        let text = output.push(&crate::apply_execution_mode(text.to_string()));
+        if settings.auto_paste {
+            insert_text(&text, true);
+        }
+        let _ = app.emit("dictation-utterance", DictationUtteranceEvent { index, text });
+    }
+
+    // Copied once, rather than after every utterance
+    if !settings.auto_paste && !output.text.is_empty() {
+        insert_text(&output.text, false);
+    }
+}
+
+/// Paste at the cursor, or only copy when auto-paste is off
+fn insert_text(text: &str, auto_paste: bool) {
+    let result = ClipboardManager::new().and_then(|clipboard| {
+        if auto_paste {
+            clipboard.auto_paste(text)
+        } else {
+            clipboard.copy_text(text)
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("Dictation: failed to insert text: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_separates_utterances() {
+        let mut output = DictationOutput::default();
+        assert_eq!(output.push("Bonjour."), "Bonjour.");
+        assert_eq!(output.push("On se voit lundi."), " On se voit lundi.");
+        assert_eq!(output.text, "Bonjour. On se voit lundi.");
+        assert_eq!(output.inserted, 2);
+    }
+}
@@ -4,14 +4,15 @@ pub mod transcription;
 pub mod hotkey;
 pub mod clipboard;
 pub mod config;
+pub mod dictation;
 pub mod llm;
 
-use audio::{AudioRecorder, VoiceActivityDetector};
+use audio::{AudioChannel, AudioRecorder, AudioSource, DualAudioSource, Endpoint, EndpointSettings, Endpointer, EnergyVad, Utterance, UtteranceSegmenter, Vad, VoiceActivityDetector};
 use transcription::engine::TranscriptionEngine;
 use clipboard::ClipboardManager;
 use hotkey::HotkeyListener;
-use config::settings::{LlmModel, ExecutionMode, LlmServiceType};
-use std::sync::mpsc::{self, Receiver, Sender};
+use config::settings::{LlmModel, ExecutionMode, LlmServiceType, SystemAudioMode};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::thread;
 use tauri::{AppHandle, Emitter, Manager, State};
 
@@ -19,7 +20,23 @@ use tauri::{AppHandle, Emitter, Manager, State};
 pub enum AudioCommand {
     StartRecording,
     StopRecording { reply: Sender<Result<Vec<f32>, String>> },
+    /// Stop and keep the channels of the source apart (microphone and system audio)
+    StopRecordingChannels { reply: Sender<Result<Vec<AudioChannel>, String>> },
     IsRecording { reply: Sender<bool> },
+    /// Rebuild the recorder on another device (`None` = default device).
+    /// Replies with the name of the device actually opened.
+    SetDevice {
+        device_name: Option<String>,
+        reply: Sender<Result<String, String>>,
+    },
+    /// Change the duration after which a recording is stopped automatically
+    SetMaxDuration { max_duration: Option<std::time::Duration> },
+    /// Enable or disable stopping recordings at the end of speech
+    SetEndpointing { endpointing: Option<EndpointSettings> },
+    /// Enable or disable continuous dictation (pause that ends an utterance)
+    SetDictation { utterance_silence_ms: Option<u32> },
+    /// End a continuous dictation: transcribe the last utterance and drop the recording
+    StopDictation,
     Shutdown,
 }
 
@@ -27,8 +44,11 @@ pub enum AudioCommand {
 pub enum TranscriptionCommand {
     Transcribe {
         audio: Vec<f32>,
-        language: Option<String>,
-        reply: Sender<Result<String, String>>,
+        language: transcription::LanguageSelection,
+        /// Text said just before this audio, given to Whisper as context
+        prompt: Option<String>,
+        decode: config::WhisperDecodeOptions,
+        reply: Sender<Result<transcription::TranscriptionResult, String>>,
     },
     ReloadModel {
         model_path: String,
@@ -37,69 +57,707 @@ pub enum TranscriptionCommand {
     Shutdown,
 }
 
+/// How often the audio worker checks the device and lets an idle source release its resources
+const AUDIO_IDLE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How often the audio worker runs the VAD on new audio during an auto-stop recording
+/// or a continuous dictation
+const ENDPOINT_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// How long before the maximum recording duration the user is warned
+const RECORDING_LIMIT_WARNING: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Longest continuous dictation kept for `get_vad_timeline` (5 minutes at 16kHz)
+const MAX_KEPT_DICTATION_SAMPLES: usize = 5 * 60 * 16000;
+
+/// Creates the audio source for the selected device (`None` = default device),
+/// lazily on the first recording and again after a device change or failure
+type AudioSourceFactory = Box<dyn FnMut(Option<&str>) -> Result<Box<dyn AudioSource>, String> + Send>;
+
+#[derive(Clone, serde::Serialize)]
+struct DeviceChangedEvent {
+    device_name: String,
+    reason: String, // "selected", "reconnected" or "fallback"
+}
+
+#[derive(Clone, serde::Serialize)]
+struct DeviceErrorEvent {
+    device_name: Option<String>,
+    message: String,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct RecordingLimitEvent {
+    max_seconds: u64,
+    remaining_seconds: u64,
+}
+
 // Audio worker that runs in dedicated thread
 struct AudioWorker {
-    recorder: Option<AudioRecorder>,
+    source: Option<Box<dyn AudioSource>>,
+    factory: AudioSourceFactory,
+    // Device selected by the user (None = default device)
+    device_name: Option<String>,
+    // Audio recorded before the device failed in the middle of a recording
+    salvaged: Vec<AudioChannel>,
+    // Recordings are stopped automatically after this long
+    max_duration: Option<std::time::Duration>,
+    recording_started: Option<std::time::Instant>,
+    limit_warned: bool,
+    // Recording stopped at the maximum duration or end of speech, waiting to be collected
+    finished: Option<Result<Vec<AudioChannel>, String>>,
+    // Auto-stop: end-of-speech detection on the audio streamed by the source
+    endpointing: Option<EndpointSettings>,
+    endpointer: Option<Endpointer>,
+    // Continuous dictation: utterances cut from the streamed audio and transcribed while recording
+    utterance_silence_ms: Option<u32>,
+    segmenter: Option<UtteranceSegmenter>,
+    dictation: Option<dictation::DictationSession>,
+    // Audio of the dictation so far, dropped once longer than `MAX_KEPT_DICTATION_SAMPLES`
+    dictation_audio: Option<Vec<f32>>,
+    vad: Option<Box<dyn Vad>>,
+    // Set while auto-stop or dictation reads the audio streamed to `speech_tx`
+    speech_listening: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    speech_tx: Sender<Vec<f32>>,
+    speech_rx: Receiver<Vec<f32>>,
+    app: Option<AppHandle>,
     rx: Receiver<AudioCommand>,
 }
 
 impl AudioWorker {
+    /// Worker recording from the microphone and/or system audio configured in settings
     fn new(rx: Receiver<AudioCommand>) -> Self {
+        let settings = config::AppSettings::load().unwrap_or_default();
+        let mut worker = Self::with_factory(rx, Box::new(create_audio_source))
+            .with_max_duration(settings.max_recording_duration())
+            .with_endpointing(settings.endpointing())
+            .with_dictation(settings.dictation_silence_ms());
+        worker.device_name = settings.device_name;
+        worker
+    }
+
+    /// Worker using an already created source (file, in-memory fixture, ...)
+    #[cfg(test)]
+    fn with_source(rx: Receiver<AudioCommand>, source: Box<dyn AudioSource>) -> Self {
+        let mut worker = Self::with_factory(rx, Box::new(|_| Err("No audio source available".to_string())));
+        worker.source = Some(source);
+        worker
+    }
+
+    /// Worker creating its source on first use
+    fn with_factory(rx: Receiver<AudioCommand>, factory: AudioSourceFactory) -> Self {
+        let (speech_tx, speech_rx) = mpsc::channel();
         Self {
-            recorder: None,
+            source: None,
+            factory,
+            device_name: None,
+            salvaged: Vec::new(),
+            max_duration: None,
+            recording_started: None,
+            limit_warned: false,
+            finished: None,
+            endpointing: None,
+            endpointer: None,
+            utterance_silence_ms: None,
+            segmenter: None,
+            dictation: None,
+            dictation_audio: None,
+            vad: None,
+            speech_listening: Default::default(),
+            speech_tx,
+            speech_rx,
+            app: None,
             rx,
         }
     }
 
+    /// Stop recordings automatically after `max_duration`
+    fn with_max_duration(mut self, max_duration: Option<std::time::Duration>) -> Self {
+        self.max_duration = max_duration;
+        self
+    }
+
+    /// Stop recordings automatically when the user stops speaking
+    fn with_endpointing(mut self, endpointing: Option<EndpointSettings>) -> Self {
+        self.endpointing = endpointing;
+        self
+    }
+
+    /// Transcribe recordings utterance by utterance, cut at pauses of `utterance_silence_ms`
+    fn with_dictation(mut self, utterance_silence_ms: Option<u32>) -> Self {
+        self.utterance_silence_ms = utterance_silence_ms;
+        self
+    }
+
+    /// Report device changes and errors to the UI
+    fn with_app_handle(mut self, app: AppHandle) -> Self {
+        self.app = Some(app);
+        self
+    }
+
     fn run(mut self) {
+        // Open the microphone right away when it should be kept warm
+        if config::AppSettings::load().unwrap_or_default().keep_microphone_warm {
+            let _ = self.ensure_source();
+        }
+
         loop {
-            match self.rx.recv() {
-                Ok(AudioCommand::StartRecording) => {
-                    if self.recorder.is_none() {
-                        // Load settings to check if a specific device is configured
-                        let settings = config::AppSettings::load().unwrap_or_default();
+            let timeout = if self.endpointer.is_some() || self.segmenter.is_some() {
+                ENDPOINT_CHECK_INTERVAL
+            } else {
+                AUDIO_IDLE_CHECK_INTERVAL
+            };
+            let command = self.rx.recv_timeout(timeout);
 
-                        let recorder_result = if let Some(device_name) = settings.device_name {
-                            println!("Using configured audio device: {}", device_name);
-                            AudioRecorder::new_with_device(&device_name)
-                        } else {
-                            println!("Using default audio device");
-                            AudioRecorder::new()
-                        };
+            // Recover from a failed device before handling the command
+            self.check_device();
+            self.check_duration();
+            self.check_speech();
 
-                        match recorder_result {
-                            Ok(rec) => self.recorder = Some(rec),
-                            Err(e) => eprintln!("Failed to create recorder: {}", e),
+            match command {
+                Ok(AudioCommand::StartRecording) => {
+                    self.retry_selected_device();
+
+                    // Audio streamed at the end of the previous recording
+                    while self.speech_rx.try_recv().is_ok() {}
+                    // Before starting, for the pre-roll streamed when the recording begins
+                    self.set_speech_listening(self.utterance_silence_ms.is_some() || self.endpointing.is_some());
+
+                    match self.start_source() {
+                        Ok(()) => {
+                            self.recording_started = Some(std::time::Instant::now());
+                            self.limit_warned = false;
+                            self.finished = None;
+                            self.start_speech_detection();
                         }
-                    }
-
-                    if let Some(ref mut recorder) = self.recorder {
-                        if let Err(e) = recorder.start_recording() {
+                        Err(e) => {
                             eprintln!("Failed to start recording: {}", e);
+                            self.set_speech_listening(false);
                         }
                     }
                 }
                 Ok(AudioCommand::StopRecording { reply }) => {
-                    let result = if let Some(ref mut recorder) = self.recorder {
-                        recorder.stop_recording()
-                    } else {
-                        Err("No recorder initialized".to_string())
-                    };
-                    let _ = reply.send(result);
+                    let _ = reply.send(self.take_recording().map(audio::source::mix_channels));
+                }
+                Ok(AudioCommand::StopRecordingChannels { reply }) => {
+                    let _ = reply.send(self.take_recording());
+                }
+                Ok(AudioCommand::SetMaxDuration { max_duration }) => {
+                    self.max_duration = max_duration;
+                }
+                Ok(AudioCommand::SetEndpointing { endpointing }) => {
+                    self.endpointing = endpointing;
+                }
+                Ok(AudioCommand::SetDictation { utterance_silence_ms }) => {
+                    self.utterance_silence_ms = utterance_silence_ms;
+                }
+                Ok(AudioCommand::StopDictation) => {
+                    self.stop_dictation();
+                }
+                Ok(AudioCommand::SetDevice { device_name, reply }) => {
+                    let _ = reply.send(self.switch_device(device_name));
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(ref mut source) = self.source {
+                        if !source.is_active() {
+                            source.on_idle();
+                        }
+                    }
                 }
                 Ok(AudioCommand::IsRecording { reply }) => {
-                    let is_recording = self
-                        .recorder
-                        .as_ref()
-                        .map_or(false, |r| r.is_recording());
+                    let is_recording = !self.salvaged.is_empty()
+                        || self.source.as_ref().is_some_and(|s| s.is_active());
                     let _ = reply.send(is_recording);
                 }
-                Ok(AudioCommand::Shutdown) | Err(_) => {
+                Ok(AudioCommand::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
                     break;
                 }
             }
         }
     }
+
+    /// The recording stopped at the maximum duration or end of speech, or the one in progress
+    fn take_recording(&mut self) -> Result<Vec<AudioChannel>, String> {
+        match self.finished.take() {
+            Some(result) => result,
+            None => self.stop_source(),
+        }
+    }
+
+    /// Stop the recording, including the audio salvaged from a failed device
+    fn stop_source(&mut self) -> Result<Vec<AudioChannel>, String> {
+        self.set_speech_listening(false);
+        self.recording_started = None;
+        self.endpointer = None;
+        self.end_dictation();
+        let result = if let Some(ref mut source) = self.source {
+            source.stop_and_drain_channels()
+        } else {
+            Err("No recorder initialized".to_string())
+        };
+
+        // Tell the user when the recording was too quiet or clipped
+        if result.is_ok() {
+            if let Some(warning) = self.source.as_ref().and_then(|s| s.level_warning()) {
+                self.emit("audio-level-warning", warning);
+            }
+        }
+
+        self.prepend_salvaged(result)
+    }
+
+    /// Stop the recording and throw its audio away (the sample listener got all of it)
+    fn cancel_source(&mut self) {
+        self.recording_started = None;
+        self.endpointer = None;
+        self.salvaged.clear();
+        if let Some(ref mut source) = self.source {
+            if let Err(e) = source.cancel() {
+                eprintln!("Failed to stop recording: {}", e);
+            }
+        }
+        // A dictation being stopped still cuts its last utterance from the tail
+        if self.segmenter.is_none() {
+            self.set_speech_listening(false);
+        }
+    }
+
+    /// Stream the recorded audio to `speech_rx` only while auto-stop or dictation reads it
+    fn set_speech_listening(&self, listening: bool) {
+        self.speech_listening.store(listening, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Create the source if needed
+    fn ensure_source(&mut self) -> Result<(), String> {
+        if self.source.is_none() {
+            self.open_source(None)?;
+        }
+        Ok(())
+    }
+
+    /// Start recording, on the next device of the fallback chain if the current
+    /// one fails to start (e.g. unplugged while the microphone was not kept warm)
+    fn start_source(&mut self) -> Result<(), String> {
+        self.ensure_source()?;
+        let source = self.source.as_mut().ok_or_else(|| "No recorder initialized".to_string())?;
+        let error = match source.start() {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+
+        let failed_device = source.device_name();
+        eprintln!("Failed to start recording on {:?}: {}", failed_device, error);
+        self.source = None;
+        self.emit_device_error(failed_device, error);
+
+        self.open_source(Some("reconnected"))?;
+        self.source
+            .as_mut()
+            .ok_or_else(|| "No recorder initialized".to_string())?
+            .start()
+    }
+
+    /// Go back to the selected device if it is available again after recordings
+    /// on a fallback device
+    fn retry_selected_device(&mut self) {
+        let Some(selected) = self.device_name.clone() else {
+            return;
+        };
+        let Some(source) = self.source.as_ref() else {
+            return;
+        };
+        if source.is_active() || source.device_name().as_deref() == Some(selected.as_str()) {
+            return;
+        }
+
+        // The factory falls back again while the selected device is still missing
+        if let Ok(candidate) = (self.factory)(Some(&selected)) {
+            if candidate.device_name().as_deref() == Some(selected.as_str()) {
+                self.source = None;
+                self.install_source(candidate, Some("reconnected"));
+            }
+        }
+    }
+
+    /// Create the source for the selected device (or a fallback) and let it get
+    /// ready for the next recording. Returns the name of the device opened.
+    ///
+    /// `reason` is reported in the `device-changed` event; a plain first open
+    /// (`None`) is only reported when a fallback device had to be used.
+    fn open_source(&mut self, reason: Option<&str>) -> Result<String, String> {
+        match (self.factory)(self.device_name.as_deref()) {
+            Ok(source) => Ok(self.install_source(source, reason)),
+            Err(e) => {
+                self.emit_device_error(self.device_name.clone(), e.clone());
+                Err(e)
+            }
+        }
+    }
+
+    /// Use a new source: listeners, preparation and `device-changed` event
+    fn install_source(&mut self, mut source: Box<dyn AudioSource>, reason: Option<&str>) -> String {
+        // Recorded audio for end-of-speech detection and continuous dictation
+        let speech_tx = self.speech_tx.clone();
+        let speech_listening = self.speech_listening.clone();
+        source.set_sample_listener(std::sync::Arc::new(move |samples: &[f32]| {
+            if speech_listening.load(std::sync::atomic::Ordering::Relaxed) {
+                let _ = speech_tx.send(samples.to_vec());
+            }
+        }));
+
+        // Live input level for the indicator window
+        if let Some(ref app) = self.app {
+            let app = app.clone();
+            source.set_level_listener(std::sync::Arc::new(move |level| {
+                let _ = app.emit("audio-level", level);
+            }));
+        }
+
+        if let Err(e) = source.prepare() {
+            eprintln!("Failed to prepare audio source: {}", e);
+        }
+
+        let device_name = source.device_name().unwrap_or_default();
+        let reason = match self.device_name.as_deref() {
+            Some(selected) if selected != device_name => Some("fallback"),
+            _ => reason,
+        };
+        self.source = Some(source);
+
+        if let Some(reason) = reason {
+            println!("Audio device {}: {}", reason, device_name);
+            self.emit(
+                "device-changed",
+                DeviceChangedEvent {
+                    device_name: device_name.clone(),
+                    reason: reason.to_string(),
+                },
+            );
+        }
+
+        device_name
+    }
+
+    /// Rebuild the source on another device
+    fn switch_device(&mut self, device_name: Option<String>) -> Result<String, String> {
+        if self.source.as_ref().is_some_and(|s| s.is_active()) {
+            return Err("Cannot change audio device while recording".to_string());
+        }
+
+        println!("Switching audio device to {}", device_name.as_deref().unwrap_or("default device"));
+        self.device_name = device_name;
+        self.source = None; // Release the previous device first
+        self.open_source(Some("selected"))
+    }
+
+    /// Rebuild the source if its stream reported an error (e.g. USB headset
+    /// unplugged). A recording in progress continues on the new device, with
+    /// the audio captured before the failure kept aside.
+    fn check_device(&mut self) {
+        let Some(source) = self.source.as_mut() else {
+            return;
+        };
+        let Some(error) = source.take_error() else {
+            return;
+        };
+
+        let failed_device = source.device_name();
+        eprintln!("Audio device {:?} failed: {}", failed_device, error);
+
+        let was_recording = source.is_active();
+        if was_recording {
+            match source.stop_and_drain_channels() {
+                Ok(channels) => {
+                    let salvaged = std::mem::take(&mut self.salvaged);
+                    self.salvaged = if salvaged.is_empty() {
+                        channels
+                    } else {
+                        audio::source::concat_channels(salvaged, channels)
+                    };
+                }
+                Err(e) => eprintln!("Failed to recover audio from failed device: {}", e),
+            }
+        }
+        self.source = None;
+        self.emit_device_error(failed_device, error);
+
+        // Reopens the same device if it is still there, otherwise a fallback
+        if let Err(e) = self.open_source(Some("reconnected")) {
+            eprintln!("Failed to reconnect audio device: {}", e);
+            return;
+        }
+
+        if was_recording {
+            if let Some(ref mut source) = self.source {
+                if let Err(e) = source.start() {
+                    eprintln!("Failed to resume recording: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Warn the user when the recording approaches the maximum duration, then
+    /// stop it and run it through the pipeline as if the hotkey was pressed
+    fn check_duration(&mut self) {
+        let (Some(started), Some(max_duration)) = (self.recording_started, self.max_duration) else {
+            return;
+        };
+        let elapsed = started.elapsed();
+
+        if elapsed >= max_duration {
+            println!("Maximum recording duration reached ({}s), stopping", max_duration.as_secs());
+            let result = self.stop_source();
+            self.finished = Some(result);
+            self.emit(
+                "recording-limit-reached",
+                RecordingLimitEvent {
+                    max_seconds: max_duration.as_secs(),
+                    remaining_seconds: 0,
+                },
+            );
+            if let Some(ref app) = self.app {
+                complete_recording(app);
+            }
+        } else if !self.limit_warned && elapsed + RECORDING_LIMIT_WARNING >= max_duration {
+            self.limit_warned = true;
+            self.emit(
+                "recording-limit-warning",
+                RecordingLimitEvent {
+                    max_seconds: max_duration.as_secs(),
+                    remaining_seconds: (max_duration - elapsed).as_secs(),
+                },
+            );
+        }
+    }
+
+    /// Watch the recording that just started for utterances (continuous
+    /// dictation) or the end of speech (auto-stop), if enabled
+    fn start_speech_detection(&mut self) {
+        if self.utterance_silence_ms.is_none() && self.endpointing.is_none() {
+            return;
+        }
+
+        // Switch to Silero once its model has been downloaded
+        if self.vad.as_ref().is_some_and(|vad| vad.name() == "energy") && vad_model_path().is_ok_and(|path| path.exists()) {
+            self.vad = None;
+        }
+        let vad = self.vad.get_or_insert_with(load_vad);
+        vad.reset();
+
+        // The VAD settings may have changed since the model was loaded
+        let settings = config::AppSettings::load().unwrap_or_default();
+        if let Err(e) = vad.set_settings(settings.vad) {
+            eprintln!("Invalid VAD settings: {}", e);
+        }
+        let chunk_size = vad.chunk_size();
+
+        if let Some(silence_ms) = self.utterance_silence_ms {
+            self.segmenter = Some(UtteranceSegmenter::new(silence_ms, chunk_size));
+            self.dictation = self.app.as_ref().map(|app| {
+                let transcription_tx = app.state::<AppState>().transcription_tx.clone();
+                dictation::DictationSession::new(transcription_tx, app.clone(), take_recording_language(app))
+            });
+            self.dictation_audio = Some(Vec::new());
+        } else if let Some(settings) = self.endpointing {
+            self.endpointer = Some(Endpointer::new(settings, chunk_size));
+        }
+    }
+
+    /// Run the VAD on the audio recorded since the last check. A continuous
+    /// dictation gets every finished utterance; an auto-stop recording is
+    /// stopped once the user stopped speaking, or cancelled if nobody spoke.
+    fn check_speech(&mut self) {
+        let blocks: Vec<Vec<f32>> = self.speech_rx.try_iter().collect();
+        if let Some(audio) = self.dictation_audio.as_mut() {
+            blocks.iter().for_each(|block| audio.extend_from_slice(block));
+            if audio.len() > MAX_KEPT_DICTATION_SAMPLES {
+                self.dictation_audio = None;
+            }
+        }
+        let Some(vad) = self.vad.as_mut() else {
+            return;
+        };
+
+        if let Some(segmenter) = self.segmenter.as_mut() {
+            let utterances: Vec<Utterance> = blocks
+                .iter()
+                .flat_map(|block| segmenter.push(block, |chunk| vad.is_speech(chunk)))
+                .collect();
+            for utterance in utterances {
+                self.submit_utterance(utterance);
+            }
+            return;
+        }
+
+        let Some(endpointer) = self.endpointer.as_mut() else {
+            return;
+        };
+
+        match blocks.iter().find_map(|block| endpointer.push(block, |chunk| vad.is_speech(chunk))) {
+            Some(Endpoint::SpeechEnded) => {
+                println!("End of speech detected, stopping recording");
+                let result = self.stop_source();
+                self.finished = Some(result);
+                self.emit("recording-auto-stopped", ());
+                if let Some(ref app) = self.app {
+                    complete_recording(app);
+                }
+            }
+            Some(Endpoint::NoSpeech) => {
+                println!("No speech detected, cancelling recording");
+                self.cancel_source();
+                self.emit("recording-cancelled", "no-speech");
+                if let Some(ref app) = self.app {
+                    cancel_recording(app);
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Hand a finished utterance of the continuous dictation over for transcription
+    fn submit_utterance(&mut self, utterance: Utterance) {
+        match self.dictation.as_mut() {
+            Some(dictation) => dictation.submit(utterance.samples),
+            None => println!(
+                "Utterance at {:.1}s dropped (no dictation session)",
+                utterance.segment.start as f32 / 16000.0
+            ),
+        }
+    }
+
+    /// End the continuous dictation: the audio still streaming completes the
+    /// last utterance, and the recording itself is dropped since every
+    /// utterance was already transcribed
+    fn stop_dictation(&mut self) {
+        if self.segmenter.is_none() {
+            // Dictation turned on during the recording: it was recorded as a whole
+            if self.source.as_ref().is_some_and(|s| s.is_active()) {
+                if let Some(ref app) = self.app {
+                    let state = app.state::<AppState>();
+                    handle_recording_complete(state.audio_tx.clone(), state.transcription_tx.clone(), app.clone());
+                }
+            }
+            return;
+        }
+
+        self.cancel_source();
+        self.check_speech();
+        if let Some(utterance) = self.segmenter.take().and_then(UtteranceSegmenter::finish) {
+            self.submit_utterance(utterance);
+        }
+        self.end_dictation();
+    }
+
+    /// Stop cutting utterances; the ones already submitted are still inserted
+    fn end_dictation(&mut self) {
+        self.segmenter = None;
+        self.set_speech_listening(false);
+        let audio = self.dictation_audio.take().unwrap_or_default();
+        if let Some(dictation) = self.dictation.take() {
+            if let Some(ref app) = self.app {
+                remember_recording(app, audio);
+            }
+            dictation.finish();
+        }
+    }
+
+    /// Prepend the audio recorded before a device failure to the recording result
+    fn prepend_salvaged(&mut self, result: Result<Vec<AudioChannel>, String>) -> Result<Vec<AudioChannel>, String> {
+        if self.salvaged.is_empty() {
+            return result;
+        }
+
+        let salvaged = std::mem::take(&mut self.salvaged);
+        match result {
+            Ok(rest) => Ok(audio::source::concat_channels(salvaged, rest)),
+            Err(e) => {
+                eprintln!("Returning audio recorded before the device failure only: {}", e);
+                Ok(salvaged)
+            }
+        }
+    }
+
+    fn emit_device_error(&self, device_name: Option<String>, message: String) {
+        self.emit("device-error", DeviceErrorEvent { device_name, message });
+    }
+
+    fn emit<S: serde::Serialize + Clone>(&self, event: &str, payload: S) {
+        if let Some(ref app) = self.app {
+            let _ = app.emit(event, payload);
+        }
+    }
+}
+
+/// Finish a recording stopped by the audio worker (maximum duration, end of
+/// speech): reset the toggle state and transcribe it (the worker hands the
+/// audio over on the next stop command)
+fn complete_recording(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    *state.toggle_recording.lock().unwrap() = false;
+    let _ = app.emit("recording-stopped", ());
+    handle_recording_complete(state.audio_tx.clone(), state.transcription_tx.clone(), app.clone());
+}
+
+/// Clean up after a recording cancelled by the audio worker (nobody spoke)
+fn cancel_recording(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    *state.toggle_recording.lock().unwrap() = false;
+    let _ = app.emit("recording-stopped", ());
+    if let Some(window) = app.get_webview_window("indicator") {
+        let _ = window.hide();
+    }
+}
+
+/// Create the source for the system audio mode in settings: the microphone,
+/// the system audio (monitor of the output), or both at once
+fn create_audio_source(device_name: Option<&str>) -> Result<Box<dyn AudioSource>, String> {
+    let settings = config::AppSettings::load().unwrap_or_default();
+
+    match settings.system_audio {
+        SystemAudioMode::Off => Ok(Box::new(create_microphone_recorder(&settings, device_name)?)),
+        SystemAudioMode::Only => Ok(Box::new(create_system_recorder(&settings)?)),
+        SystemAudioMode::Mix | SystemAudioMode::Separate => {
+            let microphone = create_microphone_recorder(&settings, device_name)?;
+            let system = create_system_recorder(&settings)?;
+            let separate = settings.system_audio == SystemAudioMode::Separate;
+            Ok(Box::new(DualAudioSource::new(microphone, system, separate)))
+        }
+    }
+}
+
+/// Create the recorder for the system audio monitor selected in settings
+fn create_system_recorder(settings: &config::AppSettings) -> Result<AudioRecorder, String> {
+    println!(
+        "Using system audio from {}",
+        settings.system_audio_device.as_deref().unwrap_or("the default output")
+    );
+    let mut recorder = AudioRecorder::new_system_audio(settings.system_audio_device.as_deref())?;
+    recorder.set_keep_warm(settings.keep_warm());
+    recorder.set_spool(settings.spool());
+    recorder.set_preprocessing(settings.preprocessing.clone());
+    Ok(recorder)
+}
+
+/// Create the microphone recorder for the selected device, falling back to the
+/// devices listed in settings and then to the default device
+fn create_microphone_recorder(settings: &config::AppSettings, device_name: Option<&str>) -> Result<AudioRecorder, String> {
+    let devices = settings.preferred_devices(device_name);
+    if devices.is_empty() {
+        println!("Using default audio device");
+    } else {
+        println!("Using configured audio devices: {:?}", devices);
+    }
+    let mut recorder = AudioRecorder::new_with_preferred_devices(&devices)?;
+
+    if let Err(e) = recorder.set_input_channel(settings.input_channel) {
+        eprintln!("Failed to select input channel: {}. Downmixing all channels.", e);
+    }
+    recorder.set_keep_warm(settings.keep_warm());
+    recorder.set_preprocessing(settings.preprocessing.clone());
+    recorder.set_spool(settings.spool());
+
+    Ok(recorder)
 }
 
 // Transcription worker that runs in dedicated thread
@@ -121,7 +779,7 @@ impl TranscriptionWorker {
     fn run(mut self) {
         loop {
             match self.rx.recv() {
-                Ok(TranscriptionCommand::Transcribe { audio, language, reply }) => {
+                Ok(TranscriptionCommand::Transcribe { audio, language, prompt, decode, reply }) => {
                     println!("TranscriptionWorker: Received transcribe request with {} samples", audio.len());
 
                     // Lazy load the engine on first use
@@ -142,26 +800,21 @@ impl TranscriptionWorker {
 
                     println!("TranscriptionWorker: Starting transcription...");
 
-                    // Load custom words from settings for contextual biasing
+                    // Load the prompt templates, custom words and style hint for contextual biasing
                     let settings = config::AppSettings::load().unwrap_or_default();
-                    let custom_words = if !settings.custom_words.is_empty() {
-                        Some(settings.custom_words.as_slice())
-                    } else {
-                        None
-                    };
-
-                    if let Some(words) = custom_words {
-                        println!("Loaded {} custom words for contextual biasing", words.len());
+                    let initial_prompt = settings.initial_prompt(prompt);
+                    if !initial_prompt.custom_words.is_empty() {
+                        println!("Loaded {} custom words for contextual biasing", initial_prompt.custom_words.len());
                     }
 
                     let result = if let Some(ref mut engine) = self.engine {
-                        engine.transcribe(&audio, language.as_deref(), custom_words)
+                        engine.transcribe(&audio, &language, &initial_prompt, &decode)
                     } else {
                         Err("Transcription engine not initialized".to_string())
                     };
 
                     match &result {
-                        Ok(text) => println!("TranscriptionWorker: Transcription successful: '{}'", text),
+                        Ok(result) => println!("TranscriptionWorker: Transcription successful: '{}'", result.text),
                         Err(e) => eprintln!("TranscriptionWorker: Transcription failed: {}", e),
                     }
 
@@ -199,6 +852,15 @@ impl TranscriptionWorker {
 pub struct AppState {
     audio_tx: Sender<AudioCommand>,
     transcription_tx: Sender<TranscriptionCommand>,
+    // Recording state of the toggle hotkey
+    toggle_recording: std::sync::Arc<std::sync::Mutex<bool>>,
+    // Last recording or dictation (16kHz mono), kept to calibrate the VAD settings;
+    // empty after a file transcription or a recording too long to keep
+    last_recording: std::sync::Mutex<Vec<f32>>,
+    // Model downloads, run one at a time
+    downloads: transcription::downloader::DownloadQueue,
+    // Language chosen from the tray or hotkey for the next recording only ("auto" to detect it)
+    next_language: std::sync::Mutex<Option<String>>,
 }
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
@@ -243,24 +905,132 @@ fn is_recording(state: State<'_, AppState>) -> Result<bool, String> {
 }
 
 #[tauri::command]
-fn transcribe(state: State<'_, AppState>, audio: Vec<f32>) -> Result<String, String> {
-    // Load settings to get language preference
+fn transcribe(app: AppHandle, state: State<'_, AppState>, audio: Vec<f32>) -> Result<String, String> {
+    request_transcription(&state.transcription_tx, audio, None, recording_language(&app)).map(|result| result.text)
+}
+
+/// Transcribe with the timed segments, token probabilities and detected language.
+/// The audio is not trimmed by the VAD, so the segment times are positions in `audio`.
+#[tauri::command]
+fn transcribe_detailed(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    audio: Vec<f32>,
+) -> Result<transcription::TranscriptionResult, String> {
+    request_transcription(&state.transcription_tx, audio, None, recording_language(&app))
+}
+
+/// Language of the next recording: the one chosen for it from the tray or hotkey, or the setting
+fn recording_language(app: &AppHandle) -> transcription::LanguageSelection {
+    let settings = config::AppSettings::load().unwrap_or_default();
+    match app.state::<AppState>().next_language.lock().unwrap().as_deref() {
+        Some(language) => transcription::LanguageSelection::from_setting(language, &settings.allowed_languages),
+        None => settings.language_selection(),
+    }
+}
+
+/// Language of the recording that just finished, or of the dictation that starts,
+/// clearing the choice made for it
+fn take_recording_language(app: &AppHandle) -> transcription::LanguageSelection {
+    let language = recording_language(app);
+    if let Some(language) = app.state::<AppState>().next_language.lock().unwrap().take() {
+        println!("Next-recording language '{}' used, back to the setting", language);
+        let _ = app.emit("next-language-changed", None::<String>);
+    }
+    language
+}
+
+/// Set the language of the next recording only ("auto" to detect it among the allowed
+/// languages), or None to use the setting again
+#[tauri::command]
+fn set_next_language(app: AppHandle, language: Option<String>) -> Result<(), String> {
+    if let Some(ref language) = language {
+        if language != "auto" && (!(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_lowercase())) {
+            return Err(format!("Invalid language code: '{}'", language));
+        }
+        // No allowed languages means any language
+        let allowed = config::AppSettings::load().unwrap_or_default().allowed_languages;
+        if language != "auto" && !allowed.is_empty() && !allowed.contains(language) {
+            return Err(format!("Language '{}' is not one of the allowed languages {:?}", language, allowed));
+        }
+    }
+    println!("Language of the next recording: {:?}", language);
+    *app.state::<AppState>().next_language.lock().unwrap() = language.clone();
+    let _ = app.emit("next-language-changed", language);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_next_language(state: State<'_, AppState>) -> Option<String> {
+    state.next_language.lock().unwrap().clone()
+}
+
+/// Move the next-recording language to the next one of: the setting, then each allowed language
+fn cycle_next_language(app: &AppHandle) {
     let settings = config::AppSettings::load().unwrap_or_default();
-    let language = Some(settings.language);
+    let current = app.state::<AppState>().next_language.lock().unwrap().clone();
+    let next = match current {
+        None => settings.allowed_languages.first().cloned(),
+        Some(language) => settings
+            .allowed_languages
+            .iter()
+            .skip_while(|allowed| **allowed != language)
+            .nth(1)
+            .cloned(),
+    };
+    let _ = set_next_language(app.clone(), next);
+}
 
-    let (reply_tx, reply_rx) = mpsc::channel();
-    state
-        .transcription_tx
-        .send(TranscriptionCommand::Transcribe {
-            audio,
-            language,
-            reply: reply_tx,
-        })
-        .map_err(|e| format!("Failed to send command: {}", e))?;
+/// Id of the tray icon, to rebuild its menu
+const TRAY_ID: &str = "main";
 
-    reply_rx
-        .recv()
-        .map_err(|e| format!("Failed to receive reply: {}", e))?
+/// Tray menu: settings, execution modes, language of the next recording and quit
+fn build_tray_menu(app: &AppHandle) -> tauri::Result<tauri::menu::Menu<tauri::Wry>> {
+    use tauri::menu::{MenuBuilder, MenuItemBuilder, SubmenuBuilder};
+
+    let settings_item = MenuItemBuilder::with_id("settings", "Paramètres").build(app)?;
+
+    // Load execution modes and create Modes submenu
+    let app_settings = config::AppSettings::load().unwrap_or_default();
+    let mut modes_submenu = SubmenuBuilder::new(app, "Modes");
+
+    for mode in &app_settings.execution_modes {
+        let mode_id = format!("mode_{}", mode.id);
+        let mode_label = if mode.id == app_settings.active_mode {
+            format!("✓ {}", mode.name)
+        } else {
+            mode.name.clone()
+        };
+        let mode_item = MenuItemBuilder::with_id(&mode_id, mode_label).build(app)?;
+        modes_submenu = modes_submenu.item(&mode_item);
+    }
+
+    let modes_menu = modes_submenu.build()?;
+
+    // Language of the next recording only
+    let mut languages_submenu = SubmenuBuilder::new(app, "Langue (prochain enregistrement)")
+        .item(&MenuItemBuilder::with_id("lang_auto", "Détection automatique").build(app)?);
+    for language in &app_settings.allowed_languages {
+        let language_item = MenuItemBuilder::with_id(format!("lang_{}", language), language.to_uppercase()).build(app)?;
+        languages_submenu = languages_submenu.item(&language_item);
+    }
+    let languages_menu = languages_submenu.build()?;
+
+    let quit = MenuItemBuilder::with_id("quit", "Quitter").build(app)?;
+
+    MenuBuilder::new(app)
+        .items(&[&settings_item, &modes_menu, &languages_menu, &quit])
+        .build()
+}
+
+/// Rebuild the tray menu from the saved settings (modes and allowed languages)
+fn refresh_tray_menu(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    if let Err(e) = build_tray_menu(app).and_then(|menu| tray.set_menu(Some(menu))) {
+        eprintln!("Failed to rebuild the tray menu: {}", e);
+    }
 }
 
 #[tauri::command]
@@ -280,9 +1050,52 @@ fn get_settings() -> Result<config::AppSettings, String> {
     config::AppSettings::load()
 }
 
+/// Save the fields sent by the settings page over the saved settings (the page
+/// only sends the fields it shows)
 #[tauri::command]
-fn save_settings(settings: config::AppSettings) -> Result<(), String> {
-    settings.save()
+fn save_settings(app: AppHandle, state: State<'_, AppState>, settings: serde_json::Value) -> Result<(), String> {
+    let previous = config::AppSettings::load().unwrap_or_default();
+    let mut settings = previous.with_changes(settings)?;
+    settings.vad.validate()?;
+    settings.decode.validate()?;
+    for mode in &settings.execution_modes {
+        if let Some(decode) = &mode.decode {
+            decode.validate().map_err(|e| format!("Execution mode '{}': {}", mode.name, e))?;
+        }
+    }
+    for template in settings.prompt_templates.values() {
+        transcription::prompt::validate_template(template)?;
+    }
+    for list in &settings.vocabulary_lists {
+        list.validate()?;
+    }
+    settings.migrate_custom_words();
+    settings.save()?;
+    refresh_tray_menu(&app);
+
+    // Apply the new microphone configuration without restarting
+    if settings.audio_input_changed(&previous) {
+        if let Err(e) = send_set_device(&state.audio_tx, settings.device_name.clone()) {
+            eprintln!("Failed to apply audio device settings: {}", e);
+        }
+    }
+    if settings.max_recording_secs != previous.max_recording_secs {
+        let _ = state.audio_tx.send(AudioCommand::SetMaxDuration {
+            max_duration: settings.max_recording_duration(),
+        });
+    }
+    if settings.endpointing() != previous.endpointing() {
+        let _ = state.audio_tx.send(AudioCommand::SetEndpointing {
+            endpointing: settings.endpointing(),
+        });
+    }
+    if settings.dictation_silence_ms() != previous.dictation_silence_ms() {
+        let _ = state.audio_tx.send(AudioCommand::SetDictation {
+            utterance_silence_ms: settings.dictation_silence_ms(),
+        });
+    }
+
+    Ok(())
 }
 
 #[tauri::command]
@@ -319,9 +1132,44 @@ fn update_hotkey(app: AppHandle, new_hotkey: String) -> Result<(), String> {
 
 #[tauri::command]
 fn get_audio_devices() -> Result<Vec<(String, bool)>, String> {
+    Ok(audio::AudioRecorder::list_devices()?
+        .into_iter()
+        .filter(|d| d.kind == audio::DeviceKind::Microphone)
+        .map(|d| (d.name, d.is_default))
+        .collect())
+}
+
+/// List microphones and system audio monitors, told apart by `kind`
+#[tauri::command]
+fn list_audio_devices() -> Result<Vec<audio::AudioDeviceInfo>, String> {
     audio::AudioRecorder::list_devices()
 }
 
+/// Switch the recording device at runtime (`None` = default device) and save it
+/// Returns the name of the device actually opened (may be a fallback)
+#[tauri::command]
+fn set_audio_device(state: State<'_, AppState>, device_name: Option<String>) -> Result<String, String> {
+    let mut settings = config::AppSettings::load()?;
+    settings.device_name = device_name.clone();
+    settings.save()?;
+
+    send_set_device(&state.audio_tx, device_name)
+}
+
+fn send_set_device(audio_tx: &Sender<AudioCommand>, device_name: Option<String>) -> Result<String, String> {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    audio_tx
+        .send(AudioCommand::SetDevice {
+            device_name,
+            reply: reply_tx,
+        })
+        .map_err(|e| format!("Failed to send set device command: {}", e))?;
+
+    reply_rx
+        .recv()
+        .map_err(|e| format!("Failed to receive reply: {}", e))?
+}
+
 #[tauri::command]
 fn update_cancel_key(app: AppHandle, new_cancel_key: String) -> Result<(), String> {
     use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut};
@@ -354,8 +1202,56 @@ fn update_cancel_key(app: AppHandle, new_cancel_key: String) -> Result<(), Strin
     Ok(())
 }
 
+/// Change the shortcut cycling the language of the next recording (None removes it)
+#[tauri::command]
+fn update_language_switch_key(app: AppHandle, new_key: Option<String>) -> Result<(), String> {
+    use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut};
+    use std::str::FromStr;
+
+    let mut settings = config::AppSettings::load()?;
+
+    let shortcut = new_key
+        .as_deref()
+        .map(Shortcut::from_str)
+        .transpose()
+        .map_err(|e| format!("Invalid language switch key format: {}", e))?;
+
+    // The recording and cancel keys, which the language switch key must not take over
+    let taken_by = |shortcut: &Shortcut| {
+        [("recording hotkey", &settings.hotkey), ("cancel key", &settings.cancel_key)]
+            .into_iter()
+            .find(|(_, key)| Shortcut::from_str(key).is_ok_and(|key| key == *shortcut))
+            .map(|(name, _)| name)
+    };
+    if let Some(name) = shortcut.as_ref().and_then(taken_by) {
+        return Err(format!("{} is already the {}", new_key.unwrap_or_default(), name));
+    }
+
+    // A key saved before this check may be the same as another shortcut, which stays registered
+    if let Some(old_shortcut) = settings
+        .language_switch_key
+        .as_deref()
+        .and_then(|key| Shortcut::from_str(key).ok())
+        .filter(|old_shortcut| taken_by(old_shortcut).is_none())
+    {
+        let _ = app.global_shortcut().unregister(old_shortcut);
+    }
+    if let Some(shortcut) = shortcut {
+        app.global_shortcut().register(shortcut)
+            .map_err(|e| format!("Failed to register language switch key: {}", e))?;
+    }
+
+    println!("Language switch key: {:?}", new_key);
+    settings.language_switch_key = new_key;
+    settings.save()
+}
+
 #[tauri::command]
 fn reload_model(state: State<'_, AppState>, model_name: String) -> Result<(), String> {
+    if vad_model_path()?.file_name().is_some_and(|name| name.to_string_lossy() == model_name) {
+        return Err(format!("{} is the voice activity model, not a transcription model", model_name));
+    }
+
     // Construct the full model path
     let model_path = std::env::var("FLEMME_MODEL_PATH")
         .ok()
@@ -392,15 +1288,16 @@ fn reload_model(state: State<'_, AppState>, model_name: String) -> Result<(), St
         .map_err(|e| format!("Failed to receive reply: {}", e))?
 }
 
+/// Add a word to the default vocabulary list
 #[tauri::command]
 fn add_custom_word(word: String) -> Result<(), String> {
     let mut settings = config::AppSettings::load()?;
+    let list = settings.default_vocabulary_list();
 
     // Avoid duplicates
-    if !settings.custom_words.contains(&word) {
-        settings.custom_words.push(word);
+    if list.add_term(&word, 1) {
+        println!("Added custom word, total: {}", list.terms.len());
         settings.save()?;
-        println!("Added custom word, total: {}", settings.custom_words.len());
     }
 
     Ok(())
@@ -409,171 +1306,431 @@ fn add_custom_word(word: String) -> Result<(), String> {
 #[tauri::command]
 fn remove_custom_word(word: String) -> Result<(), String> {
     let mut settings = config::AppSettings::load()?;
-    settings.custom_words.retain(|w| w != &word);
+    let list = settings.default_vocabulary_list();
+    list.remove_term(&word);
+    println!("Removed custom word, remaining: {}", list.terms.len());
     settings.save()?;
-    println!("Removed custom word, remaining: {}", settings.custom_words.len());
     Ok(())
 }
 
 #[tauri::command]
 fn clear_custom_words() -> Result<(), String> {
     let mut settings = config::AppSettings::load()?;
-    settings.custom_words.clear();
+    settings.default_vocabulary_list().terms.clear();
     settings.save()?;
     println!("Cleared all custom words");
     Ok(())
 }
 
+/// Words of the default vocabulary list
 #[tauri::command]
 fn get_custom_words() -> Result<Vec<String>, String> {
+    let mut settings = config::AppSettings::load()?;
+    Ok(settings.default_vocabulary_list().terms.iter().map(|term| term.text.clone()).collect())
+}
+
+#[tauri::command]
+fn get_vocabulary_lists() -> Result<Vec<config::VocabularyList>, String> {
+    Ok(config::AppSettings::load()?.vocabulary_lists)
+}
+
+/// Add a vocabulary list, or replace the one with the same id. Returns its id
+#[tauri::command]
+fn save_vocabulary_list(mut list: config::VocabularyList) -> Result<String, String> {
+    list.validate()?;
+    let mut settings = config::AppSettings::load()?;
+
+    if list.id.is_empty() {
+        list.id = format!("vocabulary_{}", uuid::Uuid::new_v4());
+    }
+    if let Some(mode) = list.modes.iter().find(|mode| !settings.execution_modes.iter().any(|m| m.id == **mode)) {
+        return Err(format!("Execution mode not found: {}", mode));
+    }
+
+    let id = list.id.clone();
+    match settings.vocabulary_lists.iter_mut().find(|other| other.id == id) {
+        Some(other) => *other = list,
+        None => settings.vocabulary_lists.push(list),
+    }
+    settings.save()?;
+
+    println!("Vocabulary list saved: {}", id);
+    Ok(id)
+}
+
+#[tauri::command]
+fn delete_vocabulary_list(id: String) -> Result<(), String> {
+    let mut settings = config::AppSettings::load()?;
+    let count = settings.vocabulary_lists.len();
+    settings.vocabulary_lists.retain(|list| list.id != id);
+    if settings.vocabulary_lists.len() == count {
+        return Err(format!("Vocabulary list not found: {}", id));
+    }
+    settings.save()?;
+
+    println!("Vocabulary list deleted: {}", id);
+    Ok(())
+}
+
+/// Create a vocabulary list from a TXT (one term a line) or CSV (`term,weight`) file,
+/// named after the file unless a name is given
+#[tauri::command]
+fn import_vocabulary_list(path: String, name: Option<String>) -> Result<config::VocabularyList, String> {
+    let path = std::path::PathBuf::from(path);
+    let terms = config::vocabulary::import_file(&path)?;
+
+    let name = name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+        .ok_or_else(|| format!("No name for the vocabulary list of {:?}", path))?;
+    let mut list = config::VocabularyList::new(format!("vocabulary_{}", uuid::Uuid::new_v4()), name);
+    for term in &terms {
+        list.add_term(&term.text, term.weight);
+    }
+    list.validate()?;
+
+    let mut settings = config::AppSettings::load()?;
+    settings.vocabulary_lists.push(list.clone());
+    settings.save()?;
+
+    println!("Imported {} terms from {:?} into vocabulary list '{}'", list.terms.len(), path, list.name);
+    Ok(list)
+}
+
+/// Write a vocabulary list to a TXT or CSV file, from the extension of `path`
+#[tauri::command]
+fn export_vocabulary_list(id: String, path: String) -> Result<(), String> {
+    let settings = config::AppSettings::load()?;
+    let list = settings
+        .vocabulary_lists
+        .iter()
+        .find(|list| list.id == id)
+        .ok_or_else(|| format!("Vocabulary list not found: {}", id))?;
+    config::vocabulary::export_file(list, std::path::Path::new(&path))?;
+
+    println!("Exported vocabulary list '{}' to {}", list.name, path);
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct VocabularyReport {
+    language: Option<String>,
+    /// Prompt of the active mode before any previous text
+    prompt: String,
+    prompt_tokens: usize,
+    included_words: Vec<String>,
+    /// Words left out to stay within Whisper's prompt limit
+    dropped_words: Vec<String>,
+    /// Always true: the tokens are counted with `estimate_tokens`, the model tokenizer
+    /// being in the transcription worker, so the words that fit may differ by a few
+    estimated: bool,
+}
+
+/// Which custom words fit in the Whisper prompt of the active mode for a language
+/// (the first allowed language when none is given and the language is detected)
+#[tauri::command]
+fn get_vocabulary_report(language: Option<String>) -> Result<VocabularyReport, String> {
     let settings = config::AppSettings::load()?;
-    Ok(settings.custom_words)
+    let language = language.or_else(|| match settings.language_selection() {
+        transcription::LanguageSelection::Fixed(language) => Some(language),
+        transcription::LanguageSelection::Detect(allowed) => allowed.first().cloned(),
+    });
+
+    let prompt = settings.initial_prompt(None);
+    let built = prompt.build(
+        language.as_deref(),
+        transcription::prompt::MAX_PROMPT_TOKENS,
+        transcription::prompt::estimate_tokens,
+    );
+    let included_words = prompt
+        .words(language.as_deref())
+        .into_iter()
+        .filter(|word| !built.dropped_words.contains(word))
+        .collect();
+
+    Ok(VocabularyReport {
+        language,
+        prompt_tokens: transcription::prompt::estimate_tokens(&built.text),
+        prompt: built.text,
+        included_words,
+        dropped_words: built.dropped_words,
+        estimated: true,
+    })
 }
 
 #[derive(serde::Serialize, Clone)]
 struct ModelInfo {
     name: String,
+    display_name: String,
+    /// The VAD model is listed to be downloaded again, not to transcribe with
+    kind: transcription::ModelKind,
+    /// Languages an imported model is meant for, empty for multilingual models
+    languages: Vec<String>,
+    /// Imported by the user rather than from the catalog
+    is_custom: bool,
     size_mb: f64,
     is_downloaded: bool,
+    /// Bytes of an interrupted download, resumed by the next `download_model`
+    partial_bytes: u64,
     download_url: String,
+    quantization: Option<String>,
+    recommended_hardware: String,
+    sha256: Option<String>,
 }
 
-#[tauri::command]
-fn list_available_models() -> Result<Vec<ModelInfo>, String> {
-    let models_dir = dirs::data_dir()
-        .ok_or_else(|| "Failed to get data directory".to_string())?
-        .join("Flemme")
-        .join("models");
-
-    // Create models directory if it doesn't exist
-    if !models_dir.exists() {
-        std::fs::create_dir_all(&models_dir)
-            .map_err(|e| format!("Failed to create models directory: {}", e))?;
-    }
-
-    // List of available Whisper models with download URLs (Q5 quantized for better performance)
-    let available_models = vec![
-        ("ggml-base-q5_1.bin", 59.7, "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-base-q5_1.bin"),
-        ("ggml-small-q5_1.bin", 192.0, "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small-q5_1.bin"),
-        ("ggml-medium-q5_0.bin", 940.0, "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-medium-q5_0.bin"),
-        ("ggml-large-v2-q5_0.bin", 1820.0, "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-large-v2-q5_0.bin"),
-        ("ggml-large-v3-turbo-q5_0.bin", 950.0, "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-large-v3-turbo-q5_0.bin"),
-    ];
-
-    let mut result = Vec::new();
-    for (name, size_mb, url) in available_models {
-        let model_path = models_dir.join(name);
-        let is_downloaded = model_path.exists();
-
-        result.push(ModelInfo {
-            name: name.to_string(),
-            size_mb,
-            is_downloaded,
-            download_url: url.to_string(),
-        });
-    }
+/// The model catalog, from the manifest configured in settings or the bundled one
+async fn load_model_catalog() -> Result<transcription::ModelCatalog, String> {
+    let settings = config::AppSettings::load().unwrap_or_default();
+    transcription::ModelCatalog::load(settings.model_manifest_url.as_deref()).await
+}
 
-    Ok(result)
+#[tauri::command]
+async fn list_available_models() -> Result<Vec<ModelInfo>, String> {
+    let catalog = load_model_catalog().await?;
+    let downloader = transcription::ModelDownloader::new()?;
+    let custom_models = transcription::CustomModels::load(downloader.models_dir())?;
+
+    let catalog_models = catalog.whisper_models().chain(catalog.vad_model()).map(|model| ModelInfo {
+        name: model.name.clone(),
+        display_name: match (model.kind, &model.version) {
+            (transcription::ModelKind::Vad, Some(version)) => format!("Silero VAD {}", version),
+            (transcription::ModelKind::Vad, None) => "Silero VAD".to_string(),
+            _ => model.name.clone(),
+        },
+        kind: model.kind,
+        languages: Vec::new(),
+        is_custom: false,
+        size_mb: model.size_mb(),
+        is_downloaded: downloader.check_model_exists(&model.name),
+        partial_bytes: downloader.partial_size(&model.name),
+        download_url: model.url.clone(),
+        quantization: model.quantization.clone(),
+        recommended_hardware: model.recommended_hardware.clone(),
+        sha256: model.sha256.clone(),
+    });
+    let imported_models = custom_models.models.iter().map(|model| ModelInfo {
+        name: model.name.clone(),
+        display_name: model.display_name.clone(),
+        kind: transcription::ModelKind::Whisper,
+        languages: model.languages.clone(),
+        is_custom: true,
+        size_mb: downloader
+            .model_path(&model.name)
+            .and_then(|path| std::fs::metadata(path).map_err(|e| e.to_string()))
+            .map(|metadata| metadata.len() as f64 / 1_000_000.0)
+            .unwrap_or(0.0),
+        is_downloaded: downloader.check_model_exists(&model.name),
+        partial_bytes: 0,
+        download_url: model.source.clone(),
+        quantization: None,
+        recommended_hardware: String::new(),
+        sha256: None,
+    });
+    Ok(catalog_models.chain(imported_models).collect())
 }
 
 #[tauri::command]
 fn delete_model(model_name: String) -> Result<(), String> {
-    let models_dir = dirs::data_dir()
-        .ok_or_else(|| "Failed to get data directory".to_string())?
-        .join("Flemme")
-        .join("models");
+    let downloader = transcription::ModelDownloader::new()?;
+    downloader.delete_model(&model_name)?;
+    transcription::CustomModels::update(downloader.models_dir(), |custom_models| custom_models.remove(&model_name))
+}
+
+/// Import a Whisper model (ggml or GGUF) from a local file, copied or linked
+/// into the models directory
+#[tauri::command]
+async fn import_model_file(
+    path: String,
+    display_name: Option<String>,
+    languages: Vec<String>,
+    link: bool,
+) -> Result<(), String> {
+    let catalog_names = catalog_model_names().await?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let source = std::path::PathBuf::from(&path);
+        let name = source
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| format!("Invalid model file name: {:?}", source))?;
+        let metadata = transcription::CustomModel::new(name, display_name, languages, path.clone(), link)?;
+
+        let downloader = transcription::ModelDownloader::new()?;
+        let mode = if link {
+            transcription::custom_models::ImportMode::Link
+        } else {
+            transcription::custom_models::ImportMode::Copy
+        };
+        let model_path = transcription::custom_models::import_file(&source, downloader.models_dir(), &catalog_names, mode)?;
+        register_custom_model(&downloader, &model_path, metadata)
+    })
+    .await
+    .map_err(|e| format!("Model import task failed: {}", e))?
+}
+
+/// Import a Whisper model (ggml or GGUF) from any URL, downloaded like the catalog models
+#[tauri::command]
+async fn import_model_url(
+    app: AppHandle,
+    url: String,
+    display_name: Option<String>,
+    languages: Vec<String>,
+) -> Result<(), String> {
+    let name = transcription::custom_models::file_name_from_url(&url)?;
+    transcription::custom_models::check_name(&name, &catalog_model_names().await?)?;
+    let metadata = transcription::CustomModel::new(&name, display_name, languages, url.clone(), false)?;
+    let downloader = transcription::ModelDownloader::new()?;
+    if downloader.check_model_exists(&name) {
+        return Err(format!("A model named '{}' already exists", name));
+    }
+
+    let model = transcription::models::ModelInfo {
+        name,
+        kind: transcription::ModelKind::Whisper,
+        model: None,
+        quantization: None,
+        version: None,
+        size: 0,
+        sha256: None,
+        url,
+        recommended_hardware: String::new(),
+    };
+    let model_path = queue_download(&app, model).await?;
 
-    let model_path = models_dir.join(&model_name);
+    tauri::async_runtime::spawn_blocking(move || register_custom_model(&downloader, &model_path, metadata))
+        .await
+        .map_err(|e| format!("Model import task failed: {}", e))?
+}
 
-    if !model_path.exists() {
-        return Err(format!("Model '{}' does not exist", model_name));
+/// Test-load an imported model in a throwaway engine and add it to the custom
+/// models manifest. A model Whisper cannot load is removed again.
+fn register_custom_model(
+    downloader: &transcription::ModelDownloader,
+    model_path: &std::path::Path,
+    metadata: transcription::CustomModel,
+) -> Result<(), String> {
+    if let Err(e) = transcription::custom_models::validate_model(model_path) {
+        let _ = std::fs::remove_file(model_path);
+        return Err(e);
     }
 
-    std::fs::remove_file(&model_path)
-        .map_err(|e| format!("Failed to delete model '{}': {}", model_name, e))?;
+    println!("Imported model '{}' ({})", metadata.name, metadata.display_name);
+    transcription::CustomModels::update(downloader.models_dir(), |custom_models| {
+        custom_models.upsert(metadata);
+        true
+    })
+}
 
-    println!("Model '{}' deleted successfully", model_name);
-    Ok(())
+/// File names of the catalog models, that imported models cannot take
+async fn catalog_model_names() -> Result<Vec<String>, String> {
+    let catalog = load_model_catalog().await?;
+    Ok(catalog.models.into_iter().map(|model| model.name).collect())
 }
 
 #[derive(Clone, serde::Serialize)]
 struct DownloadProgress {
     model_name: String,
+    /// "queued" behind other downloads, or "downloading"
+    status: &'static str,
     downloaded_bytes: u64,
     total_bytes: u64,
     percentage: f64,
 }
 
+/// Download a model once the downloads requested before are done
 #[tauri::command]
 async fn download_model(
     app: AppHandle,
     model_name: String,
     download_url: String,
 ) -> Result<(), String> {
-    use std::io::Write;
-
-    let models_dir = dirs::data_dir()
-        .ok_or_else(|| "Failed to get data directory".to_string())?
-        .join("Flemme")
-        .join("models");
+    // Models of the catalog come with their checksum; others are fetched from the given URL
+    let catalog = load_model_catalog().await?;
+    let model = catalog.find(&model_name).cloned().unwrap_or_else(|| transcription::models::ModelInfo {
+        name: model_name.clone(),
+        kind: transcription::ModelKind::Whisper,
+        model: None,
+        quantization: None,
+        version: None,
+        size: 0,
+        sha256: None,
+        url: download_url,
+        recommended_hardware: String::new(),
+    });
 
-    // Create models directory if it doesn't exist
-    if !models_dir.exists() {
-        std::fs::create_dir_all(&models_dir)
-            .map_err(|e| format!("Failed to create models directory: {}", e))?;
+    // The VAD model is checked once downloaded
+    if model.kind == transcription::ModelKind::Vad {
+        return download_vad_model(app).await;
     }
+    queue_download(&app, model).await?;
+    Ok(())
+}
 
-    let model_path = models_dir.join(&model_name);
+/// Download a model through the download queue, reporting its progress as
+/// "download-progress" events
+async fn queue_download(
+    app: &AppHandle,
+    model: transcription::models::ModelInfo,
+) -> Result<std::path::PathBuf, String> {
+    let state = app.state::<AppState>();
+    let downloader = transcription::ModelDownloader::new()?;
+
+    if !state.downloads.pending().is_empty() {
+        println!("Model '{}' queued behind {:?}", model.name, state.downloads.pending());
+        let _ = app.emit(
+            "download-progress",
+            DownloadProgress {
+                model_name: model.name.clone(),
+                status: "queued",
+                downloaded_bytes: downloader.partial_size(&model.name),
+                total_bytes: model.size,
+                percentage: 0.0,
+            },
+        );
+    }
 
-    println!("Downloading model '{}' from {}", model_name, download_url);
+    let model_name = model.name.clone();
+    let app = app.clone();
+    state
+        .downloads
+        .run(&model_name, |cancel| async move {
+            let mut last_emitted_percentage = -1.0;
+            downloader
+                .download_model(&model, &cancel, |downloaded_bytes, total_bytes| {
+                    let percentage = if total_bytes > 0 {
+                        (downloaded_bytes as f64 / total_bytes as f64) * 100.0
+                    } else {
+                        0.0
+                    };
 
-    // Download with progress tracking
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&download_url)
-        .send()
+                    // Emit progress event only if percentage changed by at least 1%
+                    if (percentage - last_emitted_percentage).abs() >= 1.0 || downloaded_bytes == total_bytes {
+                        println!("Download progress: {:.1}% ({}/{} bytes)", percentage, downloaded_bytes, total_bytes);
+                        let _ = app.emit(
+                            "download-progress",
+                            DownloadProgress {
+                                model_name: model.name.clone(),
+                                status: "downloading",
+                                downloaded_bytes,
+                                total_bytes,
+                                percentage,
+                            },
+                        );
+                        last_emitted_percentage = percentage;
+                    }
+                })
+                .await
+        })
         .await
-        .map_err(|e| format!("Failed to download: {}", e))?;
-
-    let total_bytes = response.content_length().unwrap_or(0);
-    let mut downloaded_bytes = 0u64;
-    let mut last_emitted_percentage = 0.0;
-
-    let mut file = std::fs::File::create(&model_path)
-        .map_err(|e| format!("Failed to create file: {}", e))?;
-
-    let mut stream = response.bytes_stream();
-    use futures_util::StreamExt;
-
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| format!("Download error: {}", e))?;
-        file.write_all(&chunk)
-            .map_err(|e| format!("Failed to write to file: {}", e))?;
-
-        downloaded_bytes += chunk.len() as u64;
-        let percentage = if total_bytes > 0 {
-            (downloaded_bytes as f64 / total_bytes as f64) * 100.0
-        } else {
-            0.0
-        };
+}
 
-        // Emit progress event only if percentage changed by at least 1%
-        if (percentage - last_emitted_percentage).abs() >= 1.0 || downloaded_bytes == total_bytes {
-            println!("Download progress: {:.1}% ({}/{} bytes)", percentage, downloaded_bytes, total_bytes);
-            let _ = app.emit(
-                "download-progress",
-                DownloadProgress {
-                    model_name: model_name.clone(),
-                    downloaded_bytes,
-                    total_bytes,
-                    percentage,
-                },
-            );
-            last_emitted_percentage = percentage;
-        }
+/// Stop a running or queued model download; what was downloaded is kept to resume later
+#[tauri::command]
+fn cancel_download(state: State<'_, AppState>, model_name: String) -> Result<(), String> {
+    if !state.downloads.cancel(&model_name) {
+        return Err(format!("No download of model '{}' in progress", model_name));
     }
-
-    println!("Model '{}' downloaded successfully", model_name);
+    println!("Cancelling download of model '{}'", model_name);
     Ok(())
 }
 
@@ -586,17 +1743,18 @@ fn handle_recording_complete(
 ) {
     thread::spawn(move || {
         let pipeline_start = std::time::Instant::now();
+        let language = take_recording_language(&_app_handle);
 
         // Stop recording and get audio data
         let stop_start = std::time::Instant::now();
         let (reply_tx, reply_rx) = mpsc::channel();
-        if let Err(e) = audio_tx.send(AudioCommand::StopRecording { reply: reply_tx }) {
+        if let Err(e) = audio_tx.send(AudioCommand::StopRecordingChannels { reply: reply_tx }) {
             eprintln!("Failed to send stop recording command: {}", e);
             return;
         }
 
-        let audio_data = match reply_rx.recv() {
-            Ok(Ok(data)) => data,
+        let mut channels = match reply_rx.recv() {
+            Ok(Ok(channels)) => channels,
             Ok(Err(e)) => {
                 eprintln!("Failed to stop recording: {}", e);
                 return;
@@ -607,259 +1765,787 @@ fn handle_recording_complete(
             }
         };
 
-        println!("[TIMING] stop_recording (with resampling): {:.0}ms", stop_start.elapsed().as_millis());
-        println!("Recording stopped, got {} samples", audio_data.len());
+        println!("[TIMING] stop_recording (resampler flush): {:.0}ms", stop_start.elapsed().as_millis());
+        println!(
+            "Recording stopped, got {} samples",
+            channels.iter().map(|c| c.len()).max().unwrap_or(0)
+        );
 
         // Check if we have audio data
-        if audio_data.is_empty() {
+        if channels.iter().all(|c| c.is_empty()) {
             eprintln!("No audio data recorded!");
             return;
         }
 
-        // Apply Voice Activity Detection to filter silence
-        println!("Applying VAD to filter silence...");
-
-        // Add padding at the beginning to prevent VAD from cutting the start of speech
-        // 150ms of silence gives VAD time to "warm up" and detect speech properly
-        let padding_samples = (16000.0 * 0.15) as usize; // 150ms at 16kHz
-        let mut padded_audio = vec![0.0; padding_samples];
-        padded_audio.extend_from_slice(&audio_data);
-        println!("Added {}ms padding before VAD (from {} to {} samples)",
-                 padding_samples as f32 / 16.0, audio_data.len(), padded_audio.len());
-
-        // Store original audio length before moving audio_data
-        let original_audio_len = padded_audio.len();
-
-        // Get VAD model path
-        let vad_model_path = dirs::data_dir()
-            .ok_or_else(|| "Failed to get data directory".to_string())
-            .map(|d| d.join("Flemme").join("models").join("silero_vad.onnx"));
-
-        let filtered_audio = match vad_model_path {
-            Ok(model_path) if model_path.exists() => {
-                match VoiceActivityDetector::new_default(&model_path) {
-            Ok(mut vad) => {
-                // Use 512 samples per chunk (32ms at 16kHz) for VAD analysis
-                let chunk_size = 512;
-                let filtered = vad.filter_silence(&padded_audio, chunk_size);
-
-                let original_duration = padded_audio.len() as f32 / 16000.0;
-                let filtered_duration = filtered.len() as f32 / 16000.0;
-                let silence_removed = original_duration - filtered_duration;
-
-                println!("VAD: Original={:.2}s, Filtered={:.2}s, Silence removed={:.2}s",
-                         original_duration, filtered_duration, silence_removed);
-
-                // If VAD filtered out everything, it means no speech was detected
-                // Return empty audio to skip transcription
-                filtered
+        // Microphone and system audio kept apart: transcribe each side and label it
+        let labeled = channels.iter().any(|c| c.label.is_some());
+
+        // A long recording stays in its spool file and is transcribed in chunks
+        let spooled = match channels.as_mut_slice() {
+            [channel] if !labeled => channel.spooled.take(),
+            _ => None,
+        };
+
+        let audio_to_transcribe = if labeled || spooled.is_some() {
+            Vec::new()
+        } else {
+            let audio_data = audio::source::mix_channels(std::mem::take(&mut channels));
+            let speech = filter_speech(&audio_data, load_vad().as_mut());
+            remember_recording(&_app_handle, audio_data);
+            if speech.is_empty() {
+                println!("Skipping transcription.");
+                return;
+            }
+            speech
+        };
+
+        println!("Sending audio to transcription engine...");
+
+        // Emit transcription started event to show loading indicator
+        let _ = _app_handle.emit("transcription-started", ());
+
+        // Transcribe the audio
+        let transcribe_start = std::time::Instant::now();
+        let result = if let Some(spooled) = spooled {
+            // Too long to keep for `get_vad_timeline`
+            remember_recording(&_app_handle, Vec::new());
+            let result = audio::file::AudioFileDecoder::open(&spooled.path).and_then(|decoder| {
+                transcribe_file_chunks(&transcription_tx, decoder, &spooled.preprocessing, language, |_| {})
+            });
+            match result {
+                Ok(_) => spooled.delete(),
+                Err(_) => eprintln!("Recording kept in {:?}, it can be recovered on the next start", spooled.path),
+            }
+            result
+        } else if labeled {
+            let result = transcribe_labeled_channels(&transcription_tx, &channels, load_vad().as_mut(), &language);
+            remember_recording(&_app_handle, audio::source::mix_channels(channels));
+            result
+        } else {
+            request_transcription(&transcription_tx, audio_to_transcribe, None, language).map(|result| result.text)
+        };
+        let transcription = match result {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Transcription failed: {}", e);
+                return;
+            }
+        };
+
+        println!("[TIMING] Transcription worker (includes queue wait): {:.0}ms", transcribe_start.elapsed().as_millis());
+        println!("Transcription completed: {}", transcription);
+
+        // Emit transcription completed event
+        let _ = _app_handle.emit("transcription-completed", ());
+
+        // Hide indicator window after CSS transition completes (300ms transition + 100ms buffer)
+        let app_handle_for_hide = _app_handle.clone();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+            if let Some(window) = app_handle_for_hide.get_webview_window("indicator") {
+                let _ = window.hide();
             }
+        });
+
+        // Process through LLM if using a custom execution mode
+        let final_text = apply_execution_mode(transcription);
+
+        println!("[TIMING] ==========================================");
+        println!("[TIMING] TOTAL PIPELINE: {:.0}ms", pipeline_start.elapsed().as_millis());
+        println!("[TIMING] ==========================================");
+
+        // Wait for indicator window to close and focus to return to previous app
+        std::thread::sleep(std::time::Duration::from_millis(600));
+
+        // Auto-paste the final text if enabled in settings
+        let settings = config::AppSettings::load().unwrap_or_default();
+        if !final_text.is_empty() {
+
+            if settings.auto_paste {
+                match ClipboardManager::new() {
+                    Ok(clipboard) => {
+                        if let Err(e) = clipboard.auto_paste(&final_text) {
+                            eprintln!("Failed to auto-paste: {}", e);
+                        } else {
+                            println!("Text auto-pasted successfully");
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to create clipboard manager: {}", e);
+                    }
+                }
+            } else {
+                println!("Auto-paste disabled, copying to clipboard only");
+                match ClipboardManager::new() {
+                    Ok(clipboard) => {
+                        if let Err(e) = clipboard.copy_text(&final_text) {
+                            eprintln!("Failed to copy to clipboard: {}", e);
+                        } else {
+                            println!("Text copied to clipboard");
+                        }
+                    }
                     Err(e) => {
-                        eprintln!("Failed to initialize VAD: {}. Using padded audio.", e);
-                        padded_audio
+                        eprintln!("Failed to create clipboard manager: {}", e);
                     }
                 }
             }
-            Ok(model_path) => {
-                eprintln!("VAD model not found at {:?}. Using padded audio. Please download the model first.", model_path);
-                padded_audio
+        } else {
+            println!("No text to paste (empty result)");
+        }
+    });
+}
+
+/// Keep the last recording for `get_vad_timeline`
+fn remember_recording(app: &AppHandle, audio: Vec<f32>) {
+    *app.state::<AppState>().last_recording.lock().unwrap() = audio;
+}
+
+/// Path of the Silero VAD model in the models directory
+fn vad_model_path() -> Result<std::path::PathBuf, String> {
+    transcription::downloader::models_dir().map(|d| d.join("silero_vad.onnx"))
+}
+
+#[derive(Clone, serde::Serialize)]
+struct VadModelStatus {
+    /// "downloading", "ready" or "failed"
+    status: &'static str,
+    /// Silero release the detector supports
+    version: &'static str,
+    error: Option<String>,
+}
+
+/// Download the Silero VAD model of the catalog, reporting "vad-model-status" events.
+/// Done on first run; recordings use the energy-based VAD until it succeeds.
+#[tauri::command]
+async fn download_vad_model(app: AppHandle) -> Result<(), String> {
+    let emit_status = |status, error| {
+        let _ = app.emit(
+            "vad-model-status",
+            VadModelStatus {
+                status,
+                version: audio::vad::SILERO_MODEL_VERSION,
+                error,
+            },
+        );
+    };
+
+    emit_status("downloading", None);
+    match provision_vad_model(&app).await {
+        Ok(()) => {
+            println!("Silero VAD model {} ready", audio::vad::SILERO_MODEL_VERSION);
+            emit_status("ready", None);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("Failed to provision the Silero VAD model: {}", e);
+            emit_status("failed", Some(e.clone()));
+            Err(e)
+        }
+    }
+}
+
+/// The VAD model of the catalog, if this version of Flemme supports it
+async fn catalog_vad_model() -> Result<transcription::models::ModelInfo, String> {
+    let catalog = load_model_catalog().await?;
+    let model = catalog
+        .vad_model()
+        .cloned()
+        .ok_or_else(|| "The model catalog has no VAD model".to_string())?;
+    if let Some(ref version) = model.version {
+        if version != audio::vad::SILERO_MODEL_VERSION {
+            return Err(format!(
+                "The model catalog offers Silero VAD {}, this version of Flemme supports {}",
+                version,
+                audio::vad::SILERO_MODEL_VERSION
+            ));
+        }
+    }
+    if model.name != "silero_vad.onnx" {
+        return Err(format!("The VAD model must be named silero_vad.onnx, not {}", model.name));
+    }
+    Ok(model)
+}
+
+/// Download the VAD model, replacing the one on disk once the new one passed
+/// its checks (the downloader moves it in place only when complete)
+async fn provision_vad_model(app: &AppHandle) -> Result<(), String> {
+    let model = catalog_vad_model().await?;
+    let model_path = vad_model_path()?;
+    let backup_path = model_path.with_file_name("silero_vad.onnx.previous");
+    let backup = model_path.exists();
+    if backup {
+        std::fs::copy(&model_path, &backup_path).map_err(|e| format!("Failed to back up the VAD model: {}", e))?;
+    }
+
+    let result = match queue_download(app, model.clone()).await {
+        // Put the previous model back, or leave none rather than one that can't be used
+        Ok(_) => check_vad_model(&model).await.inspect_err(|_| {
+            let restored = if backup {
+                std::fs::rename(&backup_path, &model_path)
+            } else {
+                std::fs::remove_file(&model_path)
+            };
+            if let Err(e) = restored {
+                eprintln!("Failed to restore the previous VAD model: {}", e);
             }
+        }),
+        Err(e) => Err(e),
+    };
+    if backup_path.exists() {
+        let _ = std::fs::remove_file(&backup_path);
+    }
+    result
+}
+
+/// Check the VAD model on disk against the catalog and load it once, so a
+/// model with other inputs is rejected now rather than on every recording
+async fn check_vad_model(model: &transcription::models::ModelInfo) -> Result<(), String> {
+    transcription::ModelDownloader::new()?.verify_model(model)?;
+    let model_path = vad_model_path()?;
+    tauri::async_runtime::spawn_blocking(move || VoiceActivityDetector::new_default(&model_path).map(|_| ()))
+        .await
+        .map_err(|e| format!("VAD model check failed: {}", e))?
+}
+
+/// Whether the VAD model on disk can be used as is
+async fn vad_model_ready() -> bool {
+    if !vad_model_path().is_ok_and(|path| path.exists()) {
+        return false;
+    }
+    match catalog_vad_model().await {
+        Ok(model) => match check_vad_model(&model).await {
+            Ok(()) => true,
             Err(e) => {
-                eprintln!("Failed to get VAD model path: {}. Using padded audio.", e);
-                padded_audio
+                eprintln!("{}. Downloading the VAD model again.", e);
+                false
             }
-        };
+        },
+        // Nothing better to download
+        Err(_) => true,
+    }
+}
 
-        // Check if we still have audio after VAD filtering
-        // If VAD removed everything or most of the audio (>95%), it might be too aggressive
-        let audio_to_transcribe = if filtered_audio.is_empty() {
-            println!("Warning: VAD filtered out all audio. This might be a very short recording or pure silence.");
-            println!("Skipping transcription.");
-            return;
-        } else if filtered_audio.len() < (original_audio_len / 20) {
-            // Less than 5% remains - likely too aggressive, but still try to transcribe
-            println!("Warning: VAD removed >95% of audio ({:.2}s -> {:.2}s). This might be a very short utterance.",
-                     original_audio_len as f32 / 16000.0, filtered_audio.len() as f32 / 16000.0);
-            filtered_audio
-        } else {
-            filtered_audio
-        };
+/// Load the VAD tuned from settings
+fn load_vad() -> Box<dyn Vad> {
+    let settings = config::AppSettings::load().unwrap_or_default();
+    load_vad_with(settings.vad).0
+}
 
-        println!("Sending audio to transcription engine...");
+/// Load the Silero VAD model if it has been downloaded, or else the energy-based
+/// detector, with the reason Silero could not be used
+fn load_vad_with(settings: config::VadSettings) -> (Box<dyn Vad>, Option<String>) {
+    let reason = match vad_model_path() {
+        Ok(model_path) if model_path.exists() => {
+            match VoiceActivityDetector::with_settings(&model_path, settings.clone()) {
+                Ok(vad) => return (Box::new(vad), None),
+                Err(e) => format!("Failed to initialize Silero VAD: {}", e),
+            }
+        }
+        Ok(model_path) => format!("Silero VAD model not found at {:?}", model_path),
+        Err(e) => format!("Failed to get VAD model path: {}", e),
+    };
+    eprintln!("{}. Using the energy-based VAD.", reason);
 
-        // Emit transcription started event to show loading indicator
-        let _ = _app_handle.emit("transcription-started", ());
+    let vad = EnergyVad::new(settings).or_else(|e| {
+        eprintln!("Invalid VAD settings: {}. Using the defaults.", e);
+        EnergyVad::new(config::VadSettings::default())
+    });
+    (Box::new(vad.expect("Default VAD settings are valid")), Some(reason))
+}
 
-        // Load settings to get language preference
-        let settings = config::AppSettings::load().unwrap_or_default();
-        let language = Some(settings.language);
+/// Apply Voice Activity Detection to 16kHz audio before transcription
+/// Returns an empty buffer when no speech was detected
+fn filter_speech(audio_data: &[f32], vad: &mut dyn Vad) -> Vec<f32> {
+    println!("Applying VAD ({}) to filter silence...", vad.name());
+
+    let original_audio_len = audio_data.len();
+
+    // 512 samples per chunk (32ms at 16kHz) by default
+    let chunk_size = vad.chunk_size();
+    // Speech segments are padded by `speech_pad_ms` on both sides. If VAD
+    // filtered out everything, no speech was detected: return empty audio to
+    // skip transcription
+    let filtered_audio = vad.filter_silence(audio_data, chunk_size);
+
+    let original_duration = audio_data.len() as f32 / 16000.0;
+    let filtered_duration = filtered_audio.len() as f32 / 16000.0;
+    let silence_removed = original_duration - filtered_duration;
+
+    println!("VAD: Original={:.2}s, Filtered={:.2}s, Silence removed={:.2}s",
+             original_duration, filtered_duration, silence_removed);
+
+    // Check if we still have audio after VAD filtering
+    // If VAD removed everything or most of the audio (>95%), it might be too aggressive
+    if filtered_audio.is_empty() {
+        println!("Warning: VAD filtered out all audio. This might be a very short recording or pure silence.");
+    } else if filtered_audio.len() < (original_audio_len / 20) {
+        // Less than 5% remains - likely too aggressive, but still try to transcribe
+        println!("Warning: VAD removed >95% of audio ({:.2}s -> {:.2}s). This might be a very short utterance.",
+                 original_audio_len as f32 / 16000.0, filtered_audio.len() as f32 / 16000.0);
+    }
 
-        // Transcribe the audio
-        let transcribe_start = std::time::Instant::now();
-        let (reply_tx, reply_rx) = mpsc::channel();
-        if let Err(e) = transcription_tx.send(TranscriptionCommand::Transcribe {
-            audio: audio_to_transcribe,
+    filtered_audio
+}
+
+/// Send audio to the transcription worker and wait for the transcription
+fn request_transcription(
+    transcription_tx: &Sender<TranscriptionCommand>,
+    audio: Vec<f32>,
+    prompt: Option<String>,
+    language: transcription::LanguageSelection,
+) -> Result<transcription::TranscriptionResult, String> {
+    let decode = config::AppSettings::load().unwrap_or_default().decode_options();
+
+    let (reply_tx, reply_rx) = mpsc::channel();
+    transcription_tx
+        .send(TranscriptionCommand::Transcribe {
+            audio,
             language,
+            prompt,
+            decode,
             reply: reply_tx,
-        }) {
-            eprintln!("Failed to send transcription command: {}", e);
-            return;
-        }
+        })
+        .map_err(|e| format!("Failed to send transcription command: {}", e))?;
 
-        let transcription = match reply_rx.recv() {
-            Ok(Ok(text)) => text,
-            Ok(Err(e)) => {
-                eprintln!("Transcription failed: {}", e);
-                return;
-            }
-            Err(e) => {
-                eprintln!("Failed to receive transcription: {}", e);
-                return;
-            }
-        };
+    reply_rx
+        .recv()
+        .map_err(|e| format!("Failed to receive transcription: {}", e))?
+}
 
-        println!("[TIMING] Transcription worker (includes queue wait): {:.0}ms", transcribe_start.elapsed().as_millis());
-        println!("Transcription completed: {}", transcription);
+/// Transcribe each labeled channel utterance by utterance and interleave them
+/// in time order as "Label: text" lines (e.g. "Me: ..." / "Others: ...")
+fn transcribe_labeled_channels(
+    transcription_tx: &Sender<TranscriptionCommand>,
+    channels: &[AudioChannel],
+    vad: &mut dyn Vad,
+    language: &transcription::LanguageSelection,
+) -> Result<String, String> {
+    let mut utterances: Vec<(usize, String, String)> = Vec::new();
+
+    for channel in channels {
+        let label = channel.label.clone().unwrap_or_default();
+        // Padded and merged as when filtering a single recording
+        let segments = vad.padded_speech_segments(&channel.samples, vad.chunk_size());
+        println!("Channel '{}': {} utterance(s)", label, segments.len());
+
+        for segment in segments {
+            let audio = channel.samples[segment.start..segment.end].to_vec();
+            let text = request_transcription(transcription_tx, audio, None, language.clone())?.text;
+            let text = text.trim();
+            if !text.is_empty() {
+                utterances.push((segment.start, label.clone(), text.to_string()));
+            }
+        }
+    }
 
-        // Emit transcription completed event
-        let _ = _app_handle.emit("transcription-completed", ());
+    utterances.sort_by_key(|(start, _, _)| *start);
+    Ok(utterances
+        .into_iter()
+        .map(|(_, label, text)| format!("{}: {}", label, text))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
 
-        // Hide indicator window after CSS transition completes (300ms transition + 100ms buffer)
-        let app_handle_for_hide = _app_handle.clone();
-        tauri::async_runtime::spawn(async move {
-            tokio::time::sleep(std::time::Duration::from_millis(400)).await;
-            if let Some(window) = app_handle_for_hide.get_webview_window("indicator") {
-                let _ = window.hide();
-            }
-        });
+/// Run the transcription through the LLM of the active execution mode
+/// Falls back to the raw transcription on any error
+fn apply_execution_mode(transcription: String) -> String {
+    let settings = config::AppSettings::load().unwrap_or_default();
+    if !transcription.is_empty() && settings.active_mode != "standard" {
+        // Find the active execution mode
+        let mode = settings.execution_modes.iter()
+            .find(|m| m.id == settings.active_mode);
+
+        if let Some(mode) = mode {
+            if let Some(ref llm_model_id) = mode.llm_model_id {
+                // Find the LLM model
+                let llm_model = settings.llm_models.iter()
+                    .find(|m| m.id == *llm_model_id);
+
+                if let Some(llm_model) = llm_model {
+                    println!("=== EXECUTING MODE: {} ===", mode.name);
+                    println!("Using LLM: {}", llm_model.name);
+                    println!("Service Type: {:?}", llm_model.service_type);
+
+                    // Get API key from keyring, or use empty string for local providers
+                    let api_key = if llm_model.service_type.requires_api_key() {
+                        match llm::keyring_manager::get_api_key(llm_model_id) {
+                            Ok(Some(key)) => key,
+                            Ok(None) => {
+                                eprintln!("No API key found for LLM: {}", llm_model_id);
+                                eprintln!("Falling back to raw transcription");
+                                transcription.clone()
+                            }
+                            Err(e) => {
+                                eprintln!("Failed to retrieve API key: {}", e);
+                                eprintln!("Falling back to raw transcription");
+                                transcription.clone()
+                            }
+                        }
+                    } else {
+                        println!("Local LLM provider - no API key required");
+                        String::new()
+                    };
 
-        // Process through LLM if using a custom execution mode
-        let settings = config::AppSettings::load().unwrap_or_default();
-        let final_text = if !transcription.is_empty() && settings.active_mode != "standard" {
-            // Find the active execution mode
-            let mode = settings.execution_modes.iter()
-                .find(|m| m.id == settings.active_mode);
-
-            if let Some(mode) = mode {
-                if let Some(ref llm_model_id) = mode.llm_model_id {
-                    // Find the LLM model
-                    let llm_model = settings.llm_models.iter()
-                        .find(|m| m.id == *llm_model_id);
-
-                    if let Some(llm_model) = llm_model {
-                        println!("=== EXECUTING MODE: {} ===", mode.name);
-                        println!("Using LLM: {}", llm_model.name);
-                        println!("Service Type: {:?}", llm_model.service_type);
-
-                        // Get API key from keyring, or use empty string for local providers
-                        let api_key = if llm_model.service_type.requires_api_key() {
-                            match llm::keyring_manager::get_api_key(llm_model_id) {
-                                Ok(Some(key)) => key,
-                                Ok(None) => {
-                                    eprintln!("No API key found for LLM: {}", llm_model_id);
-                                    eprintln!("Falling back to raw transcription");
-                                    transcription.clone()
-                                }
-                                Err(e) => {
-                                    eprintln!("Failed to retrieve API key: {}", e);
-                                    eprintln!("Falling back to raw transcription");
-                                    transcription.clone()
-                                }
+                    // Only proceed if we got an API key (or don't need one)
+                    if !llm_model.service_type.requires_api_key() || !api_key.is_empty() {
+                        // Call the LLM asynchronously
+                        let llm_model_clone = llm_model.clone();
+                        let system_prompt = mode.system_prompt.clone();
+                        let transcription_clone = transcription.clone();
+
+                        println!("Calling LLM API...");
+                        let runtime = tokio::runtime::Runtime::new().unwrap();
+                        match runtime.block_on(llm::call_llm(
+                            &llm_model_clone,
+                            &api_key,
+                            &system_prompt,
+                            &transcription_clone
+                        )) {
+                            Ok(llm_response) => {
+                                println!("LLM processing successful");
+                                llm_response
                             }
-                        } else {
-                            println!("Local LLM provider - no API key required");
-                            String::new()
-                        };
-
-                        // Only proceed if we got an API key (or don't need one)
-                        if !llm_model.service_type.requires_api_key() || !api_key.is_empty() {
-                            // Call the LLM asynchronously
-                            let llm_model_clone = llm_model.clone();
-                            let system_prompt = mode.system_prompt.clone();
-                            let transcription_clone = transcription.clone();
-
-                            println!("Calling LLM API...");
-                            let runtime = tokio::runtime::Runtime::new().unwrap();
-                            match runtime.block_on(llm::call_llm(
-                                &llm_model_clone,
-                                &api_key,
-                                &system_prompt,
-                                &transcription_clone
-                            )) {
-                                Ok(llm_response) => {
-                                    println!("LLM processing successful");
-                                    llm_response
-                                }
-                                Err(e) => {
-                                    eprintln!("LLM call failed: {}", e);
-                                    eprintln!("Falling back to raw transcription");
-                                    transcription
-                                }
+                            Err(e) => {
+                                eprintln!("LLM call failed: {}", e);
+                                eprintln!("Falling back to raw transcription");
+                                transcription
                             }
-                        } else {
-                            transcription
                         }
                     } else {
-                        eprintln!("LLM model not found: {}", llm_model_id);
-                        eprintln!("Falling back to raw transcription");
                         transcription
                     }
                 } else {
-                    // Mode has no LLM configured, use raw transcription
+                    eprintln!("LLM model not found: {}", llm_model_id);
+                    eprintln!("Falling back to raw transcription");
                     transcription
                 }
             } else {
-                eprintln!("Active mode not found: {}", settings.active_mode);
-                eprintln!("Falling back to raw transcription");
+                // Mode has no LLM configured, use raw transcription
                 transcription
             }
         } else {
-            // Standard mode or empty transcription
+            eprintln!("Active mode not found: {}", settings.active_mode);
+            eprintln!("Falling back to raw transcription");
             transcription
-        };
+        }
+    } else {
+        // Standard mode or empty transcription
+        transcription
+    }
+}
 
-        println!("[TIMING] ==========================================");
-        println!("[TIMING] TOTAL PIPELINE: {:.0}ms", pipeline_start.elapsed().as_millis());
-        println!("[TIMING] ==========================================");
+/// Longest piece of a file sent to Whisper at once (Whisper works on 30s windows)
+const FILE_CHUNK_SECONDS: usize = 30;
 
-        // Wait for indicator window to close and focus to return to previous app
-        std::thread::sleep(std::time::Duration::from_millis(600));
+/// How much of a file is decoded at a time
+const FILE_BLOCK_SECONDS: usize = 10;
 
-        // Auto-paste the final text if enabled in settings
-        if !final_text.is_empty() {
+#[derive(Clone, serde::Serialize)]
+struct FileTranscriptionProgress {
+    path: String,
+    stage: String, // "transcribing", "processing" or "completed"
+    processed_seconds: f64,
+    total_seconds: Option<f64>,
+    percentage: Option<f64>,
+}
 
-            if settings.auto_paste {
-                match ClipboardManager::new() {
-                    Ok(clipboard) => {
-                        if let Err(e) = clipboard.auto_paste(&final_text) {
-                            eprintln!("Failed to auto-paste: {}", e);
-                        } else {
-                            println!("Text auto-pasted successfully");
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to create clipboard manager: {}", e);
-                    }
-                }
-            } else {
-                println!("Auto-paste disabled, copying to clipboard only");
-                match ClipboardManager::new() {
-                    Ok(clipboard) => {
-                        if let Err(e) = clipboard.copy_text(&final_text) {
-                            eprintln!("Failed to copy to clipboard: {}", e);
-                        } else {
-                            println!("Text copied to clipboard");
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to create clipboard manager: {}", e);
-                    }
-                }
+/// Transcribe an audio file through the same VAD → Whisper → LLM pipeline as a recording
+/// Returns the final text instead of pasting it
+#[tauri::command]
+async fn transcribe_file(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<String, String> {
+    let transcription_tx = state.transcription_tx.clone();
+    tauri::async_runtime::spawn_blocking(move || transcribe_audio_file(&app, &transcription_tx, &path))
+        .await
+        .map_err(|e| format!("File transcription task failed: {}", e))?
+}
+
+/// Decode the file incrementally and transcribe it in chunks of at most
+/// `FILE_CHUNK_SECONDS`, cut in silences found by the VAD, so memory stays
+/// bounded whatever the file length.
+fn transcribe_audio_file(
+    app: &AppHandle,
+    transcription_tx: &Sender<TranscriptionCommand>,
+    path: &str,
+) -> Result<String, String> {
+    let file_start = std::time::Instant::now();
+    let decoder = audio::file::AudioFileDecoder::open(path)?;
+    let total_seconds = decoder.duration_seconds();
+    let settings = config::AppSettings::load().unwrap_or_default();
+    let processed_samples = std::cell::Cell::new(0);
+
+    let emit_progress = |stage: &str, processed_samples: usize| {
+        let processed_seconds = processed_samples as f64 / 16000.0;
+        let _ = app.emit(
+            "file-transcription-progress",
+            FileTranscriptionProgress {
+                path: path.to_string(),
+                stage: stage.to_string(),
+                processed_seconds,
+                total_seconds,
+                percentage: total_seconds
+                    .filter(|total| *total > 0.0)
+                    .map(|total| (processed_seconds / total * 100.0).min(100.0)),
+            },
+        );
+    };
+
+    // Decoded chunk by chunk, the file is not kept for `get_vad_timeline`
+    remember_recording(app, Vec::new());
+    emit_progress("transcribing", 0);
+    let transcription = transcribe_file_chunks(
+        transcription_tx,
+        decoder,
+        &settings.preprocessing,
+        settings.language_selection(),
+        |processed| {
+            processed_samples.set(processed);
+            emit_progress("transcribing", processed);
+        },
+    )?;
+
+    emit_progress("processing", processed_samples.get());
+    let final_text = apply_execution_mode(transcription);
+    emit_progress("completed", processed_samples.get());
+
+    println!("[TIMING] File transcription TOTAL: {:.0}ms", file_start.elapsed().as_millis());
+    Ok(final_text)
+}
+
+/// Transcribe decoded audio chunk by chunk, reporting the samples done so far
+/// to `progress`. Returns the transcription before the execution mode.
+fn transcribe_file_chunks(
+    transcription_tx: &Sender<TranscriptionCommand>,
+    mut decoder: audio::file::AudioFileDecoder,
+    preprocessing: &config::PreprocessingSettings,
+    mut language: transcription::LanguageSelection,
+    progress: impl Fn(usize),
+) -> Result<String, String> {
+    let mut vad = load_vad();
+    let chunk_samples = FILE_CHUNK_SECONDS * 16000;
+    let mut pending: Vec<f32> = Vec::with_capacity(chunk_samples + FILE_BLOCK_SECONDS * 16000);
+    let mut processed_samples = 0usize;
+    let mut texts: Vec<String> = Vec::new();
+    let mut end_of_file = false;
+
+    while !end_of_file || !pending.is_empty() {
+        // Top up the pending audio until a full chunk is available
+        while !end_of_file && pending.len() < chunk_samples {
+            match decoder.next_block(FILE_BLOCK_SECONDS * 16000)? {
+                Some(block) => pending.extend_from_slice(&block),
+                None => end_of_file = true,
             }
-        } else {
-            println!("No text to paste (empty result)");
         }
-    });
+
+        let window_len = pending.len().min(chunk_samples);
+        let mut window = pending[..window_len].to_vec();
+        audio::processing::prepare_for_transcription(&mut window, preprocessing);
+
+        // One VAD pass per chunk places the cut and filters the speech
+        let chunk_size = vad.chunk_size();
+        let probabilities = vad.probabilities(&window, chunk_size);
+
+        let is_tail = pending.len() <= chunk_samples;
+        let cut = chunk_cut(&probabilities.segments(vad.settings()), window_len, is_tail);
+        pending.drain(..cut);
+        processed_samples += cut;
+
+        let speech = probabilities.truncated(cut).filter(&window[..cut], vad.settings());
+        if !speech.is_empty() {
+            let result = request_transcription(transcription_tx, speech, None, language.clone())?;
+            // The whole file is in the language detected on its first chunk
+            if let (transcription::LanguageSelection::Detect(_), Some(detected)) = (&language, &result.language) {
+                println!("File language: {}", detected);
+                language = transcription::LanguageSelection::Fixed(detected.clone());
+            }
+            let text = result.text.trim();
+            if !text.is_empty() {
+                texts.push(text.to_string());
+            }
+        }
+
+        progress(processed_samples);
+    }
+
+    let transcription = texts.join(" ");
+    println!("File transcription completed ({} chunks): {}", texts.len(), transcription);
+    Ok(transcription)
+}
+
+/// Recordings left on disk by a previous run that crashed or quit while recording.
+/// The UI calls it once loaded: an event sent at startup would arrive before it listens.
+#[tauri::command]
+fn list_orphaned_recordings() -> Result<Vec<audio::OrphanedRecording>, String> {
+    Ok(audio::spool::list_orphans(&audio::spool::spool_dir()?))
+}
+
+/// Transcribe an orphaned recording through the file pipeline, then delete it
+#[tauri::command]
+async fn recover_recording(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<String, String> {
+    let path = find_orphaned_recording(&path)?;
+    let transcription_tx = state.transcription_tx.clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        audio::spool::repair_spool(&path)?;
+        let text = transcribe_audio_file(&app, &transcription_tx, &path.to_string_lossy())?;
+        if let Err(e) = std::fs::remove_file(&path) {
+            eprintln!("Failed to delete recovered recording {:?}: {}", path, e);
+        }
+        println!("Recovered recording {:?}", path);
+        Ok(text)
+    })
+    .await
+    .map_err(|e| format!("Recovery task failed: {}", e))?
+}
+
+/// Delete an orphaned recording without transcribing it
+#[tauri::command]
+fn discard_orphaned_recording(path: String) -> Result<(), String> {
+    let path = find_orphaned_recording(&path)?;
+    std::fs::remove_file(&path).map_err(|e| format!("Failed to delete recording {:?}: {}", path, e))
+}
+
+/// Check that `path` is one of the orphaned recordings (not any file on disk)
+fn find_orphaned_recording(path: &str) -> Result<std::path::PathBuf, String> {
+    audio::spool::list_orphans(&audio::spool::spool_dir()?)
+        .into_iter()
+        .find(|o| o.path == path)
+        .map(|o| std::path::PathBuf::from(o.path))
+        .ok_or_else(|| format!("No orphaned recording at {}", path))
+}
+
+/// Find where to cut a chunk: in the middle of the longest silence between speech
+/// segments, or at the end of the chunk if there is none
+fn find_silence_cut(segments: &[audio::SpeechSegment], len: usize) -> usize {
+    // Silences between consecutive segments, plus the trailing silence
+    let mut gaps: Vec<(usize, usize)> = segments
+        .windows(2)
+        .map(|pair| (pair[0].end, pair[1].start))
+        .collect();
+    if let Some(last) = segments.last() {
+        gaps.push((last.end, len));
+    }
+
+    // Cut in the middle of the longest silence (the latest one on ties)
+    gaps.into_iter()
+        .filter(|(start, end)| end > start)
+        .max_by_key(|(start, end)| (end - start, *start))
+        .map(|(start, end)| (start + end) / 2)
+        .filter(|&cut| cut > 0)
+        .unwrap_or(len)
+}
+
+/// Samples of a file window to transcribe as the next chunk: up to the longest
+/// silence of the window, or the whole window at the end of the file
+fn chunk_cut(segments: &[audio::SpeechSegment], window_len: usize, is_tail: bool) -> usize {
+    if is_tail {
+        return window_len;
+    }
+    find_silence_cut(segments, window_len)
+}
+
+#[derive(Clone, serde::Serialize)]
+struct VadTimelineSegment {
+    start_seconds: f64,
+    end_seconds: f64,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct VadTimeline {
+    /// Detector that produced the timeline ("silero" or "energy")
+    detector: String,
+    duration_seconds: f64,
+    speech_ratio: f32,
+    /// More speech than `keep_all_speech_ratio`: the recording is transcribed whole
+    kept_whole: bool,
+    /// Speech segments kept otherwise, padded and merged as when filtering
+    segments: Vec<VadTimelineSegment>,
+}
+
+/// Run the VAD on the last recording and return where it found speech, with
+/// the saved VAD settings or the ones being tried out (`vad`). Fails when the
+/// last transcription was a file or a recording too long to keep.
+#[tauri::command]
+async fn get_vad_timeline(
+    state: State<'_, AppState>,
+    vad: Option<config::VadSettings>,
+) -> Result<VadTimeline, String> {
+    let audio = state.last_recording.lock().unwrap().clone();
+    if audio.is_empty() {
+        return Err("No recording to analyze: files and long recordings are not kept".to_string());
+    }
+    let settings = match vad {
+        Some(settings) => settings,
+        None => config::AppSettings::load()?.vad,
+    };
+
+    tauri::async_runtime::spawn_blocking(move || vad_timeline(&audio, settings))
+        .await
+        .map_err(|e| format!("VAD timeline task failed: {}", e))?
+}
+
+#[derive(Clone, serde::Serialize)]
+struct VadDiagnostics {
+    /// Detector used for recordings ("silero" or "energy")
+    detector: String,
+    silero_model_path: Option<String>,
+    /// Why Silero is not used, when falling back to the energy-based VAD
+    fallback_reason: Option<String>,
+}
+
+/// Report which voice activity detector recordings go through
+#[tauri::command]
+async fn get_vad_diagnostics() -> Result<VadDiagnostics, String> {
+    let settings = config::AppSettings::load()?.vad;
+    tauri::async_runtime::spawn_blocking(move || {
+        let (vad, fallback_reason) = load_vad_with(settings);
+        VadDiagnostics {
+            detector: vad.name().to_string(),
+            silero_model_path: vad_model_path().ok().map(|path| path.to_string_lossy().to_string()),
+            fallback_reason,
+        }
+    })
+    .await
+    .map_err(|e| format!("VAD diagnostics task failed: {}", e))
+}
+
+fn vad_timeline(audio: &[f32], settings: config::VadSettings) -> Result<VadTimeline, String> {
+    settings.validate()?;
+    let keep_all_speech_ratio = settings.keep_all_speech_ratio;
+    let (mut vad, _) = load_vad_with(settings);
+
+    let probabilities = vad.probabilities(audio, vad.chunk_size());
+    let speech_ratio = probabilities.speech_ratio(vad.threshold());
+    let segments = probabilities.padded_segments(vad.settings());
+    println!(
+        "VAD timeline ({}): {:.1}% speech, {} segment(s) in {:.2}s",
+        vad.name(),
+        speech_ratio * 100.0,
+        segments.len(),
+        audio.len() as f32 / 16000.0
+    );
+
+    let seconds = |samples: usize| samples as f64 / 16000.0;
+    Ok(VadTimeline {
+        detector: vad.name().to_string(),
+        duration_seconds: seconds(audio.len()),
+        speech_ratio,
+        kept_whole: speech_ratio > keep_all_speech_ratio,
+        segments: segments
+            .iter()
+            .map(|segment| VadTimelineSegment {
+                start_seconds: seconds(segment.start),
+                end_seconds: seconds(segment.end),
+            })
+            .collect(),
+    })
 }
 
 // ============================================================================
@@ -1063,7 +2749,7 @@ fn get_indicator_info() -> Result<(String, String), String> {
 
 /// Set the active execution mode
 #[tauri::command]
-fn set_active_mode(mode_id: String) -> Result<(), String> {
+fn set_active_mode(app: AppHandle, mode_id: String) -> Result<(), String> {
     let mut settings = config::AppSettings::load()
         .map_err(|e| format!("Failed to load settings: {}", e))?;
 
@@ -1077,19 +2763,27 @@ fn set_active_mode(mode_id: String) -> Result<(), String> {
         .map_err(|e| format!("Failed to save settings: {}", e))?;
 
     println!("Active execution mode set to: {}", mode_id);
+    refresh_tray_menu(&app);
     Ok(())
 }
 
 /// Add a new execution mode
 #[tauri::command]
 fn add_execution_mode(
+    app: AppHandle,
     name: String,
     llm_model_id: Option<String>,
     system_prompt: String,
+    decode: Option<config::WhisperDecodeOptions>,
+    style_hint: Option<String>,
 ) -> Result<String, String> {
     let mut settings = config::AppSettings::load()
         .map_err(|e| format!("Failed to load settings: {}", e))?;
 
+    if let Some(ref decode) = decode {
+        decode.validate()?;
+    }
+
     // If an LLM model is specified, verify it exists
     if let Some(ref model_id) = llm_model_id {
         if !settings.llm_models.iter().any(|m| m.id == *model_id) {
@@ -1106,28 +2800,38 @@ fn add_execution_mode(
         name,
         llm_model_id,
         system_prompt,
+        decode,
+        style_hint: style_hint.unwrap_or_default(),
     });
 
     settings.save()
         .map_err(|e| format!("Failed to save settings: {}", e))?;
 
     println!("Execution mode added with ID: {}", id);
+    refresh_tray_menu(&app);
     Ok(id)
 }
 
 /// Update an existing execution mode
 #[tauri::command]
 fn update_execution_mode(
+    app: AppHandle,
     id: String,
     name: String,
     llm_model_id: Option<String>,
     system_prompt: String,
+    decode: Option<config::WhisperDecodeOptions>,
+    style_hint: Option<String>,
 ) -> Result<(), String> {
     // Prevent modifying the standard mode
     if id == "standard" {
         return Err("Cannot modify the built-in 'standard' mode".to_string());
     }
 
+    if let Some(ref decode) = decode {
+        decode.validate()?;
+    }
+
     let mut settings = config::AppSettings::load()
         .map_err(|e| format!("Failed to load settings: {}", e))?;
 
@@ -1147,17 +2851,20 @@ fn update_execution_mode(
     mode.name = name;
     mode.llm_model_id = llm_model_id;
     mode.system_prompt = system_prompt;
+    mode.decode = decode;
+    mode.style_hint = style_hint.unwrap_or_default();
 
     settings.save()
         .map_err(|e| format!("Failed to save settings: {}", e))?;
 
     println!("Execution mode updated: {}", id);
+    refresh_tray_menu(&app);
     Ok(())
 }
 
 /// Delete an execution mode
 #[tauri::command]
-fn delete_execution_mode(id: String) -> Result<(), String> {
+fn delete_execution_mode(app: AppHandle, id: String) -> Result<(), String> {
     // Prevent deleting the standard mode
     if id == "standard" {
         return Err("Cannot delete the built-in 'standard' mode".to_string());
@@ -1184,6 +2891,7 @@ fn delete_execution_mode(id: String) -> Result<(), String> {
         .map_err(|e| format!("Failed to save settings: {}", e))?;
 
     println!("Execution mode deleted: {}", id);
+    refresh_tray_menu(&app);
     Ok(())
 }
 
@@ -1211,12 +2919,6 @@ pub fn run() {
     // Create channel for transcription commands
     let (transcription_tx, transcription_rx) = mpsc::channel();
 
-    // Spawn audio worker thread
-    thread::spawn(move || {
-        let worker = AudioWorker::new(audio_rx);
-        worker.run();
-    });
-
     // Spawn transcription worker thread
     thread::spawn(move || {
         let worker = TranscriptionWorker::new(model_path, transcription_rx);
@@ -1231,12 +2933,39 @@ pub fn run() {
         .manage(AppState {
             audio_tx: audio_tx.clone(),
             transcription_tx: transcription_tx.clone(),
+            toggle_recording: std::sync::Arc::new(std::sync::Mutex::new(false)),
+            last_recording: std::sync::Mutex::new(Vec::new()),
+            downloads: transcription::downloader::DownloadQueue::default(),
+            next_language: std::sync::Mutex::new(None),
         })
         .setup(move |app| {
+            // Spawn audio worker thread (reports device changes to the UI)
+            let audio_app_handle = app.handle().clone();
+            thread::spawn(move || {
+                let worker = AudioWorker::new(audio_rx).with_app_handle(audio_app_handle);
+                worker.run();
+            });
+
+            // The UI offers to recover them through `list_orphaned_recordings`
+            if let Ok(dir) = audio::spool::spool_dir() {
+                let orphans = audio::spool::list_orphans(&dir);
+                if !orphans.is_empty() {
+                    println!("Found {} interrupted recording(s) in {:?}", orphans.len(), dir);
+                }
+            }
+
+            // Fetch the Silero VAD model on first run, and again when the one on
+            // disk is not the release of the catalog (e.g. left by an older Flemme)
+            let vad_app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                if !vad_model_ready().await {
+                    let _ = download_vad_model(vad_app_handle).await;
+                }
+            });
+
             #[cfg(desktop)]
             {
                 use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
-                use tauri::menu::{MenuBuilder, MenuItemBuilder};
                 use tauri::tray::{TrayIconBuilder, TrayIconEvent};
                 use tauri::Manager;
                 use tauri::{WebviewUrl, WebviewWindowBuilder};
@@ -1277,9 +3006,8 @@ pub fn run() {
                 let transcription_tx_clone = transcription_tx.clone();
                 let app_handle = app.handle().clone();
 
-                // Track recording state for toggle mode
-                use std::sync::{Arc, Mutex};
-                let is_recording = Arc::new(Mutex::new(false));
+                // Track recording state for toggle mode (also reset by the audio worker's auto-stop)
+                let is_recording = app.state::<AppState>().toggle_recording.clone();
                 let is_recording_clone = is_recording.clone();
                 let is_recording_for_cancel = is_recording.clone();
 
@@ -1290,7 +3018,7 @@ pub fn run() {
                 let settings = config::AppSettings::load().unwrap_or_default();
                 let cancel_key = settings.cancel_key.clone();
 
-                // Register the global shortcut plugin with handler for both main and cancel keys
+                // Register the global shortcut plugin with handler for the main, cancel and language keys
                 app.handle().plugin(
                     tauri_plugin_global_shortcut::Builder::new()
                         .with_handler(move |_app, shortcut, event| {
@@ -1298,6 +3026,14 @@ pub fn run() {
                             let settings = config::AppSettings::load().unwrap_or_default();
                             let shortcut_str = shortcut.to_string();
 
+                            // Language of the next recording (read from settings, so a changed key applies at once)
+                            if settings.language_switch_key.as_deref() == Some(shortcut_str.as_str()) {
+                                if let ShortcutState::Pressed = event.state() {
+                                    cycle_next_language(_app);
+                                }
+                                return;
+                            }
+
                             // Check if this is the cancel key
                             if shortcut_str == cancel_key {
                                 // Only handle in toggle mode when recording is active
@@ -1358,7 +3094,13 @@ pub fn run() {
                                 if let ShortcutState::Pressed = event.state() {
                                     let mut recording = is_recording_clone.lock().unwrap();
 
-                                    if *recording {
+                                    if *recording && settings.continuous_dictation {
+                                        // The utterances were inserted along the way, only the last one is left
+                                        println!("Hotkey pressed (toggle) - ending continuous dictation");
+                                        *recording = false;
+                                        let _ = app_handle.emit("recording-stopped", ());
+                                        let _ = audio_tx_clone.send(AudioCommand::StopDictation);
+                                    } else if *recording {
                                         // Already recording, stop it
                                         println!("Hotkey pressed (toggle) - stopping recording and transcribing");
                                         *recording = false;
@@ -1378,10 +3120,13 @@ pub fn run() {
                                         let _ = app_handle.emit("recording-started", ());
                                         let _ = audio_tx_clone.send(AudioCommand::StartRecording);
 
-                                        // Show indicator window
+                                        // Show indicator window (without focus during a continuous
+                                        // dictation, the text is pasted into the active window)
                                         if let Some(window) = _app.get_webview_window("indicator") {
                                             let _ = window.show();
-                                            let _ = window.set_focus();
+                                            if !settings.continuous_dictation {
+                                                let _ = window.set_focus();
+                                            }
                                         }
                                     }
                                 }
@@ -1402,38 +3147,26 @@ pub fn run() {
                     println!("Cancel shortcut registered: {}", settings.cancel_key);
                 }
 
+                // Register the language switch shortcut
+                if let Some(ref language_key) = settings.language_switch_key {
+                    match Shortcut::from_str(language_key) {
+                        Ok(language_shortcut) => {
+                            app.global_shortcut().register(language_shortcut)?;
+                            println!("Language switch shortcut registered: {}", language_key);
+                        }
+                        Err(e) => eprintln!("Invalid language switch shortcut '{}': {}", language_key, e),
+                    }
+                }
+
                 // Clone for menu event handler
                 let audio_tx_for_quit = audio_tx.clone();
                 let transcription_tx_for_quit = transcription_tx.clone();
 
                 // Create system tray menu
-                let settings_item = MenuItemBuilder::with_id("settings", "Paramètres").build(app)?;
-
-                // Load execution modes and create Modes submenu
-                use tauri::menu::SubmenuBuilder;
-                let app_settings = config::AppSettings::load().unwrap_or_default();
-                let mut modes_submenu = SubmenuBuilder::new(app, "Modes");
-
-                for mode in &app_settings.execution_modes {
-                    let mode_id = format!("mode_{}", mode.id);
-                    let mode_label = if mode.id == app_settings.active_mode {
-                        format!("✓ {}", mode.name)
-                    } else {
-                        mode.name.clone()
-                    };
-                    let mode_item = MenuItemBuilder::with_id(&mode_id, mode_label).build(app)?;
-                    modes_submenu = modes_submenu.item(&mode_item);
-                }
-
-                let modes_menu = modes_submenu.build()?;
-                let quit = MenuItemBuilder::with_id("quit", "Quitter").build(app)?;
-
-                let menu = MenuBuilder::new(app)
-                    .items(&[&settings_item, &modes_menu, &quit])
-                    .build()?;
+                let menu = build_tray_menu(app.handle())?;
 
                 // Build the tray icon
-                let _tray = TrayIconBuilder::new()
+                let _tray = TrayIconBuilder::with_id(TRAY_ID)
                     .icon(app.default_window_icon().unwrap().clone())
                     .menu(&menu)
                     .on_menu_event(move |app, event| {
@@ -1464,6 +3197,12 @@ pub fn run() {
                                 println!("Worker threads shutdown complete");
                                 app.exit(0);
                             }
+                            id if id.starts_with("lang_") => {
+                                let language = id.strip_prefix("lang_").unwrap().to_string();
+                                if let Err(e) = set_next_language(app.clone(), Some(language)) {
+                                    eprintln!("Failed to set the next recording language: {}", e);
+                                }
+                            }
                             id if id.starts_with("mode_") => {
                                 // Extract the mode ID by removing "mode_" prefix
                                 let mode_id = id.strip_prefix("mode_").unwrap();
@@ -1476,7 +3215,7 @@ pub fn run() {
                                             eprintln!("Failed to save active mode: {}", e);
                                         } else {
                                             println!("Active mode changed to: {}", mode_id);
-                                            // TODO: Rebuild tray menu to update checkmarks
+                                            refresh_tray_menu(app);
                                         }
                                     }
                                 }
@@ -1517,6 +3256,10 @@ pub fn run() {
             stop_recording,
             is_recording,
             transcribe,
+            transcribe_detailed,
+            set_next_language,
+            update_language_switch_key,
+            get_next_language,
             auto_paste,
             copy_to_clipboard,
             get_settings,
@@ -1525,13 +3268,31 @@ pub fn run() {
             update_cancel_key,
             reload_model,
             get_audio_devices,
+            list_audio_devices,
+            set_audio_device,
+            list_orphaned_recordings,
+            recover_recording,
+            discard_orphaned_recording,
             add_custom_word,
             remove_custom_word,
             clear_custom_words,
             get_custom_words,
+            get_vocabulary_lists,
+            save_vocabulary_list,
+            delete_vocabulary_list,
+            import_vocabulary_list,
+            export_vocabulary_list,
+            get_vocabulary_report,
             list_available_models,
             download_model,
             delete_model,
+            cancel_download,
+            import_model_file,
+            import_model_url,
+            download_vad_model,
+            transcribe_file,
+            get_vad_timeline,
+            get_vad_diagnostics,
             get_llm_models,
             add_llm_model,
             update_llm_model,
@@ -1550,3 +3311,218 @@ pub fn run() {
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audio::MemoryAudioSource;
+
+    #[test]
+    fn test_audio_worker_with_memory_source() {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let source = MemoryAudioSource::new(vec![0.1; 32000], 32000);
+            AudioWorker::with_source(rx, Box::new(source)).run();
+        });
+
+        tx.send(AudioCommand::StartRecording).unwrap();
+
+        let (reply_tx, reply_rx) = mpsc::channel();
+        tx.send(AudioCommand::IsRecording { reply: reply_tx }).unwrap();
+        assert!(reply_rx.recv().unwrap());
+
+        let (reply_tx, reply_rx) = mpsc::channel();
+        tx.send(AudioCommand::StopRecording { reply: reply_tx }).unwrap();
+        let audio = reply_rx.recv().unwrap().unwrap();
+        assert_eq!(audio.len(), 16000);
+
+        tx.send(AudioCommand::Shutdown).unwrap();
+        handle.join().unwrap();
+    }
+
+    /// Source standing for a device, optionally failing once while recording,
+    /// or failing to start while `unplugged` is set
+    struct TestDevice {
+        name: String,
+        active: bool,
+        fail: bool,
+        samples: usize,
+        unplugged: Option<std::sync::Arc<std::sync::atomic::AtomicBool>>,
+    }
+
+    impl AudioSource for TestDevice {
+        fn start(&mut self) -> Result<(), String> {
+            if self.unplugged.as_ref().is_some_and(|unplugged| unplugged.load(std::sync::atomic::Ordering::SeqCst)) {
+                return Err("Device not available".to_string());
+            }
+            self.active = true;
+            Ok(())
+        }
+
+        fn stop_and_drain(&mut self) -> Result<Vec<f32>, String> {
+            if !self.active {
+                return Err("Not recording".to_string());
+            }
+            self.active = false;
+            Ok(vec![0.5; self.samples])
+        }
+
+        fn is_active(&self) -> bool {
+            self.active
+        }
+
+        fn device_name(&self) -> Option<String> {
+            Some(self.name.clone())
+        }
+
+        fn take_error(&mut self) -> Option<String> {
+            if self.active && self.fail {
+                self.fail = false;
+                return Some("Device unplugged".to_string());
+            }
+            None
+        }
+    }
+
+    #[test]
+    fn test_audio_worker_reconnects_after_device_error() {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut opened = 0;
+            let factory: AudioSourceFactory = Box::new(move |device_name| {
+                opened += 1;
+                Ok(Box::new(TestDevice {
+                    name: device_name.unwrap_or("default").to_string(),
+                    active: false,
+                    fail: opened == 1,
+                    samples: 1600,
+                    unplugged: None,
+                }))
+            });
+            AudioWorker::with_factory(rx, factory).run();
+        });
+
+        tx.send(AudioCommand::StartRecording).unwrap();
+
+        // The failure is detected here and the recording moves to a new source
+        let (reply_tx, reply_rx) = mpsc::channel();
+        tx.send(AudioCommand::IsRecording { reply: reply_tx }).unwrap();
+        assert!(reply_rx.recv().unwrap());
+
+        // Audio from before the failure is kept
+        let (reply_tx, reply_rx) = mpsc::channel();
+        tx.send(AudioCommand::StopRecording { reply: reply_tx }).unwrap();
+        assert_eq!(reply_rx.recv().unwrap().unwrap().len(), 3200);
+
+        let (reply_tx, reply_rx) = mpsc::channel();
+        tx.send(AudioCommand::SetDevice {
+            device_name: Some("USB Headset".to_string()),
+            reply: reply_tx,
+        })
+        .unwrap();
+        assert_eq!(reply_rx.recv().unwrap().unwrap(), "USB Headset");
+
+        tx.send(AudioCommand::Shutdown).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_audio_worker_falls_back_when_start_fails_and_retries_selected_device() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let unplugged = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        let handle = {
+            let unplugged = unplugged.clone();
+            thread::spawn(move || {
+                // The headset when it is plugged in, the built-in microphone otherwise
+                let factory: AudioSourceFactory = Box::new(move |device_name| {
+                    let headset = device_name == Some("USB Headset") && !unplugged.load(Ordering::SeqCst);
+                    Ok(Box::new(TestDevice {
+                        name: if headset { "USB Headset" } else { "Built-in" }.to_string(),
+                        active: false,
+                        fail: false,
+                        samples: if headset { 1600 } else { 800 },
+                        unplugged: headset.then(|| unplugged.clone()),
+                    }))
+                });
+                AudioWorker::with_factory(rx, factory).run();
+            })
+        };
+        let record = || {
+            tx.send(AudioCommand::StartRecording).unwrap();
+            let (reply_tx, reply_rx) = mpsc::channel();
+            tx.send(AudioCommand::StopRecording { reply: reply_tx }).unwrap();
+            reply_rx.recv().unwrap().unwrap().len()
+        };
+
+        let (reply_tx, reply_rx) = mpsc::channel();
+        tx.send(AudioCommand::SetDevice {
+            device_name: Some("USB Headset".to_string()),
+            reply: reply_tx,
+        })
+        .unwrap();
+        assert_eq!(reply_rx.recv().unwrap().unwrap(), "USB Headset");
+        assert_eq!(record(), 1600);
+
+        // Unplugged between recordings: the start fails and the built-in microphone takes over
+        unplugged.store(true, Ordering::SeqCst);
+        assert_eq!(record(), 800);
+        assert_eq!(record(), 800);
+
+        // Plugged in again: the next recording is back on the headset
+        unplugged.store(false, Ordering::SeqCst);
+        assert_eq!(record(), 1600);
+
+        tx.send(AudioCommand::Shutdown).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_audio_worker_stops_at_max_duration() {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let source = MemoryAudioSource::new(vec![0.1; 16000], 16000);
+            AudioWorker::with_source(rx, Box::new(source))
+                .with_max_duration(Some(std::time::Duration::from_millis(100)))
+                .run();
+        });
+
+        tx.send(AudioCommand::StartRecording).unwrap();
+        thread::sleep(std::time::Duration::from_millis(200));
+
+        // Stopped automatically, the recording waits for the next stop command
+        let (reply_tx, reply_rx) = mpsc::channel();
+        tx.send(AudioCommand::IsRecording { reply: reply_tx }).unwrap();
+        assert!(!reply_rx.recv().unwrap());
+
+        let (reply_tx, reply_rx) = mpsc::channel();
+        tx.send(AudioCommand::StopRecording { reply: reply_tx }).unwrap();
+        assert_eq!(reply_rx.recv().unwrap().unwrap().len(), 16000);
+
+        tx.send(AudioCommand::Shutdown).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_file_chunks_are_cut_in_the_longest_silence() {
+        use audio::vad::SpeechSegment;
+
+        let segments = vec![
+            SpeechSegment { start: 0, end: 200 },
+            SpeechSegment { start: 300, end: 500 },
+            SpeechSegment { start: 800, end: 900 },
+        ];
+        assert_eq!(chunk_cut(&segments, 1000, false), 650);
+
+        // The whole window without a silence, or up to the middle of the trailing one
+        assert_eq!(chunk_cut(&[SpeechSegment { start: 0, end: 1000 }], 1000, false), 1000);
+        assert_eq!(chunk_cut(&[], 1000, false), 1000);
+        let trailing_silence = [SpeechSegment { start: 0, end: 400 }];
+        assert_eq!(chunk_cut(&trailing_silence, 1000, false), 700);
+
+        // The tail of the file goes whole
+        assert_eq!(chunk_cut(&segments, 1000, true), 1000);
+    }
+}
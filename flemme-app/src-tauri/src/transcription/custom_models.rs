@@ -0,0 +1,308 @@
+// Custom models - Whisper models imported by the user from a local file or any URL,
+// described in a sidecar manifest next to them in the models directory
+use super::whisper::WhisperEngine;
+use super::TranscriptionEngine;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Manifest of the imported models, in the models directory
+const MANIFEST_FILE: &str = "custom_models.json";
+
+/// Files of the models directory an imported model must not replace
+const RESERVED_NAMES: &[&str] = &[MANIFEST_FILE, "custom_models.json.tmp", "silero_vad.onnx"];
+
+/// Imports and deletions change the manifest from different threads
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// First bytes of a whisper.cpp ggml file (0x67676d6c, little-endian) and of a GGUF file
+const GGML_MAGIC: &[u8; 4] = b"lmgg";
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomModel {
+    /// File name in the models directory
+    pub name: String,
+    pub display_name: String,
+    /// ISO 639-1 codes of the languages the model is meant for, empty if unknown
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// Path or URL the model was imported from
+    pub source: String,
+    /// The models directory holds a link to `source` rather than a copy
+    #[serde(default)]
+    pub linked: bool,
+}
+
+impl CustomModel {
+    /// Model metadata, with the file name as display name when none is given
+    pub fn new(
+        name: &str,
+        display_name: Option<String>,
+        languages: Vec<String>,
+        source: String,
+        linked: bool,
+    ) -> Result<Self, String> {
+        let languages = languages
+            .iter()
+            .map(|language| {
+                let language = language.trim().to_lowercase();
+                if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_lowercase()) {
+                    return Err(format!("Invalid language code: '{}'", language));
+                }
+                Ok(language)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            display_name: display_name
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| name.to_string()),
+            languages,
+            source,
+            linked,
+        })
+    }
+}
+
+/// The imported models, from the sidecar manifest
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CustomModels {
+    pub models: Vec<CustomModel>,
+}
+
+impl CustomModels {
+    /// Load the manifest of a models directory (empty if nothing was imported yet)
+    pub fn load(models_dir: &Path) -> Result<Self, String> {
+        let path = models_dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read custom models manifest: {}", e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid custom models manifest: {}", e))
+    }
+
+    pub fn save(&self, models_dir: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize custom models: {}", e))?;
+        // Written next to the manifest then renamed, so a crash never leaves half of it
+        let tmp_path = models_dir.join(format!("{}.tmp", MANIFEST_FILE));
+        std::fs::write(&tmp_path, json).map_err(|e| format!("Failed to write custom models manifest: {}", e))?;
+        std::fs::rename(&tmp_path, models_dir.join(MANIFEST_FILE))
+            .map_err(|e| format!("Failed to write custom models manifest: {}", e))
+    }
+
+    /// Load the manifest, change it and save it if `change` returns true, one change at a time
+    pub fn update(models_dir: &Path, change: impl FnOnce(&mut Self) -> bool) -> Result<(), String> {
+        let _lock = MANIFEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut models = Self::load(models_dir)?;
+        if change(&mut models) {
+            models.save(models_dir)?;
+        }
+        Ok(())
+    }
+
+    pub fn find(&self, name: &str) -> Option<&CustomModel> {
+        self.models.iter().find(|model| model.name == name)
+    }
+
+    /// Add a model, replacing the metadata of a model imported before under the same name
+    pub fn upsert(&mut self, model: CustomModel) {
+        self.models.retain(|other| other.name != model.name);
+        self.models.push(model);
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.models.len();
+        self.models.retain(|model| model.name != name);
+        self.models.len() != count
+    }
+}
+
+/// How a local model gets into the models directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    Copy,
+    /// Symbolic link, to avoid duplicating a large file
+    Link,
+}
+
+/// Check a model to import does not take the name of a catalog model or of a file the app uses.
+/// The names are compared whatever the case, like on Windows and macOS file systems.
+pub fn check_name(name: &str, catalog_names: &[String]) -> Result<(), String> {
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(name)) {
+        return Err(format!("'{}' is a file of the app, not a model name", name));
+    }
+    if catalog_names.iter().any(|catalog_name| catalog_name.eq_ignore_ascii_case(name)) {
+        return Err(format!("'{}' is the name of a model of the catalog. Rename the file to import it", name));
+    }
+    Ok(())
+}
+
+/// Put a local model file in the models directory, under its file name
+pub fn import_file(source: &Path, models_dir: &Path, catalog_names: &[String], mode: ImportMode) -> Result<PathBuf, String> {
+    check_model_header(source)?;
+
+    let name = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("Invalid model file name: {:?}", source))?;
+    check_name(name, catalog_names)?;
+    let target = models_dir.join(name);
+    if target.symlink_metadata().is_ok() {
+        return Err(format!("A model named '{}' already exists", name));
+    }
+
+    match mode {
+        ImportMode::Copy => std::fs::copy(source, &target).map(|_| ()),
+        ImportMode::Link => {
+            let source = source
+                .canonicalize()
+                .map_err(|e| format!("Failed to resolve {:?}: {}", source, e))?;
+            symlink(&source, &target)
+        }
+    }
+    .map_err(|e| format!("Failed to import model '{}': {}", name, e))?;
+
+    println!("Imported model {:?} as {:?}", source, target);
+    Ok(target)
+}
+
+#[cfg(unix)]
+fn symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(source, target)
+}
+
+/// Needs Developer Mode or administrator rights on Windows
+#[cfg(windows)]
+fn symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(source, target)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_source: &Path, _target: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Symbolic links are not supported"))
+}
+
+/// File name of a model to download, from the last segment of its URL
+pub fn file_name_from_url(url: &str) -> Result<String, String> {
+    let path = url
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    match path.rsplit_once('/') {
+        Some((scheme, name)) if !scheme.ends_with(':') && !scheme.ends_with(":/") && !name.is_empty() && !name.starts_with('.') => {
+            Ok(name.to_string())
+        }
+        _ => Err(format!("No model file name in URL '{}'", url)),
+    }
+}
+
+/// Check the file starts like a ggml or GGUF model, before trying to load it
+pub fn check_model_header(path: &Path) -> Result<(), String> {
+    let mut magic = [0u8; 4];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .map_err(|e| format!("Failed to read model {:?}: {}", path, e))?;
+    if &magic != GGML_MAGIC && &magic != GGUF_MAGIC {
+        return Err(format!("{:?} is not a ggml or GGUF Whisper model", path));
+    }
+    Ok(())
+}
+
+/// Test-load a model in a throwaway engine, so a model that Whisper cannot
+/// load is rejected on import rather than at the next model switch
+pub fn validate_model(path: &Path) -> Result<(), String> {
+    check_model_header(path)?;
+    WhisperEngine::new()
+        .load_model(path)
+        .map_err(|e| format!("Not a loadable Whisper model: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("flemme-custom-models-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_import_checks_the_header() {
+        let dir = test_dir("import");
+        let models_dir = dir.join("models");
+        std::fs::create_dir_all(&models_dir).unwrap();
+
+        let text = dir.join("notes.bin");
+        std::fs::write(&text, b"not a model").unwrap();
+        assert!(import_file(&text, &models_dir, &[], ImportMode::Copy).is_err());
+
+        let model = dir.join("ggml-french.bin");
+        std::fs::write(&model, b"lmgg\x01\x02\x03").unwrap();
+        let imported = import_file(&model, &models_dir, &[], ImportMode::Copy).unwrap();
+        assert_eq!(std::fs::read(&imported).unwrap(), b"lmgg\x01\x02\x03");
+
+        // Never overwrites a model
+        assert!(import_file(&model, &models_dir, &[], ImportMode::Copy).is_err());
+
+        // Nor takes the name of a catalog model or of a file of the app, even before it is downloaded
+        let base = dir.join("ggml-base.bin");
+        std::fs::write(&base, b"lmgg\x01").unwrap();
+        assert!(import_file(&base, &models_dir, &["ggml-base.bin".to_string()], ImportMode::Copy).is_err());
+        let vad = dir.join("Silero_VAD.onnx");
+        std::fs::write(&vad, b"lmgg\x01").unwrap();
+        assert!(import_file(&vad, &models_dir, &[], ImportMode::Copy).is_err());
+        assert!(!models_dir.join("ggml-base.bin").exists());
+
+        #[cfg(unix)]
+        {
+            let gguf = dir.join("whisper-fr.gguf");
+            std::fs::write(&gguf, b"GGUF\x03").unwrap();
+            let linked = import_file(&gguf, &models_dir, &[], ImportMode::Link).unwrap();
+            assert!(linked.symlink_metadata().unwrap().file_type().is_symlink());
+            assert_eq!(std::fs::read(&linked).unwrap(), b"GGUF\x03");
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let dir = test_dir("manifest");
+        assert!(CustomModels::load(&dir).unwrap().models.is_empty());
+
+        let mut models = CustomModels::default();
+        let model = CustomModel::new("ggml-french.bin", None, vec![" FR ".to_string()], "/tmp/ggml-french.bin".to_string(), false);
+        models.upsert(model.unwrap());
+        let renamed = CustomModel::new("ggml-french.bin", Some("French fine-tune".to_string()), vec![], String::new(), true);
+        models.upsert(renamed.unwrap());
+        models.save(&dir).unwrap();
+
+        let loaded = CustomModels::load(&dir).unwrap();
+        assert_eq!(loaded.models.len(), 1);
+        let model = loaded.find("ggml-french.bin").unwrap();
+        assert_eq!(model.display_name, "French fine-tune");
+        assert!(model.linked);
+
+        let model = CustomModel::new("ggml-french.bin", None, vec!["fr".to_string()], String::new(), false).unwrap();
+        assert_eq!((model.display_name.as_str(), model.languages[0].as_str()), ("ggml-french.bin", "fr"));
+        assert!(CustomModel::new("ggml-french.bin", None, vec!["french!".to_string()], String::new(), false).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_file_name_from_url() {
+        let name = file_name_from_url("https://huggingface.co/org/whisper-fr/resolve/main/ggml-model.bin?download=true");
+        assert_eq!(name.unwrap(), "ggml-model.bin");
+        assert!(file_name_from_url("https://example.com").is_err());
+        assert!(file_name_from_url("https://example.com/").is_err());
+        assert!(file_name_from_url("https://example.com/.hidden").is_err());
+    }
+}
@@ -1,20 +1,782 @@
-// ModelDownloader - downloads whisper models
-// TODO: Implement model downloading functionality
+// ModelDownloader - downloads, verifies and deletes the models of the catalog
+// Downloads go to a .part file, resumed with HTTP range requests while the
+// server still has the same file (its ETag or Last-Modified, kept in a
+// .part.etag file, goes in If-Range), and are renamed to the model file once their checksum (or at least size) is verified.
+// Models without a checksum in the catalog are checked against the one the
+// server advertises, if any (Hugging Face does for its LFS files).
+use super::models::ModelInfo;
+use futures_util::StreamExt;
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Free space kept on the disk on top of the download
+const DISK_SPACE_MARGIN: u64 = 50 * 1024 * 1024;
+
+/// A connection is given up after this long without being established
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A download is given up after this long without receiving data
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where models are stored: <data dir>/Flemme/models
+pub fn models_dir() -> Result<PathBuf, String> {
+    dirs::data_dir()
+        .ok_or_else(|| "Failed to get data directory".to_string())
+        .map(|d| d.join("Flemme").join("models"))
+}
 
 pub struct ModelDownloader {
-    // TODO: Add fields
+    models_dir: PathBuf,
+    client: reqwest::Client,
+    // Doesn't follow redirects, to read the headers of the first response
+    probe_client: reqwest::Client,
 }
 
 impl ModelDownloader {
-    pub fn new() -> Self {
-        todo!("Implement ModelDownloader::new")
+    /// Downloader for the app models directory, created if needed
+    pub fn new() -> Result<Self, String> {
+        Self::with_dir(models_dir()?)
+    }
+
+    pub fn with_dir(models_dir: PathBuf) -> Result<Self, String> {
+        std::fs::create_dir_all(&models_dir).map_err(|e| format!("Failed to create models directory: {}", e))?;
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        let probe_client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(READ_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        Ok(Self {
+            models_dir,
+            client,
+            probe_client,
+        })
+    }
+
+    pub fn models_dir(&self) -> &Path {
+        &self.models_dir
+    }
+
+    /// Path of a model file, refusing names that would leave the models directory
+    pub fn model_path(&self, model_name: &str) -> Result<PathBuf, String> {
+        if model_name.is_empty() || model_name.contains(['/', '\\']) || model_name.starts_with('.') {
+            return Err(format!("Invalid model name: '{}'", model_name));
+        }
+        Ok(self.models_dir.join(model_name))
+    }
+
+    /// The file a model is downloaded to until it is complete and verified
+    pub fn part_path(&self, model_name: &str) -> Result<PathBuf, String> {
+        self.model_path(model_name)
+            .map(|path| path.with_file_name(format!("{}.part", model_name)))
+    }
+
+    /// Where the validator (ETag or Last-Modified) of the file being downloaded is kept
+    fn validator_path(&self, model_name: &str) -> Result<PathBuf, String> {
+        self.model_path(model_name)
+            .map(|path| path.with_file_name(format!("{}.part.etag", model_name)))
+    }
+
+    fn partial_validator(&self, model_name: &str) -> Option<String> {
+        let validator = std::fs::read_to_string(self.validator_path(model_name).ok()?).ok()?;
+        Some(validator.trim().to_string()).filter(|validator| !validator.is_empty())
+    }
+
+    pub fn check_model_exists(&self, model_name: &str) -> bool {
+        self.model_path(model_name).is_ok_and(|path| path.is_file())
+    }
+
+    /// Bytes of an interrupted download, resumed by the next `download_model`
+    /// if the file on the server didn't change (0 when that can't be checked)
+    pub fn partial_size(&self, model_name: &str) -> u64 {
+        if self.partial_validator(model_name).is_none() {
+            return 0;
+        }
+        self.part_path(model_name)
+            .and_then(|path| std::fs::metadata(path).map_err(|e| e.to_string()))
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    }
+
+    /// Download a model, reporting (downloaded, total) bytes as it goes, and
+    /// check its SHA-256 (from the catalog or else the server), or its size
+    /// when there is none.
+    /// Returns the model path. Setting `cancel` stops the download; like a
+    /// network error, it keeps the .part file so the next download resumes
+    /// from there.
+    pub async fn download_model(
+        &self,
+        model: &ModelInfo,
+        cancel: &Cancellation,
+        mut on_progress: impl FnMut(u64, u64),
+    ) -> Result<PathBuf, String> {
+        let model_path = self.model_path(&model.name)?;
+        let part_path = self.part_path(&model.name)?;
+
+        let mut model = model.clone();
+        if model.sha256.is_none() {
+            let advertised = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => return Err(cancelled_error(&model.name)),
+                sha256 = self.advertised_sha256(&model.url) => sha256,
+            };
+            if let Some(ref sha256) = advertised {
+                println!("Checking model '{}' against the SHA-256 advertised by the server: {}", model.name, sha256);
+            }
+            model.sha256 = advertised;
+        }
+        let model = &model;
+
+        // Bytes already fetched by an interrupted download
+        let validator = self.partial_validator(&model.name);
+        let mut downloaded_bytes = self.partial_size(&model.name);
+        if downloaded_bytes > 0 {
+            println!("Resuming download of model '{}' at {} bytes from {}", model.name, downloaded_bytes, model.url);
+        } else {
+            println!("Downloading model '{}' from {}", model.name, model.url);
+        }
+
+        let mut response = self.request(&model.url, downloaded_bytes, validator.as_deref()).await?;
+        if downloaded_bytes > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            // Nothing to fetch past the part file: it doesn't match this model, start over
+            downloaded_bytes = 0;
+            response = self.request(&model.url, 0, None).await?;
+        }
+        let response = response
+            .error_for_status()
+            .map_err(|e| format!("Failed to download: {}", e))?;
+        if downloaded_bytes > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+            // The whole file comes back when it changed since the part file was started
+            println!("Model '{}' changed on the server or can't be resumed, starting over", model.name);
+            downloaded_bytes = 0;
+        }
+        if downloaded_bytes == 0 {
+            self.save_validator(&model.name, &response)?;
+        }
+
+        let remaining_bytes = response.content_length();
+        let total_bytes = downloaded_bytes + remaining_bytes.unwrap_or(model.size.saturating_sub(downloaded_bytes));
+        check_disk_space(&self.models_dir, total_bytes - downloaded_bytes)?;
+
+        // The bytes already downloaded go through the checksum first
+        let mut hasher = Sha256::new();
+        let file = if downloaded_bytes > 0 {
+            hash_file(&part_path, &mut hasher)?;
+            std::fs::OpenOptions::new().append(true).open(&part_path)
+        } else {
+            std::fs::File::create(&part_path)
+        };
+        let mut file = file.map_err(|e| format!("Failed to open {:?}: {}", part_path, e))?;
+        on_progress(downloaded_bytes, total_bytes);
+
+        let mut stream = response.bytes_stream();
+        let result: Result<(), String> = async {
+            loop {
+                // Cancelling doesn't wait for the next chunk, which may never come
+                let chunk = tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => return Err(cancelled_error(&model.name)),
+                    chunk = tokio::time::timeout(READ_TIMEOUT, stream.next()) => chunk.map_err(|_| {
+                        format!("Download of model '{}' stalled: no data for {}s", model.name, READ_TIMEOUT.as_secs())
+                    })?,
+                };
+                let Some(chunk) = chunk else {
+                    break;
+                };
+                let chunk = chunk.map_err(|e| format!("Download error: {}", e))?;
+                file.write_all(&chunk)
+                    .map_err(|e| format!("Failed to write to file: {}", e))?;
+                hasher.update(&chunk);
+                downloaded_bytes += chunk.len() as u64;
+                on_progress(downloaded_bytes, total_bytes);
+            }
+            if remaining_bytes.is_some() && downloaded_bytes != total_bytes {
+                return Err(format!("Download of model '{}' ended early", model.name));
+            }
+            Ok(())
+        }
+        .await;
+        drop(file);
+        result?;
+
+        // A corrupted download can't be resumed
+        let validator_path = self.validator_path(&model.name)?;
+        if let Err(e) = check_download(model, &hex_digest(hasher), downloaded_bytes) {
+            let _ = std::fs::remove_file(&part_path);
+            let _ = std::fs::remove_file(&validator_path);
+            return Err(e);
+        }
+        std::fs::rename(&part_path, &model_path)
+            .map_err(|e| format!("Failed to move {:?} to {:?}: {}", part_path, model_path, e))?;
+        let _ = std::fs::remove_file(&validator_path);
+
+        println!("Model '{}' downloaded successfully", model.name);
+        Ok(model_path)
+    }
+
+    /// SHA-256 the server advertises for `url`: Hugging Face sends the one of
+    /// its LFS files in `X-Linked-Etag`, on the redirect to the file itself
+    async fn advertised_sha256(&self, url: &str) -> Option<String> {
+        let response = self.probe_client.head(url).send().await.ok()?;
+        let etag = response.headers().get("x-linked-etag")?.to_str().ok()?;
+        let sha256 = etag.trim_start_matches("W/").trim_matches('"').to_ascii_lowercase();
+        (sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit())).then_some(sha256)
+    }
+
+    /// GET `url` from byte `offset` on, or the whole file if it no longer
+    /// matches `validator`
+    async fn request(&self, url: &str, offset: u64, validator: Option<&str>) -> Result<reqwest::Response, String> {
+        let mut request = self.client.get(url);
+        if offset > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+            if let Some(validator) = validator {
+                request = request.header(reqwest::header::IF_RANGE, validator);
+            }
+        }
+        request.send().await.map_err(|e| format!("Failed to download: {}", e))
+    }
+
+    /// Keep what identifies the file being downloaded to resume it later: its
+    /// ETag, or Last-Modified (If-Range takes no weak ETag). Without either
+    /// the download can't be resumed.
+    fn save_validator(&self, model_name: &str, response: &reqwest::Response) -> Result<(), String> {
+        let headers = response.headers();
+        let validator = headers
+            .get(reqwest::header::ETAG)
+            .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+            .or_else(|| headers.get(reqwest::header::LAST_MODIFIED))
+            .and_then(|value| value.to_str().ok());
+
+        let path = self.validator_path(model_name)?;
+        match validator {
+            Some(validator) => std::fs::write(&path, validator),
+            None => std::fs::remove_file(&path).or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            }),
+        }
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+
+    /// Check a downloaded model against the SHA-256 (or size) of the catalog
+    pub fn verify_model(&self, model: &ModelInfo) -> Result<(), String> {
+        let model_path = self.model_path(&model.name)?;
+        let size = std::fs::metadata(&model_path)
+            .map_err(|e| format!("Failed to open {:?}: {}", model_path, e))?
+            .len();
+        check_download(model, &sha256_file(&model_path)?, size)
+    }
+
+    /// Delete a model, or what was downloaded of it
+    pub fn delete_model(&self, model_name: &str) -> Result<(), String> {
+        // Imported models may be links, deleted even when what they point to is gone
+        let paths: Vec<PathBuf> = [self.model_path(model_name)?, self.part_path(model_name)?, self.validator_path(model_name)?]
+            .into_iter()
+            .filter(|path| path.symlink_metadata().is_ok())
+            .collect();
+        if paths.is_empty() {
+            return Err(format!("Model '{}' does not exist", model_name));
+        }
+
+        for path in paths {
+            std::fs::remove_file(&path).map_err(|e| format!("Failed to delete model '{}': {}", model_name, e))?;
+        }
+
+        println!("Model '{}' deleted successfully", model_name);
+        Ok(())
+    }
+}
+
+/// Cancels a download, also while it waits for its turn or for data
+#[derive(Default)]
+pub struct Cancellation {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl Cancellation {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.notify.notify_one();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once the download is cancelled
+    pub async fn cancelled(&self) {
+        // `notify_one` keeps a permit if nothing waits yet
+        if !self.is_cancelled() {
+            self.notify.notified().await;
+        }
+    }
+}
+
+struct QueuedDownload {
+    model_name: String,
+    cancel: Arc<Cancellation>,
+}
+
+/// Runs downloads one at a time, in the order they were requested
+#[derive(Default)]
+pub struct DownloadQueue {
+    turn: tokio::sync::Mutex<()>,
+    // Running download first, then the waiting ones
+    downloads: Mutex<Vec<QueuedDownload>>,
+}
+
+impl DownloadQueue {
+    /// Wait for the downloads requested before, then run `download` with its cancellation
+    pub async fn run<T, F, Fut>(&self, model_name: &str, download: F) -> Result<T, String>
+    where
+        F: FnOnce(Arc<Cancellation>) -> Fut,
+        Fut: Future<Output = Result<T, String>>,
+    {
+        let cancel = Arc::new(Cancellation::default());
+        {
+            let mut downloads = self.downloads.lock().unwrap();
+            if downloads.iter().any(|d| d.model_name == model_name) {
+                return Err(format!("Model '{}' is already being downloaded", model_name));
+            }
+            downloads.push(QueuedDownload {
+                model_name: model_name.to_string(),
+                cancel: cancel.clone(),
+            });
+        }
+        let _queued = QueueEntry {
+            queue: self,
+            model_name,
+        };
+
+        let _turn = tokio::select! {
+            turn = self.turn.lock() => turn,
+            _ = cancel.cancelled() => return Err(cancelled_error(model_name)),
+        };
+        if cancel.is_cancelled() {
+            return Err(cancelled_error(model_name));
+        }
+        download(cancel).await
+    }
+
+    /// Cancel a running or waiting download; false if there is none for this model
+    pub fn cancel(&self, model_name: &str) -> bool {
+        let downloads = self.downloads.lock().unwrap();
+        let Some(download) = downloads.iter().find(|d| d.model_name == model_name) else {
+            return false;
+        };
+        download.cancel.cancel();
+        true
+    }
+
+    /// Models being downloaded or waiting, in order
+    pub fn pending(&self) -> Vec<String> {
+        self.downloads.lock().unwrap().iter().map(|d| d.model_name.clone()).collect()
+    }
+}
+
+/// Leaves the queue when the download ends, whatever the outcome
+struct QueueEntry<'a> {
+    queue: &'a DownloadQueue,
+    model_name: &'a str,
+}
+
+impl Drop for QueueEntry<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut downloads) = self.queue.downloads.lock() {
+            downloads.retain(|d| d.model_name != self.model_name);
+        }
+    }
+}
+
+fn cancelled_error(model_name: &str) -> String {
+    format!("Download of model '{}' cancelled", model_name)
+}
+
+/// SHA-256 of a file, in lowercase hex
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut hasher = Sha256::new();
+    hash_file(path, &mut hasher)?;
+    Ok(hex_digest(hasher))
+}
+
+fn hash_file(path: &Path, hasher: &mut Sha256) -> Result<(), String> {
+    let mut file = std::fs::File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    let mut buffer = vec![0u8; 1 << 20];
+    loop {
+        let read = file.read(&mut buffer).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        if read == 0 {
+            return Ok(());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+fn check_disk_space(dir: &Path, needed_bytes: u64) -> Result<(), String> {
+    match platform::available_space(dir) {
+        Some(available) if available < needed_bytes + DISK_SPACE_MARGIN => Err(format!(
+            "Not enough disk space: {:.0} MB needed, {:.0} MB available",
+            (needed_bytes + DISK_SPACE_MARGIN) as f64 / 1_000_000.0,
+            available as f64 / 1_000_000.0
+        )),
+        Some(_) => Ok(()),
+        None => {
+            eprintln!("Could not check the free disk space in {:?}", dir);
+            Ok(())
+        }
+    }
+}
+
+fn hex_digest(hasher: Sha256) -> String {
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Check a model file against the SHA-256 of the catalog, or its size when it has none
+fn check_download(model: &ModelInfo, actual: &str, size: u64) -> Result<(), String> {
+    match model.sha256 {
+        Some(ref expected) if !expected.eq_ignore_ascii_case(actual) => Err(format!(
+            "Checksum mismatch for model '{}': expected {}, got {}",
+            model.name, expected, actual
+        )),
+        Some(_) => Ok(()),
+        None if model.size > 0 && size != model.size => Err(format!(
+            "Size mismatch for model '{}': expected {} bytes, got {}",
+            model.name, model.size, size
+        )),
+        None => {
+            println!("No checksum in the catalog for model '{}' (SHA-256 {})", model.name, actual);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// Bytes available to this user on the file system of `dir`
+    pub fn available_space(dir: &Path) -> Option<u64> {
+        let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+        let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+            return None;
+        }
+        #[allow(clippy::unnecessary_cast)] // The field types vary between platforms
+        Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    /// Bytes available to this user on the volume of `dir`
+    pub fn available_space(dir: &Path) -> Option<u64> {
+        let path: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut available = 0u64;
+        let ok = unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) };
+        (ok != 0).then_some(available)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    pub fn available_space(_dir: &std::path::Path) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcription::models::ModelKind;
+
+    fn model(name: &str, sha256: Option<&str>) -> ModelInfo {
+        ModelInfo {
+            name: name.to_string(),
+            kind: ModelKind::Whisper,
+            model: None,
+            quantization: None,
+            version: None,
+            size: 3,
+            sha256: sha256.map(str::to_string),
+            url: String::new(),
+            recommended_hardware: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_verify_and_delete_model() {
+        let dir = std::env::temp_dir().join(format!("flemme-models-test-{}", std::process::id()));
+        let downloader = ModelDownloader::with_dir(dir.clone()).unwrap();
+        std::fs::write(dir.join("model.bin"), b"abc").unwrap();
+        assert!(downloader.check_model_exists("model.bin"));
+
+        // SHA-256 of "abc"
+        let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert!(downloader.verify_model(&model("model.bin", Some(sha256))).is_ok());
+        assert!(downloader.verify_model(&model("model.bin", Some(&"0".repeat(64)))).is_err());
+        assert!(downloader.verify_model(&model("model.bin", None)).is_ok());
+        let mut larger = model("model.bin", None);
+        larger.size = 4;
+        assert!(downloader.verify_model(&larger).unwrap_err().contains("Size mismatch"));
+        // Models from any URL have no known size
+        larger.size = 0;
+        assert!(downloader.verify_model(&larger).is_ok());
+
+        downloader.delete_model("model.bin").unwrap();
+        assert!(!downloader.check_model_exists("model.bin"));
+        assert!(downloader.delete_model("model.bin").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Serves `body` on localhost with the ETag "v1", honouring "Range: bytes=N-"
+    /// requests unless their If-Range is another ETag. The first response stops
+    /// after `cut` bytes, like a dropped connection. HEAD requests get the
+    /// `advertised` SHA-256 in `X-Linked-Etag`.
+    fn serve(body: Vec<u8>, mut cut: Option<usize>, advertised: Option<String>) -> String {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/model.bin", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request);
+                if request.starts_with("HEAD ") {
+                    let etag = advertised.as_ref().map_or(String::new(), |sha256| format!("X-Linked-Etag: \"{}\"\r\n", sha256));
+                    let _ = write!(stream, "HTTP/1.1 302 Found\r\nLocation: /model.bin\r\n{}Content-Length: 0\r\n\r\n", etag);
+                    continue;
+                }
+                let header = |name: &str| {
+                    request.lines().find_map(|line| {
+                        let (key, value) = line.split_once(':')?;
+                        key.eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+                    })
+                };
+                let offset = header("range")
+                    .filter(|_| header("if-range").is_none_or(|etag| etag == "\"v1\""))
+                    .and_then(|range| range.strip_prefix("bytes=")?.strip_suffix('-')?.parse().ok())
+                    .unwrap_or(0usize);
+
+                let status = if offset > 0 { "206 Partial Content" } else { "200 OK" };
+                let content = &body[offset..];
+                let sent = cut.take().map_or(content.len(), |cut| cut.min(content.len()));
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    content.len()
+                );
+                let _ = stream.write_all(&content[..sent]);
+            }
+        });
+        url
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("flemme-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_download_is_resumed() {
+        let body: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let sha256 = hex_digest(Sha256::new_with_prefix(&body));
+        let mut model = model("model.bin", Some(&sha256));
+        model.url = serve(body.clone(), Some(100_000), None);
+
+        let dir = test_dir("download-resume");
+        let downloader = ModelDownloader::with_dir(dir.clone()).unwrap();
+        let cancel = Cancellation::default();
+
+        // The connection drops: the part file is kept, the model isn't listed as downloaded
+        assert!(downloader.download_model(&model, &cancel, |_, _| {}).await.is_err());
+        let partial = downloader.partial_size("model.bin");
+        assert!(partial > 0 && partial <= 100_000);
+        assert!(!downloader.check_model_exists("model.bin"));
+
+        // Resumed where it stopped, verified and moved in place
+        let mut progress = Vec::new();
+        let path = downloader
+            .download_model(&model, &cancel, |downloaded, total| progress.push((downloaded, total)))
+            .await
+            .unwrap();
+        assert_eq!(progress.first(), Some(&(partial, 300_000)));
+        assert_eq!(progress.last(), Some(&(300_000, 300_000)));
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        assert_eq!(downloader.partial_size("model.bin"), 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_download_is_resumed_only_from_the_same_file() {
+        let body: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        let mut model = model("model.bin", None);
+        model.size = body.len() as u64;
+        model.url = serve(body.clone(), Some(20_000), None);
+
+        let dir = test_dir("download-validator");
+        let downloader = ModelDownloader::with_dir(dir.clone()).unwrap();
+        let cancel = Cancellation::default();
+
+        // Resumed without a checksum, the server having the same file
+        assert!(downloader.download_model(&model, &cancel, |_, _| {}).await.is_err());
+        let partial = downloader.partial_size("model.bin");
+        assert!(partial > 0);
+        let mut first_progress = None;
+        let path = downloader
+            .download_model(&model, &cancel, |downloaded, _| {
+                first_progress.get_or_insert(downloaded);
+            })
+            .await
+            .unwrap();
+        assert_eq!(first_progress, Some(partial));
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        assert!(!downloader.validator_path("model.bin").unwrap().exists());
+
+        // The file changed on the server, or there is nothing to tell: downloaded again
+        let part_path = downloader.part_path("model.bin").unwrap();
+        for validator in [Some("\"v0\""), None] {
+            std::fs::write(&part_path, vec![0; 10_000]).unwrap();
+            if let Some(validator) = validator {
+                std::fs::write(downloader.validator_path("model.bin").unwrap(), validator).unwrap();
+            }
+            let path = downloader.download_model(&model, &cancel, |_, _| {}).await.unwrap();
+            assert_eq!(std::fs::read(&path).unwrap(), body);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
     }
 
-    pub fn download_model(&self, _model_name: &str, _destination: &str) -> Result<(), String> {
-        todo!("Implement model download")
+    #[tokio::test]
+    async fn test_corrupted_or_cancelled_download() {
+        let mut model = model("model.bin", Some(&"0".repeat(64)));
+        model.url = serve(vec![7; 50_000], None, None);
+
+        let dir = test_dir("download-verify");
+        let downloader = ModelDownloader::with_dir(dir.clone()).unwrap();
+
+        // Checksum mismatch: nothing is kept
+        let cancel = Cancellation::default();
+        let error = downloader.download_model(&model, &cancel, |_, _| {}).await.unwrap_err();
+        assert!(error.contains("Checksum mismatch"));
+        assert!(!downloader.check_model_exists("model.bin"));
+        assert_eq!(downloader.partial_size("model.bin"), 0);
+
+        let cancel = Cancellation::default();
+        cancel.cancel();
+        let error = downloader.download_model(&model, &cancel, |_, _| {}).await.unwrap_err();
+        assert!(error.contains("cancelled"));
+        assert!(!downloader.check_model_exists("model.bin"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_download_is_checked_against_the_advertised_checksum() {
+        let body: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        let dir = test_dir("download-advertised");
+        let downloader = ModelDownloader::with_dir(dir.clone()).unwrap();
+        let cancel = Cancellation::default();
+
+        let mut model = model("model.bin", None);
+        model.size = body.len() as u64;
+        model.url = serve(body.clone(), None, Some("0".repeat(64)));
+        let error = downloader.download_model(&model, &cancel, |_, _| {}).await.unwrap_err();
+        assert!(error.contains("Checksum mismatch"));
+
+        model.url = serve(body.clone(), None, Some(hex_digest(Sha256::new_with_prefix(&body))));
+        let path = downloader.download_model(&model, &cancel, |_, _| {}).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_stalled_download_is_cancelled() {
+        // Sends the headers, then nothing (a checksum spares the request for one)
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut model = model("model.bin", Some(&"0".repeat(64)));
+        model.url = format!("http://{}/model.bin", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0u8; 1024]);
+            let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n");
+            std::thread::sleep(Duration::from_secs(5));
+        });
+
+        let dir = test_dir("download-stalled");
+        let downloader = ModelDownloader::with_dir(dir.clone()).unwrap();
+        let cancel = Arc::new(Cancellation::default());
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            canceller.cancel();
+        });
+
+        let started = std::time::Instant::now();
+        let error = downloader.download_model(&model, &cancel, |_, _| {}).await.unwrap_err();
+        assert!(error.contains("cancelled"));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_queue_runs_downloads_in_sequence() {
+        let queue = DownloadQueue::default();
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+
+        let first = queue.run("a", |_| async move {
+            release_rx.await.unwrap();
+            Ok("a")
+        });
+        let second = queue.run("b", |_| async { Ok("b") });
+        let third = queue.run("c", |_| async { Ok("c") });
+        let check = async {
+            tokio::task::yield_now().await;
+            assert_eq!(queue.pending(), ["a", "b", "c"]);
+            assert!(queue.run("a", |_| async { Ok("a") }).await.is_err());
+
+            // Cancelled while waiting for its turn
+            assert!(queue.cancel("b"));
+            assert!(!queue.cancel("d"));
+            release_tx.send(()).unwrap();
+        };
+
+        let (a, b, c, ()) = tokio::join!(first, second, third, check);
+        assert_eq!(a, Ok("a"));
+        assert!(b.unwrap_err().contains("cancelled"));
+        assert_eq!(c, Ok("c"));
+        assert!(queue.pending().is_empty());
     }
 
-    pub fn check_model_exists(&self, _model_path: &str) -> bool {
-        todo!("Implement model existence check")
+    #[test]
+    fn test_model_names_stay_in_models_dir() {
+        let downloader = ModelDownloader::with_dir(std::env::temp_dir()).unwrap();
+        assert!(downloader.model_path("../settings.json").is_err());
+        assert!(downloader.model_path("sub/model.bin").is_err());
+        assert!(downloader.model_path("ggml-base.bin").is_ok());
     }
 }
@@ -2,6 +2,8 @@
 // This file is kept for backward compatibility with existing code in lib.rs
 
 use super::whisper::WhisperEngine;
+use super::{InitialPrompt, LanguageSelection, TranscriptionResult};
+use crate::config::WhisperDecodeOptions;
 
 /// DEPRECATED: Legacy wrapper around WhisperEngine for backward compatibility
 /// New code should use WhisperEngine directly via the TranscriptionEngine trait
@@ -20,20 +22,20 @@ impl TranscriptionEngine {
         Ok(Self { inner })
     }
 
-    /// Transcribe audio samples to text
+    /// Transcribe audio samples, with the timed segments and their confidence
     /// Audio must be mono 16kHz f32 samples
-    /// language: ISO 639-1 code (e.g., "fr", "en", "es") or None for auto-detect
-    /// custom_words: Optional list of custom words/phrases for contextual biasing via initial prompt
-    pub fn transcribe(&mut self, audio_data: &[f32], language: Option<&str>, custom_words: Option<&[String]>) -> Result<String, String> {
+    /// language: ISO 639-1 code (e.g., "fr", "en", "es") or detection among allowed languages
+    /// prompt: custom words, previous text and style hint for the initial prompt
+    /// decode: Whisper decoding parameters of the active execution mode
+    pub fn transcribe(&mut self, audio_data: &[f32], language: &LanguageSelection, prompt: &InitialPrompt, decode: &WhisperDecodeOptions) -> Result<TranscriptionResult, String> {
         use super::TranscriptionEngine as TranscriptionTrait;
 
-        let result = self.inner.transcribe_with_prompt(
+        self.inner.transcribe_with_prompt(
             audio_data,
-            language.map(|s| s.to_string()),
-            custom_words
-        )?;
-
-        Ok(result.text)
+            language,
+            prompt,
+            decode
+        )
     }
 
     /// Get sample rate required by whisper (always 16kHz)
@@ -1,16 +1,139 @@
 // Transcription module - handles speech-to-text with Whisper
 pub mod whisper;
 pub mod engine; // Old engine.rs for backward compatibility
+pub mod downloader;
+pub mod models;
+pub mod custom_models;
+pub mod prompt;
 
+pub use custom_models::{CustomModel, CustomModels};
+pub use downloader::ModelDownloader;
+pub use models::{ModelCatalog, ModelKind, TranscriptionModel};
+pub use prompt::InitialPrompt;
 pub use whisper::WhisperEngine;
 
+use crate::config::WhisperDecodeOptions;
+use serde::Serialize;
 use std::path::Path;
 
+/// Language of the speech to transcribe
+#[derive(Debug, Clone, PartialEq)]
+pub enum LanguageSelection {
+    /// ISO 639-1 code, e.g. "fr"
+    Fixed(String),
+    /// Detected by Whisper on the first 30s, among these codes (any language when empty)
+    Detect(Vec<String>),
+}
+
+impl LanguageSelection {
+    /// From a language setting: a code, or "auto" to detect it among `allowed`
+    pub fn from_setting(language: &str, allowed: &[String]) -> Self {
+        if language == "auto" {
+            Self::Detect(allowed.to_vec())
+        } else {
+            Self::Fixed(language.to_string())
+        }
+    }
+}
+
+/// The allowed language Whisper finds most likely, from its probability for a language code
+pub fn most_likely_language(allowed: &[String], probability: impl Fn(&str) -> Option<f32>) -> Option<String> {
+    allowed
+        .iter()
+        .filter_map(|code| probability(code).map(|p| (code, p)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(code, _)| code.clone())
+}
+
 /// Result of a transcription operation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TranscriptionResult {
     pub text: String,
+    /// Language Whisper detected, or the one it was asked for
     pub language: Option<String>,
+    pub segments: Vec<TranscriptionSegment>,
+    /// Mean log probability of all the tokens (closer to 0 is more confident)
+    pub avg_logprob: f32,
+    /// Probability that the audio holds no speech, averaged over the segments by duration
+    pub no_speech_probability: f32,
+    /// Custom words left out of the prompt to stay within Whisper's token limit
+    pub dropped_words: Vec<String>,
+}
+
+impl TranscriptionResult {
+    pub fn new(language: Option<String>, segments: Vec<TranscriptionSegment>) -> Self {
+        let text = segments.iter().map(|segment| segment.text.as_str()).collect();
+        let logprobs: Vec<f32> = segments
+            .iter()
+            .flat_map(|segment| segment.tokens.iter().map(|token| token.logprob))
+            .collect();
+        let duration_ms: u64 = segments.iter().map(TranscriptionSegment::duration_ms).sum();
+        let no_speech_probability = if duration_ms > 0 {
+            segments
+                .iter()
+                .map(|segment| segment.no_speech_probability * segment.duration_ms() as f32)
+                .sum::<f32>()
+                / duration_ms as f32
+        } else {
+            segments.first().map_or(0.0, |segment| segment.no_speech_probability)
+        };
+
+        Self {
+            text,
+            language,
+            segments,
+            avg_logprob: mean(&logprobs),
+            no_speech_probability,
+            dropped_words: Vec::new(),
+        }
+    }
+}
+
+/// A part of a transcription, timed relative to the start of the audio given to the engine.
+/// When the caller removed the silences with the VAD first, the times are positions in the
+/// trimmed audio and do not map back to the recording.
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptionSegment {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+    /// Text tokens, without the timestamp and control tokens
+    pub tokens: Vec<TokenProbability>,
+    /// Mean log probability of the tokens (0.0 without tokens)
+    pub avg_logprob: f32,
+    pub no_speech_probability: f32,
+}
+
+impl TranscriptionSegment {
+    pub fn new(start_ms: u64, end_ms: u64, text: String, tokens: Vec<TokenProbability>, no_speech_probability: f32) -> Self {
+        let logprobs: Vec<f32> = tokens.iter().map(|token| token.logprob).collect();
+        Self {
+            start_ms,
+            end_ms,
+            text,
+            avg_logprob: mean(&logprobs),
+            tokens,
+            no_speech_probability,
+        }
+    }
+
+    pub fn duration_ms(&self) -> u64 {
+        self.end_ms.saturating_sub(self.start_ms)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenProbability {
+    pub text: String,
+    pub probability: f32,
+    pub logprob: f32,
+}
+
+fn mean(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f32>() / values.len() as f32
 }
 
 /// Trait for speech-to-text transcription engines
@@ -22,14 +145,68 @@ pub trait TranscriptionEngine {
     fn is_loaded(&self) -> bool;
 
     /// Transcribe audio data (16kHz mono f32 samples, normalized -1.0 to 1.0)
-    /// custom_words: Optional list of custom words/phrases for contextual biasing via initial prompt
-    fn transcribe(&mut self, audio_data: &[f32], language: Option<String>) -> Result<TranscriptionResult, String> {
-        self.transcribe_with_prompt(audio_data, language, None)
+    fn transcribe(&mut self, audio_data: &[f32], language: &LanguageSelection) -> Result<TranscriptionResult, String> {
+        self.transcribe_with_prompt(audio_data, language, &InitialPrompt::default(), &WhisperDecodeOptions::default())
     }
 
-    /// Transcribe with custom words for contextual biasing
-    fn transcribe_with_prompt(&mut self, audio_data: &[f32], language: Option<String>, custom_words: Option<&[String]>) -> Result<TranscriptionResult, String>;
+    /// Transcribe with an initial prompt for contextual biasing
+    /// prompt: custom words, previous utterance (continuous dictation) and style hint, in the language template
+    /// decode: decoding parameters (beam search, temperature fallback, thresholds)
+    fn transcribe_with_prompt(&mut self, audio_data: &[f32], language: &LanguageSelection, prompt: &InitialPrompt, decode: &WhisperDecodeOptions) -> Result<TranscriptionResult, String>;
 
     /// Get the name of this engine
     fn engine_name(&self) -> &str;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(text: &str, probability: f32) -> TokenProbability {
+        TokenProbability {
+            text: text.to_string(),
+            probability,
+            logprob: probability.ln(),
+        }
+    }
+
+    #[test]
+    fn test_result_aggregates_segments() {
+        let segments = vec![
+            TranscriptionSegment::new(0, 3000, " Bonjour".to_string(), vec![token(" Bon", 0.5), token("jour", 0.5)], 0.1),
+            TranscriptionSegment::new(3000, 4000, " à tous.".to_string(), vec![token(" à", 1.0), token(" tous.", 1.0)], 0.5),
+        ];
+        let result = TranscriptionResult::new(Some("fr".to_string()), segments);
+
+        assert_eq!(result.text, " Bonjour à tous.");
+        assert!((result.segments[0].avg_logprob - 0.5f32.ln()).abs() < 1e-6);
+        assert_eq!(result.segments[1].avg_logprob, 0.0);
+        assert!((result.avg_logprob - 0.5f32.ln() / 2.0).abs() < 1e-6);
+        // Weighted by duration: 3s at 0.1, 1s at 0.5
+        assert!((result.no_speech_probability - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_detected_language_is_an_allowed_one() {
+        // Accented English that Whisper hesitates to call Welsh
+        let probability = |code: &str| match code {
+            "cy" => Some(0.5),
+            "en" => Some(0.3),
+            "fr" => Some(0.1),
+            _ => None,
+        };
+        let allowed = ["fr".to_string(), "en".to_string()];
+        assert_eq!(most_likely_language(&allowed, probability).as_deref(), Some("en"));
+        assert_eq!(most_likely_language(&["xx".to_string()], probability), None);
+
+        assert_eq!(LanguageSelection::from_setting("auto", &allowed), LanguageSelection::Detect(allowed.to_vec()));
+        assert_eq!(LanguageSelection::from_setting("fr", &allowed), LanguageSelection::Fixed("fr".to_string()));
+    }
+
+    #[test]
+    fn test_empty_result() {
+        let result = TranscriptionResult::new(None, Vec::new());
+        assert_eq!(result.text, "");
+        assert_eq!((result.avg_logprob, result.no_speech_probability), (0.0, 0.0));
+    }
+}
@@ -1,24 +1,184 @@
-// Transcription models - defines model types and metadata
-// TODO: Implement model management
+// Transcription models - the catalog of downloadable models (Whisper variants and the VAD model)
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
-pub struct ModelInfo {
-    pub name: String,
-    pub size: u64,
-    pub url: String,
+/// Manifest shipped with the app, used when no remote manifest is configured or reachable
+const BUNDLED_MANIFEST: &str = include_str!("models.json");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelKind {
+    Whisper,
+    Vad,
 }
 
-#[derive(Debug, Clone)]
+/// Whisper model sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum TranscriptionModel {
     Tiny,
     Base,
     Small,
     Medium,
-    Large,
+    LargeV2,
+    LargeV3,
+    LargeV3Turbo,
 }
 
 impl TranscriptionModel {
-    pub fn get_info(&self) -> ModelInfo {
-        todo!("Implement model info retrieval")
+    pub const ALL: [TranscriptionModel; 7] = [
+        Self::Tiny,
+        Self::Base,
+        Self::Small,
+        Self::Medium,
+        Self::LargeV2,
+        Self::LargeV3,
+        Self::LargeV3Turbo,
+    ];
+
+    /// The recommended file for this size (the first one listed in the catalog)
+    pub fn get_info<'a>(&self, catalog: &'a ModelCatalog) -> Option<&'a ModelInfo> {
+        catalog.whisper_models().find(|info| info.model == Some(*self))
+    }
+}
+
+/// A downloadable model file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    /// File name in the models directory, e.g. "ggml-base-q5_1.bin"
+    pub name: String,
+    pub kind: ModelKind,
+    /// Whisper size, None for the VAD model
+    #[serde(default)]
+    pub model: Option<TranscriptionModel>,
+    /// Weights quantization (e.g. "q5_1"), None for full precision
+    #[serde(default)]
+    pub quantization: Option<String>,
+    /// Release of the model (e.g. "v5" for Silero VAD), when its inputs changed across releases
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Download size in bytes
+    pub size: u64,
+    /// SHA-256 of the file (lowercase hex), checked after download when known
+    #[serde(default)]
+    pub sha256: Option<String>,
+    pub url: String,
+    #[serde(default)]
+    pub recommended_hardware: String,
+}
+
+impl ModelInfo {
+    pub fn size_mb(&self) -> f64 {
+        self.size as f64 / 1_000_000.0
+    }
+}
+
+/// Models offered for download, from a JSON manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelCatalog {
+    pub version: u32,
+    pub models: Vec<ModelInfo>,
+}
+
+impl ModelCatalog {
+    /// The manifest bundled with the app
+    pub fn bundled() -> Result<Self, String> {
+        Self::from_json(BUNDLED_MANIFEST)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let catalog: ModelCatalog =
+            serde_json::from_str(json).map_err(|e| format!("Invalid model manifest: {}", e))?;
+        catalog.validate()?;
+        Ok(catalog)
+    }
+
+    /// Download a manifest, e.g. to offer models released after this version of the app
+    pub async fn fetch(url: &str) -> Result<Self, String> {
+        let json = reqwest::get(url)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to fetch model manifest: {}", e))?
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read model manifest: {}", e))?;
+        Self::from_json(&json)
+    }
+
+    /// The remote manifest if one is configured and reachable, the bundled one otherwise
+    pub async fn load(manifest_url: Option<&str>) -> Result<Self, String> {
+        if let Some(url) = manifest_url {
+            match Self::fetch(url).await {
+                Ok(catalog) => return Ok(catalog),
+                Err(e) => eprintln!("{}. Using the bundled model catalog.", e),
+            }
+        }
+        Self::bundled()
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ModelInfo> {
+        self.models.iter().find(|info| info.name == name)
+    }
+
+    pub fn whisper_models(&self) -> impl Iterator<Item = &ModelInfo> {
+        self.models.iter().filter(|info| info.kind == ModelKind::Whisper)
+    }
+
+    pub fn vad_model(&self) -> Option<&ModelInfo> {
+        self.models.iter().find(|info| info.kind == ModelKind::Vad)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (i, info) in self.models.iter().enumerate() {
+            // Names are file names in the models directory
+            if info.name.is_empty() || info.name.contains(['/', '\\']) || info.name.starts_with('.') {
+                return Err(format!("Invalid model file name: '{}'", info.name));
+            }
+            if self.models[..i].iter().any(|other| other.name == info.name) {
+                return Err(format!("Model '{}' is listed twice", info.name));
+            }
+            if let Some(ref sha256) = info.sha256 {
+                if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(format!("Invalid SHA-256 for model '{}': {}", info.name, sha256));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_catalog_covers_every_model() {
+        let catalog = ModelCatalog::bundled().unwrap();
+        for model in TranscriptionModel::ALL {
+            let info = model.get_info(&catalog).unwrap();
+            assert_eq!(info.kind, ModelKind::Whisper);
+            assert!(info.url.ends_with(&info.name));
+        }
+        let vad = catalog.vad_model().unwrap();
+        assert_eq!(vad.name, "silero_vad.onnx");
+        assert_eq!(vad.version.as_deref(), Some("v5"));
+
+        // The models offered before the catalog are still there
+        for name in ["ggml-base-q5_1.bin", "ggml-small-q5_1.bin", "ggml-medium-q5_0.bin", "ggml-large-v2-q5_0.bin", "ggml-large-v3-turbo-q5_0.bin"] {
+            assert!(catalog.find(name).is_some(), "{} missing", name);
+        }
+    }
+
+    #[test]
+    fn test_invalid_manifests_are_rejected() {
+        let manifest = |name: &str, sha256: &str| {
+            format!(
+                r#"{{"version": 1, "models": [{{"name": "{}", "kind": "vad", "size": 1, "sha256": {}, "url": "https://example.com/model"}}]}}"#,
+                name, sha256
+            )
+        };
+        assert!(ModelCatalog::from_json(&manifest("model.bin", "null")).is_ok());
+        assert!(ModelCatalog::from_json(&manifest("../model.bin", "null")).is_err());
+        assert!(ModelCatalog::from_json(&manifest("model.bin", "\"abc\"")).is_err());
+        assert!(ModelCatalog::from_json("{}").is_err());
     }
 }
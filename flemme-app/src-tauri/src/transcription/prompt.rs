@@ -0,0 +1,269 @@
+// Initial prompt - the text Whisper decodes after, built from a per-language template
+// with the custom words, the previous utterance and the style hint of the mode
+use std::collections::BTreeMap;
+
+/// Whisper keeps at most half of its 448-token text context for the prompt
+pub const MAX_PROMPT_TOKENS: usize = 224;
+
+/// Placeholders a template can use
+const PLACEHOLDERS: &[&str] = &["{style}", "{words}", "{previous}"];
+
+/// Template of the languages without one: no example sentence, so no bias towards another language
+const FALLBACK_TEMPLATE: &str = "{style} {words} {previous}";
+
+/// A well-punctuated sentence in each language helps Whisper keep proper punctuation.
+/// It comes before the custom words, and the previous text stays closest to the audio.
+const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    ("ar", "{style} مرحبا، هذا مثال بعلامات ترقيم صحيحة. {words} {previous}"),
+    ("de", "{style} Hallo, dies ist ein Beispiel mit korrekter Zeichensetzung. {words} {previous}"),
+    ("en", "{style} Hello, this is an example with correct punctuation. {words} {previous}"),
+    ("es", "{style} Hola, este es un ejemplo con una puntuación correcta. {words} {previous}"),
+    ("fr", "{style} Bonjour, ceci est un exemple avec une ponctuation correcte. {words} {previous}"),
+    ("it", "{style} Ciao, questo è un esempio con una punteggiatura corretta. {words} {previous}"),
+    ("ja", "{style} こんにちは。これは正しい句読点を使った例です。 {words} {previous}"),
+    ("ko", "{style} 안녕하세요, 이것은 올바른 문장 부호를 사용한 예입니다. {words} {previous}"),
+    ("nl", "{style} Hallo, dit is een voorbeeld met correcte interpunctie. {words} {previous}"),
+    ("pl", "{style} Dzień dobry, to jest przykład z poprawną interpunkcją. {words} {previous}"),
+    ("pt", "{style} Olá, este é um exemplo com pontuação correta. {words} {previous}"),
+    ("ru", "{style} Здравствуйте, это пример с правильной пунктуацией. {words} {previous}"),
+    ("sv", "{style} Hej, det här är ett exempel med korrekt interpunktion. {words} {previous}"),
+    ("tr", "{style} Merhaba, bu doğru noktalama işaretlerine sahip bir örnektir. {words} {previous}"),
+    ("uk", "{style} Добрий день, це приклад із правильною пунктуацією. {words} {previous}"),
+    ("zh", "{style} 你好，这是一个标点符号正确的例子。 {words} {previous}"),
+];
+
+/// Built-in templates by language code, the default of the `prompt_templates` setting
+pub fn default_templates() -> BTreeMap<String, String> {
+    DEFAULT_TEMPLATES
+        .iter()
+        .map(|(language, template)| (language.to_string(), template.to_string()))
+        .collect()
+}
+
+/// Check a template only uses the known placeholders
+pub fn validate_template(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed placeholder in prompt template: '{}'", template))?;
+        let placeholder = &rest[start..start + end + 1];
+        if !PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "Unknown placeholder {} in prompt template. Use {}",
+                placeholder,
+                PLACEHOLDERS.join(", ")
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+/// Rough token count when the model tokenizer is not available (about 3 characters a token)
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(3)
+}
+
+/// A word to bias the transcription towards
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptWord {
+    pub text: String,
+    /// Language codes the word is used for, empty for all languages
+    pub languages: Vec<String>,
+}
+
+impl PromptWord {
+    fn applies_to(&self, language: Option<&str>) -> bool {
+        self.languages.is_empty() || language.is_some_and(|language| self.languages.iter().any(|code| code == language))
+    }
+}
+
+/// An initial prompt and the custom words it could not hold
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuiltPrompt {
+    pub text: String,
+    pub dropped_words: Vec<String>,
+}
+
+/// What goes into the initial prompt, assembled once the language is known
+#[derive(Debug, Clone, Default)]
+pub struct InitialPrompt {
+    /// Templates by language code
+    pub templates: BTreeMap<String, String>,
+    /// Words to bias the transcription towards, most important first
+    pub custom_words: Vec<PromptWord>,
+    /// Text said just before this audio (continuous dictation)
+    pub previous_text: Option<String>,
+    /// Style hint of the active execution mode
+    pub style: String,
+}
+
+impl InitialPrompt {
+    /// Template of a language: the configured one, else the built-in one, else a neutral one
+    pub fn template(&self, language: Option<&str>) -> &str {
+        let Some(language) = language else {
+            return FALLBACK_TEMPLATE;
+        };
+        self.templates
+            .get(language)
+            .map(String::as_str)
+            .or_else(|| {
+                DEFAULT_TEMPLATES
+                    .iter()
+                    .find(|(code, _)| *code == language)
+                    .map(|(_, template)| *template)
+            })
+            .unwrap_or(FALLBACK_TEMPLATE)
+    }
+
+    /// Custom words used for a language, without the duplicates of more important ones
+    pub fn words(&self, language: Option<&str>) -> Vec<String> {
+        let mut words: Vec<String> = Vec::new();
+        for word in self.custom_words.iter().filter(|word| word.applies_to(language)) {
+            if !words.iter().any(|other| other.to_lowercase() == word.text.to_lowercase()) {
+                words.push(word.text.clone());
+            }
+        }
+        words
+    }
+
+    /// Prompt for a language, within `max_tokens` as counted by `count_tokens`.
+    /// Over the limit, the oldest words of the previous text go first, then the
+    /// style hint, then the least important custom words, and the template
+    /// text itself is cut last.
+    pub fn build(&self, language: Option<&str>, max_tokens: usize, count_tokens: impl Fn(&str) -> usize) -> BuiltPrompt {
+        let template = self.template(language);
+        let all_words = self.words(language);
+        let previous: Vec<&str> = self
+            .previous_text
+            .as_deref()
+            .map(|text| text.split_whitespace().collect())
+            .unwrap_or_default();
+        // Each word is at least a token, so older ones can never fit
+        let mut previous = &previous[previous.len().saturating_sub(max_tokens)..];
+        let mut style = self.style.trim();
+        let mut words = all_words.as_slice();
+        let built = |text: String, words: &[String]| BuiltPrompt {
+            text,
+            dropped_words: all_words[words.len()..].to_vec(),
+        };
+
+        loop {
+            let prompt = render(template, words, &previous.join(" "), style);
+            if count_tokens(&prompt) <= max_tokens {
+                return built(prompt, words);
+            }
+            if !previous.is_empty() {
+                previous = &previous[1..];
+            } else if !style.is_empty() {
+                style = "";
+            } else if !words.is_empty() {
+                words = &words[..words.len() - 1];
+            } else {
+                let mut prompt = prompt;
+                while count_tokens(&prompt) > max_tokens {
+                    prompt.pop();
+                }
+                return built(prompt.trim_end().to_string(), words);
+            }
+        }
+    }
+}
+
+/// Fill the placeholders of a template, without the blanks left by empty ones
+fn render(template: &str, words: &[String], previous: &str, style: &str) -> String {
+    template
+        .replace("{style}", style)
+        .replace("{words}", &words.join(", "))
+        .replace("{previous}", previous)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_words(text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+
+    fn word(text: &str, languages: &[&str]) -> PromptWord {
+        PromptWord {
+            text: text.to_string(),
+            languages: languages.iter().map(|language| language.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_template_per_language() {
+        let mut prompt = InitialPrompt {
+            custom_words: vec![word("PPAT", &[]), word("anamnèse", &["fr"]), word("Harmonie Mutuelle", &[])],
+            ..Default::default()
+        };
+        assert_eq!(
+            prompt.build(Some("en"), MAX_PROMPT_TOKENS, estimate_tokens).text,
+            "Hello, this is an example with correct punctuation. PPAT, Harmonie Mutuelle"
+        );
+        // No French example for a language without a template
+        assert_eq!(prompt.build(Some("sw"), MAX_PROMPT_TOKENS, estimate_tokens).text, "PPAT, Harmonie Mutuelle");
+        assert_eq!(prompt.words(Some("fr")), ["PPAT", "anamnèse", "Harmonie Mutuelle"]);
+
+        prompt.templates.insert("en".to_string(), "{style}: {previous}".to_string());
+        prompt.style = "Meeting notes".to_string();
+        prompt.previous_text = Some("  We agreed on Monday. ".to_string());
+        assert_eq!(prompt.build(Some("en"), MAX_PROMPT_TOKENS, estimate_tokens).text, "Meeting notes: We agreed on Monday.");
+    }
+
+    #[test]
+    fn test_previous_text_goes_last_without_moving_the_example() {
+        let mut prompt = InitialPrompt {
+            custom_words: vec![word("PPAT", &[])],
+            ..Default::default()
+        };
+        let example = "Bonjour, ceci est un exemple avec une ponctuation correcte.";
+        assert_eq!(prompt.build(Some("fr"), MAX_PROMPT_TOKENS, estimate_tokens).text, format!("{} PPAT", example));
+
+        // Continuous dictation only adds the previous utterance, next to the audio
+        prompt.previous_text = Some("On se voit lundi.".to_string());
+        assert_eq!(
+            prompt.build(Some("fr"), MAX_PROMPT_TOKENS, estimate_tokens).text,
+            format!("{} PPAT On se voit lundi.", example)
+        );
+    }
+
+    #[test]
+    fn test_lowest_priority_parts_are_truncated_first() {
+        let prompt = InitialPrompt {
+            templates: BTreeMap::from([("fr".to_string(), "{style} {words} Exemple bien ponctué. {previous}".to_string())]),
+            custom_words: vec![word("Aymeric", &[]), word("PPAT", &[]), word("aymeric", &["fr"])],
+            previous_text: Some("un deux trois quatre".to_string()),
+            style: "Note médicale.".to_string(),
+        };
+        let build = |max_tokens| prompt.build(Some("fr"), max_tokens, count_words).text;
+
+        assert_eq!(build(11), "Note médicale. Aymeric, PPAT Exemple bien ponctué. un deux trois quatre");
+        // The oldest words of the previous text go first
+        assert_eq!(build(9), "Note médicale. Aymeric, PPAT Exemple bien ponctué. trois quatre");
+        // Then the style hint
+        assert_eq!(build(6), "Aymeric, PPAT Exemple bien ponctué.");
+        // Then the last custom words
+        assert_eq!(build(4), "Aymeric Exemple bien ponctué.");
+        // And the template text last
+        assert_eq!(build(2), "Exemple bien");
+        assert_eq!(prompt.build(Some("fr"), 4, count_words).dropped_words, ["PPAT"]);
+        assert_eq!(prompt.build(Some("fr"), 2, count_words).dropped_words, ["Aymeric", "PPAT"]);
+    }
+
+    #[test]
+    fn test_validate_template() {
+        for (_, template) in DEFAULT_TEMPLATES {
+            assert!(validate_template(template).is_ok());
+        }
+        assert!(validate_template("{words} {previous}").is_ok());
+        assert!(validate_template("{mots} {previous}").is_err());
+        assert!(validate_template("{words").is_err());
+    }
+}
@@ -1,7 +1,9 @@
 // Whisper-rs transcription engine
-use super::{TranscriptionEngine, TranscriptionResult};
+use super::prompt::{estimate_tokens, InitialPrompt, MAX_PROMPT_TOKENS};
+use super::{LanguageSelection, TokenProbability, TranscriptionEngine, TranscriptionResult, TranscriptionSegment};
+use crate::config::WhisperDecodeOptions;
 use std::path::Path;
-use whisper_rs::{WhisperContext, WhisperContextParameters, FullParams, SamplingStrategy};
+use whisper_rs::{WhisperContext, WhisperContextParameters, WhisperState, FullParams, SamplingStrategy};
 
 /// Whisper-rs based transcription engine
 pub struct WhisperEngine {
@@ -58,7 +60,7 @@ impl TranscriptionEngine for WhisperEngine {
         self.model_loaded
     }
 
-    fn transcribe_with_prompt(&mut self, audio_data: &[f32], language: Option<String>, custom_words: Option<&[String]>) -> Result<TranscriptionResult, String> {
+    fn transcribe_with_prompt(&mut self, audio_data: &[f32], language: &LanguageSelection, prompt: &InitialPrompt, decode: &WhisperDecodeOptions) -> Result<TranscriptionResult, String> {
         let total_start = std::time::Instant::now();
 
         // Validate input
@@ -74,7 +76,13 @@ impl TranscriptionEngine for WhisperEngine {
 
         // Create transcription parameters
         let params_start = std::time::Instant::now();
-        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        let strategy = if decode.beam_size > 1 {
+            SamplingStrategy::BeamSearch { beam_size: decode.beam_size as i32, patience: -1.0 }
+        } else {
+            SamplingStrategy::Greedy { best_of: 1 }
+        };
+        let mut params = FullParams::new(strategy);
+        apply_decode_options(&mut params, decode);
 
         // Optimize thread count based on available CPU cores
         let num_cores = num_cpus::get();
@@ -86,31 +94,38 @@ impl TranscriptionEngine for WhisperEngine {
         params.set_n_threads(optimal_threads);
         println!("Using {} threads for transcription (CPU cores: {})", optimal_threads, num_cores);
 
-        // Set language if provided
-        if let Some(ref lang) = language {
-            params.set_language(Some(lang.as_str()));
-            params.set_translate(false);
-        }
+        // Create a new state for transcription
+        let state_start = std::time::Instant::now();
+        let mut state = context.create_state()
+            .map_err(|e| format!("Failed to create Whisper state: {:?}", e))?;
+        println!("[TIMING] Whisper - create state: {:.0}ms", state_start.elapsed().as_millis());
 
-        // Set initial prompt to improve punctuation and contextual biasing
-        // Adding a well-punctuated example helps Whisper maintain proper punctuation
-        let punctuation_example = "Bonjour, ceci est un exemple avec une ponctuation correcte.";
-
-        // Build the final prompt
-        let final_prompt = if let Some(words) = custom_words {
-            if !words.is_empty() {
-                let custom_words_str = words.join(", ");
-                println!("Using contextual biasing with {} custom words: {}", words.len(), custom_words_str);
-                format!("{} {}", punctuation_example, custom_words_str)
-            } else {
-                punctuation_example.to_string()
+        // The language set, or the allowed one Whisper finds most likely
+        let language = match language {
+            LanguageSelection::Fixed(code) => Some(code.clone()),
+            LanguageSelection::Detect(allowed) if allowed.is_empty() => None,
+            LanguageSelection::Detect(allowed) => {
+                Some(detect_language(&mut state, audio_data, allowed, optimal_threads as usize).unwrap_or_else(|e| {
+                    eprintln!("{}. Using {}.", e, allowed[0]);
+                    allowed[0].clone()
+                }))
             }
-        } else {
-            punctuation_example.to_string()
         };
+        params.set_language(Some(language.as_deref().unwrap_or("auto")));
+        params.set_translate(false);
 
-        params.set_initial_prompt(&final_prompt);
-        println!("Initial prompt set to improve punctuation");
+        // Initial prompt in the template of the language, for punctuation and contextual biasing
+        let final_prompt = prompt.build(language.as_deref(), MAX_PROMPT_TOKENS, |text| count_tokens(context, text));
+        params.set_initial_prompt(&final_prompt.text);
+        // Not the text itself, which holds what was dictated before
+        println!("Initial prompt: {} tokens", count_tokens(context, &final_prompt.text));
+        if !final_prompt.dropped_words.is_empty() {
+            println!(
+                "{} custom words left out of the prompt (token limit): {}",
+                final_prompt.dropped_words.len(),
+                final_prompt.dropped_words.join(", ")
+            );
+        }
 
         // Disable printing and other output
         params.set_print_special(false);
@@ -120,39 +135,61 @@ impl TranscriptionEngine for WhisperEngine {
 
         println!("[TIMING] Whisper - params setup: {:.0}ms", params_start.elapsed().as_millis());
 
-        // Create a new state for transcription
-        let state_start = std::time::Instant::now();
-        let mut state = context.create_state()
-            .map_err(|e| format!("Failed to create Whisper state: {:?}", e))?;
-        println!("[TIMING] Whisper - create state: {:.0}ms", state_start.elapsed().as_millis());
-
         // Run transcription
         let inference_start = std::time::Instant::now();
         state.full(params, audio_data)
             .map_err(|e| format!("Whisper transcription failed: {:?}", e))?;
         println!("[TIMING] Whisper - inference (state.full): {:.0}ms", inference_start.elapsed().as_millis());
 
-        // Extract transcribed text
+        // Extract the segments with their timing and token probabilities
         let extraction_start = std::time::Instant::now();
-        let num_segments = state.full_n_segments();
-
-        let mut text = String::new();
-        for i in 0..num_segments {
-            if let Some(segment) = state.get_segment(i) {
-                if let Ok(segment_text) = segment.to_str_lossy() {
-                    text.push_str(&segment_text);
-                }
-            }
+        // Tokens from end-of-text on are control and timestamp tokens
+        let first_special_token = context.token_eot();
+        let mut segments = Vec::new();
+        for i in 0..state.full_n_segments() {
+            let Some(segment) = state.get_segment(i) else {
+                continue;
+            };
+            let tokens = (0..segment.n_tokens())
+                .filter_map(|j| segment.get_token(j))
+                .filter(|token| token.token_data().id < first_special_token)
+                .map(|token| {
+                    let data = token.token_data();
+                    TokenProbability {
+                        text: token.to_str_lossy().map(|text| text.to_string()).unwrap_or_default(),
+                        probability: data.p,
+                        logprob: data.plog,
+                    }
+                })
+                .collect();
+            segments.push(TranscriptionSegment::new(
+                // Whisper timestamps are in centiseconds, from the start of `audio_data`
+                // (the VAD-trimmed audio when the caller filtered the silences)
+                segment.start_timestamp().max(0) as u64 * 10,
+                segment.end_timestamp().max(0) as u64 * 10,
+                segment.to_str_lossy().map(|text| text.to_string()).unwrap_or_default(),
+                tokens,
+                segment.no_speech_probability(),
+            ));
         }
+
+        let detected_language = state
+            .full_lang_id_from_state()
+            .ok()
+            .and_then(whisper_rs::get_lang_str)
+            .map(str::to_string);
         println!("[TIMING] Whisper - text extraction: {:.0}ms", extraction_start.elapsed().as_millis());
 
         println!("[TIMING] Whisper TOTAL: {:.0}ms", total_start.elapsed().as_millis());
         println!("Whisper transcription completed");
 
-        Ok(TranscriptionResult {
-            text,
-            language,
-        })
+        let mut result = TranscriptionResult::new(detected_language.or(language), segments);
+        result.dropped_words = final_prompt.dropped_words;
+        println!(
+            "Language: {:?}, average logprob: {:.2}, no-speech probability: {:.2}",
+            result.language, result.avg_logprob, result.no_speech_probability
+        );
+        Ok(result)
     }
 
     fn engine_name(&self) -> &str {
@@ -160,6 +197,58 @@ impl TranscriptionEngine for WhisperEngine {
     }
 }
 
+/// Run Whisper language detection on the first 30s and keep the most likely allowed language
+fn detect_language(state: &mut WhisperState, audio_data: &[f32], allowed: &[String], threads: usize) -> Result<String, String> {
+    let detect_start = std::time::Instant::now();
+    state.pcm_to_mel(audio_data, threads)
+        .map_err(|e| format!("Failed to compute the spectrogram for language detection: {:?}", e))?;
+    let probabilities = state.lang_detect(0, threads)
+        .map_err(|e| format!("Language detection failed: {:?}", e))?;
+
+    let language = super::most_likely_language(allowed, |code| {
+        whisper_rs::get_lang_id(code).and_then(|id| probabilities.get(id as usize).copied())
+    })
+    .ok_or_else(|| format!("No allowed language is known to Whisper: {:?}", allowed))?;
+    println!("[TIMING] Whisper - language detection: {:.0}ms ({} among {:?})", detect_start.elapsed().as_millis(), language, allowed);
+    Ok(language)
+}
+
+/// Tokens of a text for the loaded model, estimated if it cannot be tokenized
+fn count_tokens(context: &WhisperContext, text: &str) -> usize {
+    // A token is at least a byte
+    context
+        .tokenize(text, text.len() + 1)
+        .map(|tokens| tokens.len())
+        .unwrap_or_else(|_| estimate_tokens(text))
+}
+
+/// Decoding parameters other than the sampling strategy
+fn apply_decode_options(params: &mut FullParams, decode: &WhisperDecodeOptions) {
+    params.set_temperature(decode.temperature);
+    params.set_temperature_inc(decode.temperature_increment);
+    params.set_no_speech_thold(decode.no_speech_threshold);
+    params.set_logprob_thold(decode.logprob_threshold);
+    params.set_entropy_thold(decode.entropy_threshold);
+    params.set_suppress_blank(decode.suppress_blank);
+    params.set_suppress_nst(decode.suppress_non_speech_tokens);
+    params.set_single_segment(decode.single_segment);
+    if decode.max_segment_length > 0 {
+        // whisper.cpp only splits segments on token timestamps
+        params.set_token_timestamps(true);
+        params.set_split_on_word(true);
+        params.set_max_len(decode.max_segment_length as i32);
+    }
+    println!(
+        "Decoding: beam size {}, temperature {} (+{}), thresholds no-speech {} / logprob {} / entropy {}",
+        decode.beam_size,
+        decode.temperature,
+        decode.temperature_increment,
+        decode.no_speech_threshold,
+        decode.logprob_threshold,
+        decode.entropy_threshold
+    );
+}
+
 impl Default for WhisperEngine {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,52 @@
+$ErrorActionPreference = "Stop"
+
+# Fill in the SHA-256 of the models listed without one in models.json:
+# Hugging Face files from the X-Linked-Etag of their resolve URL (the SHA-256
+# of the LFS file), other files by downloading and hashing them. PowerShell 7.
+
+$manifestPath = ".\flemme-app\src-tauri\src\transcription\models.json"
+$manifest = Get-Content $manifestPath -Raw | ConvertFrom-Json
+
+$checksums = @{}
+foreach ($model in $manifest.models) {
+    if ($model.sha256) {
+        continue
+    }
+
+    Write-Host "Checksum of $($model.name)..."
+    if ($model.url -like "https://huggingface.co/*") {
+        $response = Invoke-WebRequest -Uri $model.url -Method Head -MaximumRedirection 0 -SkipHttpErrorCheck
+        $sha256 = ($response.Headers["X-Linked-Etag"] -join "").Trim('"')
+        $size = [int64]($response.Headers["X-Linked-Size"] -join "")
+    } else {
+        $file = New-TemporaryFile
+        Invoke-WebRequest -Uri $model.url -OutFile $file
+        $sha256 = (Get-FileHash $file -Algorithm SHA256).Hash
+        $size = (Get-Item $file).Length
+        Remove-Item $file
+    }
+
+    if ($sha256 -notmatch '^[0-9a-fA-F]{64}$') {
+        throw "No SHA-256 found for $($model.name) at $($model.url)"
+    }
+    if ($size -ne $model.size) {
+        throw "Size mismatch for $($model.name): $size bytes, $($model.size) in the manifest"
+    }
+    $checksums[$model.name] = $sha256.ToLower()
+}
+
+# Replace the null checksums line by line, to keep the file layout
+$name = $null
+$lines = [System.IO.File]::ReadAllText($manifestPath) -split "`n" | ForEach-Object {
+    if ($_ -match '"name": "(.+)"') {
+        $name = $Matches[1]
+    }
+    if ($checksums.ContainsKey($name) -and $_ -match '"sha256": null') {
+        $_ -replace '"sha256": null', "`"sha256`": `"$($checksums[$name])`""
+    } else {
+        $_
+    }
+}
+[System.IO.File]::WriteAllText($manifestPath, ($lines -join "`n"), [System.Text.UTF8Encoding]::new($false))
+
+Write-Host "Done: $($checksums.Count) checksum(s) added to $manifestPath"